@@ -0,0 +1,73 @@
+use std::time::Instant;
+
+use crate::state::app_state::State;
+
+/// How a benchmark case records its frames
+struct BenchmarkCase {
+    name: &'static str,
+    split_submits: bool,
+    steps_per_frame: u64,
+}
+
+/// Times `steps` steps with a submit per compute stage, then with the whole
+/// frame in one encoder at 1 and at `State::steps_per_frame` steps a frame.
+/// Each case waits on the GPU before its clock stops.
+pub(crate) fn run_benchmark(state: &mut State, steps: u64) {
+    let mut cases = vec![
+        BenchmarkCase {
+            name: "submit per stage",
+            split_submits: true,
+            steps_per_frame: 1,
+        },
+        BenchmarkCase {
+            name: "one encoder",
+            split_submits: false,
+            steps_per_frame: 1,
+        },
+    ];
+    if state.steps_per_frame > 1 {
+        cases.push(BenchmarkCase {
+            name: "one encoder",
+            split_submits: false,
+            steps_per_frame: state.steps_per_frame,
+        });
+    }
+
+    if state.profiler.enabled() {
+        println!("Benchmark: the profiler records each stage on its own, turn it off to compare");
+    }
+
+    println!(
+        "\n{:<18} {:>11} {:>8} {:>10} {:>8}",
+        "frames", "steps/frame", "steps", "steps/s", "speedup"
+    );
+    let mut baseline = None;
+    for case in cases {
+        state.split_submits = case.split_submits;
+
+        // One untimed frame so pipelines and staging buffers are warm
+        state.step_headless(case.steps_per_frame);
+        state.device.poll(wgpu::Maintain::Wait);
+
+        let started = Instant::now();
+        let mut remaining = steps;
+        while remaining > 0 {
+            let frame = case.steps_per_frame.min(remaining);
+            state.step_headless(frame);
+            remaining -= frame;
+        }
+        state.device.poll(wgpu::Maintain::Wait);
+        let rate = steps as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON);
+
+        let baseline = *baseline.get_or_insert(rate);
+        println!(
+            "{:<18} {:>11} {:>8} {:>10.1} {:>7.2}x",
+            case.name,
+            case.steps_per_frame,
+            steps,
+            rate,
+            rate / baseline
+        );
+    }
+    state.split_submits = false;
+}
@@ -0,0 +1 @@
+pub(crate) mod benchmark_functions;
@@ -0,0 +1,490 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::{
+    export::export_functions::read_field_texels,
+    modulation::modulation_functions::unmodulated_params,
+    presets::preset_functions::preset_from_params,
+    state::{app_state::State, readback_state::read_back_blocking},
+    Checkpoint, CheckpointHeader, Slime, CHECKPOINT_VERSION,
+};
+
+// The file is the magic, the version as a u32, the header length as a u64,
+// the TOML header, then the agents and the field. Bulk data is in native byte
+// order, little endian on everything wgpu runs on.
+const CHECKPOINT_MAGIC: &[u8; 8] = b"SLIMECKP";
+
+/// Copies the agents back from the GPU. Blocks until the copy is done.
+pub(crate) fn read_agents(state: &State) -> Vec<Slime> {
+    let size = state.buffers.slime_pos_buf.size();
+
+    let data = read_back_blocking(
+        &state.device,
+        &state.queue,
+        size,
+        "checkpoint agents",
+        |encoder, buffer| {
+            encoder.copy_buffer_to_buffer(&state.buffers.slime_pos_buf, 0, buffer, 0, size);
+        },
+    );
+    bytemuck::pod_collect_to_vec(&data)
+}
+
+/// Everything needed to carry on from the current step. Parameters are saved
+/// where they are, not where smoothing is taking them.
+pub(crate) fn capture_checkpoint(state: &State) -> Checkpoint {
+    let mut preset = preset_from_params(&unmodulated_params(&state.modulations, &state.params));
+    preset.modulations = state
+        .modulations
+        .iter()
+        .map(|active| active.modulation.clone())
+        .collect();
+
+    let extent = state.textures.phm_extent;
+
+    Checkpoint {
+        header: CheckpointHeader {
+            step: state.step,
+            time: state.get_time(),
+            agent_count: state.capabilities.agent_count,
+            agent_size: std::mem::size_of::<Slime>() as u32,
+            field_width: extent.width,
+            field_height: extent.height,
+            preset,
+            view_params: state.params.view_params,
+            post_params: state.params.post_params,
+        },
+        agents: read_agents(state),
+        field: read_field_texels(state),
+    }
+}
+
+pub(crate) fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<(), Box<dyn Error>> {
+    let header = toml::to_string(&checkpoint.header)?;
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(CHECKPOINT_MAGIC)?;
+    file.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+    file.write_all(&(header.len() as u64).to_le_bytes())?;
+    file.write_all(header.as_bytes())?;
+    file.write_all(bytemuck::cast_slice(&checkpoint.agents))?;
+    file.write_all(bytemuck::cast_slice(&checkpoint.field))?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Reads a checkpoint written by this or any earlier version. Sizes in the
+/// header are checked against the file's length and `limits` before anything
+/// is allocated for them.
+pub(crate) fn read_checkpoint(
+    path: &Path,
+    limits: &wgpu::Limits,
+) -> Result<Checkpoint, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let length = file.metadata()?.len();
+    let mut file = BufReader::new(file);
+
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != CHECKPOINT_MAGIC {
+        return Err("not a checkpoint file".into());
+    }
+
+    let mut version = [0u8; 4];
+    file.read_exact(&mut version)?;
+
+    match u32::from_le_bytes(version) {
+        1 => read_checkpoint_v1(&mut file, length - 12, limits),
+        version => Err(format!(
+            "checkpoint version {} is newer than this build reads, {} or earlier",
+            version, CHECKPOINT_VERSION
+        )
+        .into()),
+    }
+}
+
+// `remaining` is what's left of the file after the magic and version
+fn read_checkpoint_v1(
+    file: &mut impl Read,
+    remaining: u64,
+    limits: &wgpu::Limits,
+) -> Result<Checkpoint, Box<dyn Error>> {
+    let mut header_len = [0u8; 8];
+    file.read_exact(&mut header_len)?;
+    let header_len = u64::from_le_bytes(header_len);
+    let remaining = remaining - 8;
+    if header_len > remaining {
+        return Err(format!(
+            "checkpoint header is {} bytes, only {} are left in the file",
+            header_len, remaining
+        )
+        .into());
+    }
+
+    let mut header = vec![0u8; header_len as usize];
+    file.read_exact(&mut header)?;
+    let header: CheckpointHeader = toml::from_str(std::str::from_utf8(&header)?)?;
+
+    if header.agent_size as usize != std::mem::size_of::<Slime>() {
+        return Err(format!(
+            "checkpoint agents are {} bytes, this build's are {}",
+            header.agent_size,
+            std::mem::size_of::<Slime>()
+        )
+        .into());
+    }
+
+    let agent_bytes = header.agent_count as u64 * header.agent_size as u64;
+    let max_agent_bytes = limits
+        .max_storage_buffer_binding_size
+        .min(limits.max_buffer_size.min(u32::MAX as u64) as u32);
+    if agent_bytes > max_agent_bytes as u64 {
+        return Err(format!(
+            "checkpoint has {} agents, more than a {} byte buffer holds",
+            header.agent_count, max_agent_bytes
+        )
+        .into());
+    }
+
+    let max_dimension = limits.max_texture_dimension_2d;
+    if header.field_width > max_dimension || header.field_height > max_dimension {
+        return Err(format!(
+            "checkpoint field is {}x{}, larger than {} a side",
+            header.field_width, header.field_height, max_dimension
+        )
+        .into());
+    }
+
+    let field_bytes = header.field_width as u64
+        * header.field_height as u64
+        * std::mem::size_of::<[f32; 4]>() as u64;
+    let data_bytes = remaining - header_len;
+    if agent_bytes + field_bytes != data_bytes {
+        return Err(format!(
+            "checkpoint header describes {} bytes of agents and field, the file holds {}",
+            agent_bytes + field_bytes,
+            data_bytes
+        )
+        .into());
+    }
+
+    let agents = read_pod(file, header.agent_count as usize)?;
+    let field = read_pod(
+        file,
+        header.field_width as usize * header.field_height as usize,
+    )?;
+
+    Ok(Checkpoint {
+        header,
+        agents,
+        field,
+    })
+}
+
+fn read_pod<T: bytemuck::Pod>(file: &mut impl Read, count: usize) -> std::io::Result<Vec<T>> {
+    let mut bytes = vec![0u8; count * std::mem::size_of::<T>()];
+    file.read_exact(&mut bytes)?;
+    Ok(bytemuck::pod_collect_to_vec(&bytes))
+}
+
+/// Writes a checkpoint's agents and field back to the GPU. The agent buffers
+/// have to be built for the checkpoint's count already, see
+/// `State::set_agent_count`. A field saved at another size is resampled,
+/// the field's size is fixed when the shaders are built.
+pub(crate) fn restore_checkpoint(
+    state: &State,
+    checkpoint: &Checkpoint,
+) -> Result<(), Box<dyn Error>> {
+    let header = &checkpoint.header;
+    let extent = state.textures.phm_extent;
+
+    if header.agent_count != state.capabilities.agent_count {
+        return Err(format!(
+            "checkpoint has {} agents, the buffers are built for {}",
+            header.agent_count, state.capabilities.agent_count
+        )
+        .into());
+    }
+
+    let saved = [header.field_width, header.field_height];
+    let field = if saved == [extent.width, extent.height] {
+        checkpoint.field.clone()
+    } else {
+        println!(
+            "Resampling the checkpoint's {}x{} field to {}x{}",
+            saved[0], saved[1], extent.width, extent.height
+        );
+        resample_field(&checkpoint.field, saved, [extent.width, extent.height])
+    };
+
+    state.queue.write_buffer(
+        &state.buffers.slime_pos_buf,
+        0,
+        bytemuck::cast_slice(&checkpoint.agents),
+    );
+
+    state.queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: state.textures.phm_front(state.phm_front),
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&field),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(extent.width * 4 * 4),
+            rows_per_image: Some(extent.height),
+        },
+        extent,
+    );
+
+    Ok(())
+}
+
+/// Bilinear resample of a field, texel centres of one size mapped onto the
+/// other. Agents are kept in normalised coordinates so they need no change.
+pub(crate) fn resample_field(field: &[[f32; 4]], from: [u32; 2], to: [u32; 2]) -> Vec<[f32; 4]> {
+    let [from_width, from_height] = from.map(|size| size as usize);
+    let texel =
+        |x: usize, y: usize| field[y.min(from_height - 1) * from_width + x.min(from_width - 1)];
+    // Position of `to` texel `i`'s centre in `from`'s texels, and the
+    // weight of the next one
+    let sample = |i: u32, to: u32, from: usize| {
+        let position = ((i as f32 + 0.5) * from as f32 / to as f32 - 0.5).max(0.0);
+        (position.floor() as usize, position.fract())
+    };
+
+    let mut resampled = Vec::with_capacity(to[0] as usize * to[1] as usize);
+    for y in 0..to[1] {
+        let (y0, ty) = sample(y, to[1], from_height);
+        for x in 0..to[0] {
+            let (x0, tx) = sample(x, to[0], from_width);
+            let mut value = [0.0; 4];
+            for (weight, (dx, dy)) in [
+                ((1.0 - tx) * (1.0 - ty), (0, 0)),
+                (tx * (1.0 - ty), (1, 0)),
+                ((1.0 - tx) * ty, (0, 1)),
+                (tx * ty, (1, 1)),
+            ] {
+                let neighbour = texel(x0 + dx, y0 + dy);
+                for (channel, level) in value.iter_mut().zip(neighbour) {
+                    *channel += weight * level;
+                }
+            }
+            resampled.push(value);
+        }
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init::init_functions::init_params;
+    use std::path::PathBuf;
+
+    const AGENTS: usize = 3;
+    const WIDTH: u32 = 2;
+    const HEIGHT: u32 = 2;
+
+    // Small enough to keep as a fixture, the file format doesn't care that
+    // this build runs more agents on a bigger field
+    fn checkpoint() -> Checkpoint {
+        let params = init_params();
+        let agents = (0..AGENTS)
+            .map(|i| Slime {
+                pos: [0.1 * i as f32, 0.5],
+                vel: [0.01, -0.02 * i as f32],
+                moved_forward: 1.0,
+                rng: [i as u32 + 1, 2, 3, 4],
+                ..bytemuck::Zeroable::zeroed()
+            })
+            .collect();
+        let field = (0..WIDTH * HEIGHT)
+            .map(|i| [i as f32 * 0.25, 0.0, 0.0, 1.0])
+            .collect();
+
+        Checkpoint {
+            header: CheckpointHeader {
+                step: 1234,
+                time: 56.5,
+                agent_count: AGENTS as u32,
+                agent_size: std::mem::size_of::<Slime>() as u32,
+                field_width: WIDTH,
+                field_height: HEIGHT,
+                preset: preset_from_params(&params),
+                view_params: params.view_params,
+                post_params: params.post_params,
+            },
+            agents,
+            field,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "slime_checkpoint_{}_{}.bin",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn assert_matches(read: &Checkpoint, expected: &Checkpoint) {
+        assert_eq!(read.header.step, expected.header.step);
+        assert_eq!(read.header.time, expected.header.time);
+        assert_eq!(read.header.agent_count, expected.header.agent_count);
+        assert_eq!(
+            [read.header.field_width, read.header.field_height],
+            [expected.header.field_width, expected.header.field_height]
+        );
+        assert_eq!(read.header.preset.seed, expected.header.preset.seed);
+        assert_eq!(
+            bytemuck::cast_slice::<Slime, u8>(&read.agents),
+            bytemuck::cast_slice::<Slime, u8>(&expected.agents)
+        );
+        assert_eq!(read.field, expected.field);
+    }
+
+    fn read_error(name: &str, bytes: &[u8]) -> String {
+        let path = temp_path(name);
+        std::fs::write(&path, bytes).unwrap();
+        let result = read_checkpoint(&path, &wgpu::Limits::default());
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Ok(_) => panic!("{} should be rejected", name),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = temp_path("round_trip");
+        let written = checkpoint();
+        write_checkpoint(&path, &written).unwrap();
+        let read = read_checkpoint(&path, &wgpu::Limits::default());
+        std::fs::remove_file(&path).unwrap();
+
+        let read = read.unwrap();
+        assert_matches(&read, &written);
+        assert_eq!(read.header.preset.params, written.header.preset.params);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = b"NOTSLIME".to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        assert!(read_error("bad_magic", &bytes).contains("not a checkpoint"));
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut bytes = CHECKPOINT_MAGIC.to_vec();
+        bytes.extend_from_slice(&(CHECKPOINT_VERSION + 1).to_le_bytes());
+        assert!(read_error("unknown_version", &bytes).contains("newer"));
+    }
+
+    #[test]
+    fn rejects_mismatched_agent_size() {
+        let path = temp_path("agent_size");
+        let mut written = checkpoint();
+        written.header.agent_size += 4;
+        write_checkpoint(&path, &written).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(read_error("agent_size", &bytes).contains("bytes"));
+    }
+
+    // A written checkpoint with its header length and header text patched
+    fn patched(name: &str, patch: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let path = temp_path(name);
+        write_checkpoint(&path, &checkpoint()).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        patch(&mut bytes);
+        bytes
+    }
+
+    fn replace_in_header(bytes: &mut Vec<u8>, from: &str, to: &str) {
+        let header_len = u64::from_le_bytes(bytes[12..20].try_into().unwrap()) as usize;
+        let header = std::str::from_utf8(&bytes[20..20 + header_len]).unwrap();
+        let header = header.replacen(from, to, 1);
+        bytes[12..20].copy_from_slice(&(header.len() as u64).to_le_bytes());
+        bytes.splice(20..20 + header_len, header.into_bytes());
+    }
+
+    #[test]
+    fn rejects_a_header_longer_than_the_file() {
+        let bytes = patched("header_len", |bytes| {
+            bytes[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        });
+        assert!(read_error("header_len", &bytes).contains("header is"));
+    }
+
+    #[test]
+    fn rejects_sizes_past_the_limits() {
+        let bytes = patched("agent_count", |bytes| {
+            replace_in_header(bytes, "agent_count = 3", "agent_count = 4000000000");
+        });
+        assert!(read_error("agent_count", &bytes).contains("more than"));
+
+        let bytes = patched("field_size", |bytes| {
+            replace_in_header(bytes, "field_width = 2", "field_width = 4000000000");
+        });
+        assert!(read_error("field_size", &bytes).contains("larger than"));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = patched("truncated", |bytes| {
+            bytes.truncate(bytes.len() - 1);
+        });
+        assert!(read_error("truncated", &bytes).contains("the file holds"));
+
+        let bytes = patched("wrong_count", |bytes| {
+            replace_in_header(bytes, "agent_count = 3", "agent_count = 30");
+        });
+        assert!(read_error("wrong_count", &bytes).contains("the file holds"));
+    }
+
+    #[test]
+    fn resampling_keeps_a_flat_field_flat() {
+        let field = vec![[0.5, 0.0, 0.0, 1.0]; 4 * 3];
+        let resampled = resample_field(&field, [4, 3], [7, 5]);
+        assert_eq!(resampled.len(), 7 * 5);
+        assert!(resampled.iter().all(|texel| (texel[0] - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn resampling_to_the_same_size_is_exact() {
+        let field = (0..6)
+            .map(|i| [i as f32, 0.0, 0.0, 1.0])
+            .collect::<Vec<_>>();
+        assert_eq!(resample_field(&field, [3, 2], [3, 2]), field);
+    }
+
+    #[test]
+    fn resampling_interpolates_between_texels() {
+        // Doubling a two texel ramp puts the middle texels a quarter and
+        // three quarters of the way along
+        let field = vec![[0.0, 0.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0]];
+        let levels = resample_field(&field, [2, 1], [4, 1])
+            .iter()
+            .map(|texel| texel[0])
+            .collect::<Vec<_>>();
+        assert_eq!(levels, [0.0, 0.25, 0.75, 1.0]);
+    }
+
+    // Written by the first version of the format, has to stay loadable
+    #[test]
+    fn reads_v1_fixture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/checkpoint/fixtures/v1.bin");
+        assert_matches(
+            &read_checkpoint(&path, &wgpu::Limits::default()).unwrap(),
+            &checkpoint(),
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::Write;
+
+use crate::{DebugChannel, DebugScope};
+
+/// Every channel shaders write, packed into the buffer in this order.
+/// Shaders index them with the constants from [`debug_wgsl_constants`], a
+/// channel's name upper-cased after DEBUG_.
+pub(crate) const DEBUG_CHANNELS: &[DebugChannel] = &[
+    DebugChannel {
+        name: "sensor_totals",
+        scope: DebugScope::PerAgent,
+        labels: ["s1", "s2", "s3", "moved_forward"],
+    },
+    DebugChannel {
+        name: "agent_motion",
+        scope: DebugScope::PerAgent,
+        labels: ["heading", "speed", "moved", "on_land"],
+    },
+    DebugChannel {
+        name: "trail_centre",
+        scope: DebugScope::Global,
+        labels: ["diffused", "decayed", "attractant", "land"],
+    },
+];
+
+impl DebugChannel {
+    pub(crate) fn len(&self, agent_count: usize) -> usize {
+        match self.scope {
+            DebugScope::PerAgent => agent_count,
+            DebugScope::Global => 1,
+        }
+    }
+}
+
+/// First vec4 of `DEBUG_CHANNELS[index]` in the buffer, or the buffer's size
+/// in vec4s for `DEBUG_CHANNELS.len()`
+pub(crate) fn debug_slot(index: usize, agent_count: usize) -> usize {
+    DEBUG_CHANNELS[..index]
+        .iter()
+        .map(|channel| channel.len(agent_count))
+        .sum()
+}
+
+/// Size of the debug channels buffer in vec4s
+pub(crate) fn debug_slots(agent_count: usize) -> usize {
+    debug_slot(DEBUG_CHANNELS.len(), agent_count)
+}
+
+/// `DEBUG_CHANNELS[index]`'s values out of the whole buffer
+pub(crate) fn debug_channel_values(
+    index: usize,
+    slots: &[[f32; 4]],
+    agent_count: usize,
+) -> &[[f32; 4]] {
+    let start = debug_slot(index, agent_count);
+    &slots[start..start + DEBUG_CHANNELS[index].len(agent_count)]
+}
+
+/// The slot of each channel as a WGSL constant, and how many agents a
+/// per-agent channel has room for, prepended to shaders/common/debug.wgsl
+pub(crate) fn debug_wgsl_constants(agent_count: usize) -> String {
+    let mut text = String::new();
+    for (index, channel) in DEBUG_CHANNELS.iter().enumerate() {
+        let _ = writeln!(
+            text,
+            "const DEBUG_{}: u32 = {}u;",
+            channel.name.to_uppercase(),
+            debug_slot(index, agent_count)
+        );
+    }
+    let _ = writeln!(text, "const DEBUG_AGENT_SLOTS: u32 = {}u;", agent_count);
+    text
+}
+
+/// Lowest and highest finite value of one component across a channel,
+/// `[0, 1]` if there are none
+pub(crate) fn component_range(values: &[[f32; 4]], component: usize) -> [f32; 2] {
+    let (low, high) = values
+        .iter()
+        .map(|value| value[component])
+        .filter(|value| value.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| {
+            (low.min(value), high.max(value))
+        });
+
+    if low > high {
+        [0.0, 1.0]
+    } else if low == high {
+        // A flat channel still needs a range to map onto
+        [low - 0.5, high + 0.5]
+    } else {
+        [low, high]
+    }
+}
+
+/// A channel's values with their labels. Per-agent channels get a line per
+/// agent after the minimum, mean and maximum of each component.
+pub(crate) fn format_debug_channel(channel: &DebugChannel, values: &[[f32; 4]]) -> String {
+    let mut text = format!("\n{}:\n", channel.name);
+
+    if channel.scope == DebugScope::Global {
+        for (label, value) in channel.labels.iter().zip(values[0]) {
+            let _ = writeln!(text, "  {:>14} {}", label, value);
+        }
+        return text;
+    }
+
+    let _ = writeln!(
+        text,
+        "  {:>14} {:>14} {:>14} {:>14}",
+        "", "min", "mean", "max"
+    );
+    for (component, label) in channel.labels.iter().enumerate() {
+        let [low, high] = component_range(values, component);
+        let mean = values.iter().map(|value| value[component]).sum::<f32>() / values.len() as f32;
+        let _ = writeln!(
+            text,
+            "  {:>14} {:>14.6} {:>14.6} {:>14.6}",
+            label, low, mean, high
+        );
+    }
+
+    let _ = writeln!(
+        text,
+        "\n  {:>5} {:>14} {:>14} {:>14} {:>14}",
+        "agent", channel.labels[0], channel.labels[1], channel.labels[2], channel.labels[3]
+    );
+    for (agent, value) in values.iter().enumerate() {
+        let _ = writeln!(
+            text,
+            "  {:>5} {:>14.6} {:>14.6} {:>14.6} {:>14.6}",
+            agent, value[0], value[1], value[2], value[3]
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_fit_without_overlapping() {
+        for agent_count in [1, 256, 1000] {
+            let mut used = vec![false; debug_slots(agent_count)];
+            for (index, channel) in DEBUG_CHANNELS.iter().enumerate() {
+                let start = debug_slot(index, agent_count);
+                let end = start + channel.len(agent_count);
+                assert!(end <= used.len(), "{} runs past the buffer", channel.name);
+                for slot in &mut used[start..end] {
+                    assert!(!*slot, "{} overlaps another channel", channel.name);
+                    *slot = true;
+                }
+            }
+            assert!(used.iter().all(|&slot| slot));
+        }
+    }
+
+    #[test]
+    fn constants_follow_the_agent_count() {
+        let constants = debug_wgsl_constants(100);
+        assert!(constants.contains("const DEBUG_SENSOR_TOTALS: u32 = 0u;"));
+        assert!(constants.contains("const DEBUG_AGENT_MOTION: u32 = 100u;"));
+        assert!(constants.contains("const DEBUG_TRAIL_CENTRE: u32 = 200u;"));
+        assert!(constants.contains("const DEBUG_AGENT_SLOTS: u32 = 100u;"));
+        for channel in DEBUG_CHANNELS {
+            let name = format!("DEBUG_{}:", channel.name.to_uppercase());
+            assert!(
+                constants.contains(&name),
+                "no constant for {}",
+                channel.name
+            );
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    state::{app_state::State, readback_state::read_back_blocking},
+    FieldFormat,
+};
+
+// Bytes per texel of the Rgba32Float field
+const FIELD_TEXEL_SIZE: u32 = 16;
+
+/// Copies the current pheremone field back from the GPU, row by row from the
+/// top. Blocks until the copy is done.
+pub(crate) fn read_field_texels(state: &State) -> Vec<[f32; 4]> {
+    let extent = state.textures.phm_extent;
+    let row_size = extent.width * FIELD_TEXEL_SIZE;
+    let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let data = read_back_blocking(
+        &state.device,
+        &state.queue,
+        padded_row_size as wgpu::BufferAddress * extent.height as wgpu::BufferAddress,
+        "field",
+        |encoder, buffer| {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: state.textures.phm_front(state.phm_front),
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_row_size),
+                        rows_per_image: Some(extent.height),
+                    },
+                },
+                extent,
+            );
+        },
+    );
+
+    data.chunks_exact(padded_row_size as usize)
+        .flat_map(|row| bytemuck::pod_collect_to_vec::<u8, [f32; 4]>(&row[..row_size as usize]))
+        .collect()
+}
+
+/// The trail level of each texel, see [`read_field_texels`]
+pub(crate) fn read_field(state: &State) -> Vec<f32> {
+    read_field_texels(state)
+        .into_iter()
+        .map(|texel| texel[0])
+        .collect()
+}
+
+/// The lowest and highest finite levels in the field, `[0, 1]` if there are none
+pub(crate) fn field_range(field: &[f32]) -> [f32; 2] {
+    let (low, high) = field
+        .iter()
+        .copied()
+        .filter(|value| value.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| {
+            (low.min(value), high.max(value))
+        });
+
+    if low > high {
+        [0.0, 1.0]
+    } else {
+        [low, high]
+    }
+}
+
+/// Writes a NumPy `.npy` file, version 1.0, of little endian float32 shaped
+/// `(height, width)`
+pub(crate) fn write_npy(
+    path: &Path,
+    width: u32,
+    height: u32,
+    field: &[f32],
+) -> std::io::Result<()> {
+    assert_eq!(field.len(), width as usize * height as usize);
+
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        height, width
+    );
+    // Magic, version and length come first, the data starts 64 byte aligned
+    // after a newline
+    let unpadded = 10 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"\x93NUMPY\x01\x00")?;
+    file.write_all(&(header.len() as u16).to_le_bytes())?;
+    file.write_all(header.as_bytes())?;
+    for value in field {
+        file.write_all(&value.to_le_bytes())?;
+    }
+    file.flush()
+}
+
+/// Writes a single channel float32 OpenEXR image, channel `Y` so viewers
+/// show it as grayscale
+pub(crate) fn write_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    field: &[f32],
+) -> exr::error::UnitResult {
+    use exr::prelude::*;
+
+    assert_eq!(field.len(), width as usize * height as usize);
+
+    let channel = AnyChannel::new("Y", FlatSamples::F32(field.to_vec()));
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::named("pheremone"),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(vec![channel].into()),
+    );
+
+    Image::from_layer(layer).write().to_file(path)
+}
+
+/// Writes a 16-bit grayscale PNG, `range` mapped to black and white and
+/// anything outside it clamped
+pub(crate) fn write_png16(
+    path: &Path,
+    width: u32,
+    height: u32,
+    field: &[f32],
+    range: [f32; 2],
+) -> image::ImageResult<()> {
+    let [low, high] = range;
+    let scale = if high > low {
+        u16::MAX as f32 / (high - low)
+    } else {
+        0.0
+    };
+
+    let pixels = field
+        .iter()
+        .map(|value| ((value - low) * scale).round().clamp(0.0, u16::MAX as f32) as u16)
+        .collect::<Vec<u16>>();
+
+    image::ImageBuffer::<image::Luma<u16>, _>::from_raw(width, height, pixels)
+        .expect("field should match its size")
+        .save_with_format(path, image::ImageFormat::Png)
+}
+
+/// Writes an already read back field, see [`read_field`]. PNGs are normalised
+/// to `png_range`, or to the field's own range if that's `None`.
+pub(crate) fn write_field(
+    path: &Path,
+    format: FieldFormat,
+    extent: wgpu::Extent3d,
+    field: &[f32],
+    png_range: Option<[f32; 2]>,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (extent.width, extent.height);
+
+    match format {
+        FieldFormat::Npy => write_npy(path, width, height, field)?,
+        FieldFormat::Exr => write_exr(path, width, height, field)?,
+        FieldFormat::Png => write_png16(
+            path,
+            width,
+            height,
+            field,
+            png_range.unwrap_or_else(|| field_range(field)),
+        )?,
+    }
+    Ok(())
+}
+
+/// Reads the field back once and writes it in each export format, named by
+/// step, into the export directory
+pub(crate) fn export_fields(state: &State) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let settings = &state.export;
+    std::fs::create_dir_all(&settings.dir)?;
+
+    let field = read_field(state);
+    let mut paths = Vec::new();
+
+    for &format in &settings.formats {
+        let path = settings
+            .dir
+            .join(format!("field_{:08}.{}", state.step, format.extension()));
+        write_field(
+            &path,
+            format,
+            state.textures.phm_extent,
+            &field,
+            settings.png_range,
+        )?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 7;
+    const HEIGHT: u32 = 3;
+
+    // Not square so a transposed read shows, with values a lossy or half
+    // precision path would change
+    fn known_field() -> Vec<f32> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| match i {
+                0 => 0.0,
+                1 => 1.0e-30,
+                2 => 123_456.79,
+                3 => -2.5,
+                _ => (i as f32).sqrt() / 3.0,
+            })
+            .collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("slime_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    // Just enough of the format to check what write_npy produces
+    fn read_npy(path: &Path) -> (String, Vec<f32>) {
+        let bytes = std::fs::read(path).unwrap();
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let data_start = 10 + header_len;
+        assert_eq!(data_start % 64, 0);
+
+        let header = String::from_utf8(bytes[10..data_start].to_vec()).unwrap();
+        let data = bytes[data_start..]
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        (header, data)
+    }
+
+    #[test]
+    fn npy_round_trips() {
+        let field = known_field();
+        let path = temp_path("field.npy");
+        write_npy(&path, WIDTH, HEIGHT, &field).unwrap();
+
+        let (header, data) = read_npy(&path);
+        assert!(header.contains("'descr': '<f4'"));
+        assert!(header.contains("'fortran_order': False"));
+        assert!(header.contains("'shape': (3, 7)"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(data, field);
+    }
+
+    #[test]
+    fn exr_round_trips() {
+        let field = known_field();
+        let path = temp_path("field.exr");
+        write_exr(&path, WIDTH, HEIGHT, &field).unwrap();
+
+        let image = exr::prelude::read_first_flat_layer_from_file(&path).unwrap();
+        let layer = image.layer_data;
+        assert_eq!(layer.size, exr::math::Vec2(WIDTH as usize, HEIGHT as usize));
+        assert_eq!(layer.channel_data.list.len(), 1);
+
+        let channel = &layer.channel_data.list[0];
+        assert_eq!(channel.name.to_string(), "Y");
+        match &channel.sample_data {
+            exr::prelude::FlatSamples::F32(data) => assert_eq!(data, &field),
+            other => panic!("expected float32 samples, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn png_round_trips_within_a_step() {
+        let field = known_field();
+        let range = [-2.5, 10.0];
+        let path = temp_path("field.png");
+        write_png16(&path, WIDTH, HEIGHT, &field, range).unwrap();
+
+        let image = image::open(&path).unwrap();
+        assert_eq!(image.color(), image::ColorType::L16);
+        let image = image.into_luma16();
+        assert_eq!(image.dimensions(), (WIDTH, HEIGHT));
+
+        let step = (range[1] - range[0]) / u16::MAX as f32;
+        for (pixel, value) in image.pixels().zip(&field) {
+            let expected = value.clamp(range[0], range[1]);
+            let decoded = range[0] + pixel.0[0] as f32 * step;
+            assert!(
+                (decoded - expected).abs() <= step * 0.5 + 1e-6,
+                "{} decoded as {}",
+                value,
+                decoded
+            );
+        }
+        // Clamped to white above the range, the bottom of the range is black
+        assert_eq!(image.get_pixel(2, 0).0[0], u16::MAX);
+        assert_eq!(image.get_pixel(3, 0).0[0], 0);
+    }
+
+    #[test]
+    fn field_range_skips_non_finite() {
+        assert_eq!(
+            field_range(&[2.0, f32::NAN, -1.0, f32::INFINITY]),
+            [-1.0, 2.0]
+        );
+        assert_eq!(field_range(&[]), [0.0, 1.0]);
+    }
+}
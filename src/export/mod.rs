@@ -0,0 +1 @@
+pub(crate) mod export_functions;
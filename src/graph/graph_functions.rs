@@ -0,0 +1,885 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::{
+    export::export_functions::read_field, state::app_state::State, Graph, GraphEdge, GraphNode,
+    NamedLink, PointOfInterest,
+};
+
+const OTSU_BINS: usize = 256;
+// How far past its radius a food source reaches for a node to name
+const LABEL_REACH: f32 = 10.0;
+
+// Orthogonal neighbours come first so a trace follows the staircases thinning
+// leaves on diagonals texel by texel instead of skipping their corners
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (1, -1),
+    (1, 1),
+    (-1, 1),
+    (-1, -1),
+];
+
+// P2 to P9 of Zhang and Suen, clockwise from north
+const RING: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// The level that best splits the field into background and network, by
+/// Otsu's method over a histogram of the field's range
+pub(crate) fn otsu_threshold(field: &[f32]) -> f32 {
+    let values = field.iter().copied().filter(|value| value.is_finite());
+    let (low, high) = values
+        .clone()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| {
+            (low.min(value), high.max(value))
+        });
+    if low >= high {
+        return high.max(0.0);
+    }
+
+    let bin_width = (high - low) / OTSU_BINS as f32;
+    let mut histogram = [0u64; OTSU_BINS];
+    for value in values {
+        let bin = ((value - low) / bin_width) as usize;
+        histogram[bin.min(OTSU_BINS - 1)] += 1;
+    }
+
+    let total = histogram.iter().sum::<u64>() as f64;
+    let weighted_total = histogram
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| bin as f64 * count as f64)
+        .sum::<f64>();
+
+    // Maximise the variance between the classes either side of each bin edge
+    let (mut below, mut weighted_below) = (0.0, 0.0);
+    let (mut best_bin, mut best_variance) = (0, -1.0);
+    for (bin, &count) in histogram.iter().enumerate() {
+        below += count as f64;
+        weighted_below += bin as f64 * count as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+
+        let mean_difference = weighted_below / below - (weighted_total - weighted_below) / above;
+        let variance = below * above * mean_difference * mean_difference;
+        if variance > best_variance {
+            best_variance = variance;
+            best_bin = bin;
+        }
+    }
+
+    low + (best_bin + 1) as f32 * bin_width
+}
+
+fn texel(mask: &[bool], width: usize, height: usize, x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height && {
+        mask[y as usize * width + x as usize]
+    }
+}
+
+// Set neighbours and 0 to 1 transitions around the ring of (x, y)
+fn neighbourhood(mask: &[bool], width: usize, height: usize, x: i32, y: i32) -> (u32, u32) {
+    let ring = RING.map(|(dx, dy)| texel(mask, width, height, x + dx, y + dy));
+    let count = ring.iter().filter(|&&set| set).count() as u32;
+    let crossings = (0..8).filter(|&i| !ring[i] && ring[(i + 1) % 8]).count() as u32;
+    (count, crossings)
+}
+
+/// Thins the mask to one texel wide lines with Zhang and Suen's algorithm,
+/// keeping its connectivity and line ends
+pub(crate) fn skeletonise(mask: &[bool], width: u32, height: u32) -> Vec<bool> {
+    let (width, height) = (width as usize, height as usize);
+    let mut skeleton = mask.to_vec();
+    let mut removed = Vec::new();
+
+    loop {
+        let mut changed = false;
+
+        for first in [true, false] {
+            removed.clear();
+            for y in 0..height {
+                for x in 0..width {
+                    if !skeleton[y * width + x] {
+                        continue;
+                    }
+
+                    let (x, y) = (x as i32, y as i32);
+                    let (count, crossings) = neighbourhood(&skeleton, width, height, x, y);
+                    if !(2..=6).contains(&count) || crossings != 1 {
+                        continue;
+                    }
+
+                    let at = |(dx, dy): (i32, i32)| texel(&skeleton, width, height, x + dx, y + dy);
+                    let [north, east, south, west] = [(0, -1), (1, 0), (0, 1), (-1, 0)].map(at);
+                    let clear = if first {
+                        !(east && south && (north || west))
+                    } else {
+                        !(north && west && (east || south))
+                    };
+                    if clear {
+                        removed.push(y as usize * width + x as usize);
+                    }
+                }
+            }
+
+            changed |= !removed.is_empty();
+            for &index in &removed {
+                skeleton[index] = false;
+            }
+        }
+
+        if !changed {
+            return skeleton;
+        }
+    }
+}
+
+struct Tracer<'a> {
+    field: &'a [f32],
+    skeleton: Vec<bool>,
+    width: usize,
+    height: usize,
+    // Which node each node texel belongs to
+    node_of: Vec<Option<usize>>,
+    // Edge texels already on a path
+    visited: Vec<bool>,
+    // Texels making up each node
+    node_texels: Vec<Vec<usize>>,
+    edges: Vec<GraphEdge>,
+}
+
+impl Tracer<'_> {
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = ((index % self.width) as i32, (index / self.width) as i32);
+        NEIGHBOURS
+            .iter()
+            .filter(move |(dx, dy)| texel(&self.skeleton, self.width, self.height, x + dx, y + dy))
+            .map(move |(dx, dy)| (y + dy) as usize * self.width + (x + dx) as usize)
+    }
+
+    fn add_node(&mut self, texels: Vec<usize>) -> usize {
+        let id = self.node_texels.len();
+        for &index in &texels {
+            self.node_of[index] = Some(id);
+        }
+        self.node_texels.push(texels);
+        id
+    }
+
+    // Ends and junctions are texels whose neighbours don't form exactly two
+    // runs, touching ones make up one node
+    fn find_nodes(&mut self) {
+        let is_node = |tracer: &Self, index: usize| {
+            let (x, y) = ((index % tracer.width) as i32, (index / tracer.width) as i32);
+            let (count, crossings) =
+                neighbourhood(&tracer.skeleton, tracer.width, tracer.height, x, y);
+            tracer.skeleton[index] && count > 0 && crossings != 2
+        };
+
+        for start in 0..self.skeleton.len() {
+            if self.node_of[start].is_some() || !is_node(self, start) {
+                continue;
+            }
+
+            let id = self.add_node(vec![start]);
+            let mut pending = vec![start];
+            while let Some(index) = pending.pop() {
+                let neighbours = self.neighbours(index).collect::<Vec<usize>>();
+                for neighbour in neighbours {
+                    if self.node_of[neighbour].is_none() && is_node(self, neighbour) {
+                        self.node_of[neighbour] = Some(id);
+                        self.node_texels[id].push(neighbour);
+                        pending.push(neighbour);
+                    }
+                }
+            }
+        }
+    }
+
+    // Follows the skeleton from `first`, next to node `source`'s texel
+    // `start`, to the next node
+    fn trace(&mut self, source: usize, start: usize, first: usize) {
+        let mut path = vec![start, first];
+        self.visited[first] = true;
+        let (mut previous, mut current) = (start, first);
+
+        let target = loop {
+            // Right next to its own node a path hasn't gone anywhere yet
+            let node = self.neighbours(current).find(|&neighbour| {
+                neighbour != previous
+                    && self.node_of[neighbour].is_some_and(|node| node != source || path.len() > 3)
+            });
+            if let Some(node) = node {
+                path.push(node);
+                break self.node_of[node].unwrap();
+            }
+
+            let next = self
+                .neighbours(current)
+                .find(|&neighbour| self.node_of[neighbour].is_none() && !self.visited[neighbour]);
+            match next {
+                Some(next) => {
+                    self.visited[next] = true;
+                    path.push(next);
+                    (previous, current) = (current, next);
+                }
+                // Only left by texels thinning couldn't resolve, end the
+                // path there
+                None => break self.add_node(vec![current]),
+            }
+        };
+
+        let position = |index: usize| [(index % self.width) as u32, (index / self.width) as u32];
+        let distance = |from: usize, to: usize| {
+            let ([x0, y0], [x1, y1]) = (position(from), position(to));
+            (x0.abs_diff(x1) as f32).hypot(y0.abs_diff(y1) as f32)
+        };
+
+        // Corners of staircases are cut, measuring them as diagonal steps
+        let mut length = 0.0;
+        let mut i = 0;
+        while i + 1 < path.len() {
+            let skip = path
+                .get(i + 2)
+                .is_some_and(|&next| distance(path[i], next) < 1.5);
+            let next = if skip { i + 2 } else { i + 1 };
+            length += distance(path[i], path[next]);
+            i = next;
+        }
+        let mean_intensity =
+            path.iter().map(|&index| self.field[index]).sum::<f32>() / path.len() as f32;
+
+        self.edges.push(GraphEdge {
+            source,
+            target,
+            length,
+            mean_intensity,
+            path: path.into_iter().map(position).collect(),
+        });
+    }
+
+    fn trace_from(&mut self, node: usize) {
+        for start in self.node_texels[node].clone() {
+            let firsts = self.neighbours(start).collect::<Vec<usize>>();
+            for first in firsts {
+                if self.node_of[first].is_none() && !self.visited[first] {
+                    self.trace(node, start, first);
+                }
+            }
+        }
+    }
+}
+
+/// Thresholds and thins the field, then follows the skeleton between its
+/// junctions and ends. `threshold` defaults to [`otsu_threshold`].
+pub(crate) fn extract_graph(
+    field: &[f32],
+    width: u32,
+    height: u32,
+    threshold: Option<f32>,
+) -> Graph {
+    let threshold = threshold.unwrap_or_else(|| otsu_threshold(field));
+    let mask = field
+        .iter()
+        .map(|&value| value > threshold)
+        .collect::<Vec<bool>>();
+
+    let mut tracer = Tracer {
+        field,
+        skeleton: skeletonise(&mask, width, height),
+        width: width as usize,
+        height: height as usize,
+        node_of: vec![None; field.len()],
+        visited: vec![false; field.len()],
+        node_texels: Vec::new(),
+        edges: Vec::new(),
+    };
+
+    tracer.find_nodes();
+    for node in 0..tracer.node_texels.len() {
+        tracer.trace_from(node);
+    }
+
+    // Whatever is left is loops without a junction, anchor each with a node
+    for index in 0..tracer.skeleton.len() {
+        if tracer.skeleton[index] && tracer.node_of[index].is_none() && !tracer.visited[index] {
+            let node = tracer.add_node(vec![index]);
+            tracer.trace_from(node);
+        }
+    }
+
+    let mut degrees = vec![0; tracer.node_texels.len()];
+    for edge in &tracer.edges {
+        degrees[edge.source] += 1;
+        degrees[edge.target] += 1;
+    }
+
+    // Nodes without edges are specks, not part of any network
+    let mut ids = vec![None; degrees.len()];
+    let mut nodes = Vec::new();
+    for (node, texels) in tracer.node_texels.iter().enumerate() {
+        if degrees[node] == 0 {
+            continue;
+        }
+
+        let [sum_x, sum_y] = texels.iter().fold([0.0, 0.0], |[x, y], &index| {
+            [
+                x + (index % tracer.width) as f32 + 0.5,
+                y + (index / tracer.width) as f32 + 0.5,
+            ]
+        });
+        ids[node] = Some(nodes.len());
+        nodes.push(GraphNode {
+            position: [sum_x / texels.len() as f32, sum_y / texels.len() as f32],
+            name: None,
+            degree: degrees[node],
+        });
+    }
+
+    let edges = tracer
+        .edges
+        .into_iter()
+        .map(|edge| GraphEdge {
+            source: ids[edge.source].unwrap(),
+            target: ids[edge.target].unwrap(),
+            ..edge
+        })
+        .collect();
+
+    Graph {
+        width,
+        height,
+        threshold,
+        nodes,
+        edges,
+    }
+}
+
+/// Names the node nearest each point, within `max_distance` texels. Points
+/// sharing a node are joined with `/`. Returns the names of points with no
+/// node in reach.
+pub(crate) fn label_graph(
+    graph: &mut Graph,
+    points: &[PointOfInterest],
+    max_distance: f32,
+) -> Vec<String> {
+    let mut unplaced = Vec::new();
+
+    for point in points {
+        let distance = |node: &GraphNode| {
+            (node.position[0] - point.position[0]).hypot(node.position[1] - point.position[1])
+        };
+        let nearest = graph
+            .nodes
+            .iter_mut()
+            .map(|node| (distance(node), node))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        match nearest {
+            Some((_, node)) => {
+                node.name = Some(match node.name.take() {
+                    Some(name) => format!("{}/{}", name, point.name),
+                    None => point.name.clone(),
+                })
+            }
+            None => unplaced.push(point.name.clone()),
+        }
+    }
+
+    unplaced
+}
+
+/// Pairs of named nodes the network joins directly, without passing through
+/// another named node, with the shortest length between them
+pub(crate) fn named_links(graph: &Graph) -> Vec<NamedLink> {
+    let mut adjacent = vec![Vec::new(); graph.nodes.len()];
+    for edge in &graph.edges {
+        adjacent[edge.source].push((edge.target, edge.length));
+        adjacent[edge.target].push((edge.source, edge.length));
+    }
+
+    let mut links = Vec::new();
+    for (source, node) in graph.nodes.iter().enumerate() {
+        let Some(source_name) = &node.name else {
+            continue;
+        };
+
+        // Dijkstra, lengths are never negative so their bits sort like them
+        let mut distances = vec![f32::INFINITY; graph.nodes.len()];
+        let mut queue = BinaryHeap::new();
+        distances[source] = 0.0;
+        queue.push(Reverse((0f32.to_bits(), source)));
+
+        while let Some(Reverse((distance, current))) = queue.pop() {
+            let distance = f32::from_bits(distance);
+            if distance > distances[current] {
+                continue;
+            }
+
+            if current != source {
+                if let Some(target_name) = &graph.nodes[current].name {
+                    // Each pair once, from its lower index
+                    if current > source {
+                        links.push(NamedLink {
+                            source: source_name.clone(),
+                            target: target_name.clone(),
+                            length: distance,
+                        });
+                    }
+                    continue;
+                }
+            }
+
+            for &(next, length) in &adjacent[current] {
+                let next_distance = distance + length;
+                if next_distance < distances[next] {
+                    distances[next] = next_distance;
+                    queue.push(Reverse((next_distance.to_bits(), next)));
+                }
+            }
+        }
+    }
+
+    links
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+pub(crate) fn graph_to_graphml(graph: &Graph) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (id, target, name, kind) in [
+        ("x", "node", "x", "float"),
+        ("y", "node", "y", "float"),
+        ("degree", "node", "degree", "int"),
+        ("name", "node", "name", "string"),
+        ("length", "edge", "length", "float"),
+        ("intensity", "edge", "mean_intensity", "float"),
+    ] {
+        let _ = writeln!(
+            xml,
+            "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>",
+            id, target, name, kind
+        );
+    }
+    xml.push_str("  <graph id=\"pheremone\" edgedefault=\"undirected\">\n");
+
+    for (id, node) in graph.nodes.iter().enumerate() {
+        let name = node.name.as_deref().map_or(String::new(), |name| {
+            format!("<data key=\"name\">{}</data>", xml_escape(name))
+        });
+        let _ = writeln!(
+            xml,
+            "    <node id=\"n{}\"><data key=\"x\">{}</data><data key=\"y\">{}</data>\
+             <data key=\"degree\">{}</data>{}</node>",
+            id, node.position[0], node.position[1], node.degree, name
+        );
+    }
+    for (id, edge) in graph.edges.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\"><data key=\"length\">{}</data>\
+             <data key=\"intensity\">{}</data></edge>",
+            id, edge.source, edge.target, edge.length, edge.mean_intensity
+        );
+    }
+
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+/// Nodes as points and edges as lines along their paths. GIS tools expect y
+/// up, so coordinates are in texels from the bottom left.
+pub(crate) fn graph_to_geojson(graph: &Graph) -> String {
+    let height = graph.height as f32;
+    let mut features = Vec::new();
+
+    for (id, node) in graph.nodes.iter().enumerate() {
+        features.push(format!(
+            "{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"Point\",\"coordinates\":[{},{}]}},\
+             \"properties\":{{\"id\":{},\"degree\":{},\"name\":{}}}}}",
+            node.position[0],
+            height - node.position[1],
+            id,
+            node.degree,
+            node.name.as_deref().map_or("null".to_string(), json_string)
+        ));
+    }
+    for (id, edge) in graph.edges.iter().enumerate() {
+        let coordinates = edge
+            .path
+            .iter()
+            .map(|&[x, y]| format!("[{},{}]", x as f32 + 0.5, height - (y as f32 + 0.5)))
+            .collect::<Vec<String>>()
+            .join(",");
+        features.push(format!(
+            "{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"LineString\",\"coordinates\":[{}]}},\
+             \"properties\":{{\"id\":{},\"source\":{},\"target\":{},\"length\":{},\
+             \"mean_intensity\":{}}}}}",
+            coordinates, id, edge.source, edge.target, edge.length, edge.mean_intensity
+        ));
+    }
+
+    format!(
+        "{{\"type\":\"FeatureCollection\",\"bbox\":[0,0,{},{}],\"features\":[\n{}\n]}}\n",
+        graph.width,
+        graph.height,
+        features.join(",\n")
+    )
+}
+
+/// One row per named link, names quoted
+pub(crate) fn links_to_csv(links: &[NamedLink]) -> String {
+    let mut csv = String::from("source,target,length\n");
+    for link in links {
+        let _ = writeln!(
+            csv,
+            "\"{}\",\"{}\",{}",
+            link.source.replace('"', "\"\""),
+            link.target.replace('"', "\"\""),
+            link.length
+        );
+    }
+    csv
+}
+
+/// Extracts the network from the current field and writes it as GraphML and
+/// GeoJSON, named by step, into the export directory. With food sources
+/// loaded their nodes are named and the links between them written as CSV.
+pub(crate) fn export_graph(state: &State) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let settings = &state.export;
+    std::fs::create_dir_all(&settings.dir)?;
+
+    let extent = state.textures.phm_extent;
+    let mut graph = extract_graph(
+        &read_field(state),
+        extent.width,
+        extent.height,
+        settings.graph_threshold,
+    );
+    println!(
+        "Extracted {} nodes and {} edges above {}",
+        graph.nodes.len(),
+        graph.edges.len(),
+        graph.threshold
+    );
+
+    let sources = &state.sources;
+    let links = if sources.points.is_empty() {
+        None
+    } else {
+        let unplaced = label_graph(&mut graph, &sources.points, sources.radius + LABEL_REACH);
+        if !unplaced.is_empty() {
+            println!("No network reaches {}", unplaced.join(", "));
+        }
+        Some(named_links(&graph))
+    };
+
+    let base = settings.dir.join(format!("graph_{:08}", state.step));
+    let mut paths = vec![
+        base.with_extension("graphml"),
+        base.with_extension("geojson"),
+    ];
+    std::fs::write(&paths[0], graph_to_graphml(&graph))?;
+    std::fs::write(&paths[1], graph_to_geojson(&graph))?;
+
+    if let Some(links) = links {
+        for link in &links {
+            println!(
+                "  {} - {}, {:.1} texels",
+                link.source, link.target, link.length
+            );
+        }
+        let path = settings
+            .dir
+            .join(format!("graph_{:08}_links.csv", state.step));
+        std::fs::write(&path, links_to_csv(&links))?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    // Background of 0 with `level` wherever `inside` holds
+    fn field(inside: impl Fn(f32, f32) -> Option<f32>) -> Vec<f32> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let (x, y) = ((i % WIDTH) as f32 + 0.5, (i / WIDTH) as f32 + 0.5);
+                inside(x, y).unwrap_or(0.0)
+            })
+            .collect()
+    }
+
+    fn bar(x: f32, y: f32, from: [f32; 2], to: [f32; 2], half_width: f32) -> bool {
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let t = (((x - from[0]) * dx + (y - from[1]) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+        let (px, py) = (from[0] + t * dx - x, from[1] + t * dy - y);
+        (px * px + py * py).sqrt() <= half_width
+    }
+
+    fn degrees(graph: &Graph) -> Vec<usize> {
+        let mut degrees = graph
+            .nodes
+            .iter()
+            .map(|node| node.degree)
+            .collect::<Vec<usize>>();
+        degrees.sort();
+        degrees
+    }
+
+    #[test]
+    fn skeleton_of_a_bar_is_one_texel_wide() {
+        let mask = field(|x, y| bar(x, y, [10.0, 24.0], [54.0, 24.0], 3.0).then_some(1.0))
+            .iter()
+            .map(|&value| value > 0.5)
+            .collect::<Vec<bool>>();
+        let skeleton = skeletonise(&mask, WIDTH, HEIGHT);
+
+        for x in 14..50 {
+            let column = (0..HEIGHT)
+                .filter(|&y| skeleton[(y * WIDTH + x) as usize])
+                .count();
+            assert_eq!(column, 1, "column {}", x);
+        }
+    }
+
+    #[test]
+    fn straight_line_is_one_edge() {
+        let field = field(|x, y| bar(x, y, [8.0, 20.0], [56.0, 20.0], 2.0).then_some(x / 10.0));
+        let graph = extract_graph(&field, WIDTH, HEIGHT, Some(0.0));
+
+        assert_eq!(degrees(&graph), vec![1, 1]);
+        assert_eq!(graph.edges.len(), 1);
+
+        let edge = &graph.edges[0];
+        assert!((40.0..=50.0).contains(&edge.length), "{}", edge.length);
+        // Intensity rises along x, its mean is near the middle of the line
+        assert!(
+            (edge.mean_intensity - 3.2).abs() < 0.3,
+            "{}",
+            edge.mean_intensity
+        );
+    }
+
+    #[test]
+    fn cross_has_a_junction_and_four_arms() {
+        let field = field(|x, y| {
+            (bar(x, y, [8.0, 24.0], [56.0, 24.0], 2.5) || bar(x, y, [32.0, 4.0], [32.0, 44.0], 2.5))
+                .then_some(1.0)
+        });
+        let graph = extract_graph(&field, WIDTH, HEIGHT, Some(0.5));
+
+        assert_eq!(degrees(&graph), vec![1, 1, 1, 1, 4]);
+        assert_eq!(graph.edges.len(), 4);
+
+        let junction = graph.nodes.iter().find(|node| node.degree == 4).unwrap();
+        assert!((junction.position[0] - 32.0).abs() < 2.0);
+        assert!((junction.position[1] - 24.0).abs() < 2.0);
+
+        let total = graph.edges.iter().map(|edge| edge.length).sum::<f32>();
+        assert!((76.0..=92.0).contains(&total), "{}", total);
+    }
+
+    #[test]
+    fn ring_is_a_loop() {
+        let field = field(|x, y| {
+            let radius = ((x - 32.0).powi(2) + (y - 24.0).powi(2)).sqrt();
+            (13.0..=17.0).contains(&radius).then_some(2.0)
+        });
+        let graph = extract_graph(&field, WIDTH, HEIGHT, None);
+
+        assert_eq!(graph.edges.len(), 1);
+        let edge = &graph.edges[0];
+        assert_eq!(edge.source, edge.target);
+
+        let circumference = 2.0 * std::f32::consts::PI * 15.0;
+        assert!(
+            (edge.length - circumference).abs() < circumference * 0.15,
+            "{}",
+            edge.length
+        );
+    }
+
+    #[test]
+    fn otsu_splits_two_levels() {
+        let field = field(|x, _| (x > 40.0).then_some(5.0));
+        let threshold = otsu_threshold(&field);
+        assert!((0.0..5.0).contains(&threshold), "{}", threshold);
+
+        assert_eq!(otsu_threshold(&[0.0; 16]), 0.0);
+        let empty = extract_graph(&[0.0; 16], 4, 4, None);
+        assert!(empty.nodes.is_empty() && empty.edges.is_empty());
+    }
+
+    #[test]
+    fn exports_every_node_and_edge() {
+        let field = field(|x, y| bar(x, y, [8.0, 20.0], [56.0, 20.0], 2.0).then_some(1.0));
+        let graph = extract_graph(&field, WIDTH, HEIGHT, Some(0.5));
+
+        let graphml = graph_to_graphml(&graph);
+        assert_eq!(graphml.matches("<node ").count(), graph.nodes.len());
+        assert_eq!(graphml.matches("<edge ").count(), graph.edges.len());
+
+        let geojson = graph_to_geojson(&graph);
+        assert_eq!(geojson.matches("\"Point\"").count(), graph.nodes.len());
+        assert_eq!(geojson.matches("\"LineString\"").count(), graph.edges.len());
+        // y is flipped, the line runs along y = 48 - 20
+        assert!(geojson.contains(",28.5]"));
+    }
+
+    fn point(name: &str, position: [f32; 2]) -> PointOfInterest {
+        PointOfInterest {
+            name: name.to_string(),
+            position,
+            weight: 1.0,
+        }
+    }
+
+    #[test]
+    fn points_sharing_a_node_are_merged() {
+        let field = field(|x, y| bar(x, y, [8.0, 20.0], [56.0, 20.0], 2.0).then_some(1.0));
+        let mut graph = extract_graph(&field, WIDTH, HEIGHT, Some(0.5));
+
+        let points = [
+            point("Ueno", [6.0, 20.0]),
+            point("Okachimachi", [9.0, 21.0]),
+            point("Akihabara", [57.0, 20.0]),
+            point("Haneda", [32.0, 46.0]),
+        ];
+        let unplaced = label_graph(&mut graph, &points, 5.0);
+        assert_eq!(unplaced, ["Haneda"]);
+
+        let mut names = graph
+            .nodes
+            .iter()
+            .filter_map(|node| node.name.clone())
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, ["Akihabara", "Ueno/Okachimachi"]);
+    }
+
+    // Arms from a junction at (32, 20) up to the top and down to either
+    // bottom corner, with the end of each arm named after its direction
+    fn y_graph() -> Graph {
+        let field = field(|x, y| {
+            (bar(x, y, [32.0, 4.0], [32.0, 20.0], 2.5)
+                || bar(x, y, [32.0, 20.0], [10.0, 42.0], 2.5)
+                || bar(x, y, [32.0, 20.0], [54.0, 42.0], 2.5))
+            .then_some(1.0)
+        });
+        let mut graph = extract_graph(&field, WIDTH, HEIGHT, Some(0.5));
+        assert_eq!(degrees(&graph), vec![1, 1, 1, 3]);
+
+        let ends = [
+            point("North", [32.0, 4.0]),
+            point("West", [10.0, 42.0]),
+            point("East", [54.0, 42.0]),
+        ];
+        assert!(label_graph(&mut graph, &ends, 6.0).is_empty());
+        graph
+    }
+
+    fn link_names(links: &[NamedLink]) -> Vec<(String, String)> {
+        let mut names = links
+            .iter()
+            .map(|link| {
+                let mut pair = [link.source.clone(), link.target.clone()];
+                pair.sort();
+                let [first, second] = pair;
+                (first, second)
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn named_links_cross_unnamed_junctions() {
+        let links = named_links(&y_graph());
+        assert_eq!(
+            link_names(&links),
+            [
+                ("East".to_string(), "North".to_string()),
+                ("East".to_string(), "West".to_string()),
+                ("North".to_string(), "West".to_string()),
+            ]
+        );
+
+        // The diagonal arms are about 31 long, the upright one 16
+        let east_west = links
+            .iter()
+            .find(|link| link.source != "North" && link.target != "North")
+            .unwrap();
+        assert!(
+            (55.0..=70.0).contains(&east_west.length),
+            "{}",
+            east_west.length
+        );
+    }
+
+    #[test]
+    fn named_links_stop_at_named_nodes() {
+        let mut graph = y_graph();
+        let hub = graph
+            .nodes
+            .iter_mut()
+            .find(|node| node.degree == 3)
+            .unwrap();
+        hub.name = Some("Hub".to_string());
+
+        assert_eq!(
+            link_names(&named_links(&graph)),
+            [
+                ("East".to_string(), "Hub".to_string()),
+                ("Hub".to_string(), "North".to_string()),
+                ("Hub".to_string(), "West".to_string()),
+            ]
+        );
+    }
+}
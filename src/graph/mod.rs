@@ -0,0 +1 @@
+pub(crate) mod graph_functions;
@@ -0,0 +1,261 @@
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::{
+    params::param_functions::{
+        group_params, param_default, param_target, redo_params, set_param, set_params, undo_params,
+        PARAMS,
+    },
+    state::app_state::State,
+    updates::update_functions::update_palette_params_buffer,
+    AgentModel, BoundaryMode, PaletteMode, ParamGroup, ParamScale,
+};
+
+// Slider ranges only bound the drag, typing a value outside them is allowed
+fn param_slider(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut f32,
+    range: RangeInclusive<f32>,
+    logarithmic: bool,
+) -> bool {
+    ui.label(label);
+    let changed = ui
+        .add(
+            egui::Slider::new(value, range)
+                .logarithmic(logarithmic)
+                .clamp_to_range(false)
+                .max_decimals(8),
+        )
+        .changed();
+    ui.end_row();
+    changed
+}
+
+fn simulation_section(ui: &mut egui::Ui, state: &mut State) {
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut state.paused, "Paused");
+        if ui
+            .add_enabled(state.paused, egui::Button::new("Step"))
+            .clicked()
+        {
+            state.single_step = true;
+        }
+    });
+
+    ui.horizontal(|ui| {
+        if ui.button("Re-seed").clicked() {
+            state.reseed_slime(None);
+        }
+        if ui.button("New seed").clicked() {
+            let seed = state.next_seed();
+            state.reseed_slime(Some(seed));
+        }
+        if ui.button("Clear field").clicked() {
+            state.clear_pheremones();
+        }
+        if ui.button("Reset").clicked() {
+            state.reset(None);
+        }
+    });
+
+    let mut boundary_mode = state.boundary_mode();
+    egui::ComboBox::from_label("Boundary")
+        .selected_text(format!("{:?}", boundary_mode))
+        .show_ui(ui, |ui| {
+            for mode in BoundaryMode::ALL {
+                ui.selectable_value(&mut boundary_mode, mode, format!("{:?}", mode));
+            }
+        });
+    if boundary_mode != state.boundary_mode() {
+        state.set_boundary_mode(boundary_mode);
+    }
+
+    let mut agent_model = state.agent_model();
+    egui::ComboBox::from_label("Agent model")
+        .selected_text(format!("{:?}", agent_model))
+        .show_ui(ui, |ui| {
+            for model in AgentModel::ALL {
+                ui.selectable_value(&mut agent_model, model, format!("{:?}", model));
+            }
+        });
+    if agent_model != state.agent_model() {
+        state.set_agent_model(agent_model);
+    }
+}
+
+fn preset_section(ui: &mut egui::Ui, state: &mut State) {
+    ui.horizontal(|ui| {
+        ui.label("Path");
+        ui.text_edit_singleline(&mut state.ui.preset_path);
+    });
+
+    ui.horizontal(|ui| {
+        let path = state.ui.preset_path.clone();
+
+        if ui.button("Save").clicked() {
+            if let Err(e) = state.save_preset(Path::new(&path)) {
+                eprintln!("Error saving preset: {}", e);
+            }
+        }
+        if ui.button("Load").clicked() {
+            if let Err(e) = state.load_preset(Path::new(&path)) {
+                eprintln!("Error loading preset: {}", e);
+            }
+        }
+        if ui.button("Defaults").clicked() {
+            let defaults = PARAMS
+                .iter()
+                .map(|info| (info, param_default(info)))
+                .collect::<Vec<_>>();
+            set_params(state, &defaults);
+        }
+        if ui
+            .add_enabled(state.saved_preset.is_some(), egui::Button::new("Revert"))
+            .clicked()
+        {
+            if let Err(e) = state.revert_preset() {
+                eprintln!("Error reverting preset: {}", e);
+            }
+        }
+    });
+
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(state.history.can_undo(), egui::Button::new("Undo"))
+            .clicked()
+        {
+            undo_params(state);
+        }
+        if ui
+            .add_enabled(state.history.can_redo(), egui::Button::new("Redo"))
+            .clicked()
+        {
+            redo_params(state);
+        }
+    });
+
+    egui::Grid::new("smoothing").num_columns(2).show(ui, |ui| {
+        param_slider(
+            ui,
+            "smoothing (s)",
+            &mut state.smoother.time,
+            0.0..=10.0,
+            false,
+        );
+    });
+}
+
+fn palette_section(ui: &mut egui::Ui, state: &mut State) {
+    let mut palette_mode = state.palette_mode();
+    egui::ComboBox::from_label("Palette")
+        .selected_text(format!("{:?}", palette_mode))
+        .show_ui(ui, |ui| {
+            for mode in PaletteMode::ALL {
+                ui.selectable_value(&mut palette_mode, mode, format!("{:?}", mode));
+            }
+        });
+    if palette_mode != state.palette_mode() {
+        state.set_palette_mode(palette_mode);
+    }
+
+    egui::Grid::new("palette_params")
+        .num_columns(2)
+        .show(ui, |ui| {
+            let palette = &mut state.params.palette_params;
+            if param_slider(ui, "scale", &mut palette.scale, 0.01..=10.0, true) {
+                update_palette_params_buffer(state);
+            }
+        });
+
+    ui.horizontal(|ui| {
+        ui.label("LUT");
+        ui.text_edit_singleline(&mut state.ui.lut_path);
+        if ui.button("Load").clicked() {
+            let path = state.ui.lut_path.clone();
+            match state.load_palette_lut(Path::new(&path)) {
+                Ok(()) => state.set_palette_mode(PaletteMode::Lut),
+                Err(e) => eprintln!("Error loading palette LUT: {}", e),
+            }
+        }
+    });
+}
+
+/// One slider per registered parameter in `group`, right click resets to the
+/// default. Modulated parameters are shown but can't be dragged.
+fn param_group_section(ui: &mut egui::Ui, state: &mut State, group: ParamGroup) {
+    egui::Grid::new(group.name()).num_columns(2).show(ui, |ui| {
+        for info in group_params(group) {
+            let mut value = param_target(state, info);
+            let modulated = state
+                .modulations
+                .iter()
+                .any(|active| std::ptr::eq(active.param, info));
+
+            ui.label(info.label).on_hover_text(info.description);
+            let response = ui
+                .add_enabled(
+                    !modulated,
+                    egui::Slider::new(&mut value, info.min..=info.max)
+                        .logarithmic(info.scale == ParamScale::Log)
+                        .max_decimals(8),
+                )
+                .on_disabled_hover_text("Driven by a modulation in the preset");
+            ui.end_row();
+
+            if response.secondary_clicked() {
+                set_param(state, info, param_default(info));
+            } else if response.changed() {
+                set_param(state, info, value);
+            }
+        }
+    });
+}
+
+fn view_section(ui: &mut egui::Ui, state: &mut State) {
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut state.show_agents, "Agents");
+        ui.checkbox(&mut state.show_sensors, "Sensors");
+    });
+
+    param_group_section(ui, state, ParamGroup::View);
+}
+
+pub(crate) fn draw_gui(ctx: &egui::Context, state: &mut State) {
+    egui::Window::new("Parameters")
+        .default_pos(egui::pos2(ctx.screen_rect().right() - 360.0, 8.0))
+        .default_width(340.0)
+        .vscroll(true)
+        .show(ctx, |ui| {
+            egui::CollapsingHeader::new("Simulation")
+                .default_open(true)
+                .show(ui, |ui| simulation_section(ui, state));
+            egui::CollapsingHeader::new("Presets")
+                .default_open(true)
+                .show(ui, |ui| preset_section(ui, state));
+            egui::CollapsingHeader::new("Palette")
+                .default_open(true)
+                .show(ui, |ui| palette_section(ui, state));
+
+            match state.agent_model() {
+                AgentModel::Velocity => egui::CollapsingHeader::new("Slime")
+                    .default_open(true)
+                    .show(ui, |ui| param_group_section(ui, state, ParamGroup::Slime)),
+                AgentModel::Jones => egui::CollapsingHeader::new("Jones")
+                    .default_open(true)
+                    .show(ui, |ui| param_group_section(ui, state, ParamGroup::Jones)),
+            };
+
+            egui::CollapsingHeader::new("Pheremones")
+                .default_open(true)
+                .show(ui, |ui| {
+                    param_group_section(ui, state, ParamGroup::Pheremone)
+                });
+            egui::CollapsingHeader::new("View")
+                .default_open(true)
+                .show(ui, |ui| view_section(ui, state));
+            egui::CollapsingHeader::new("Post")
+                .default_open(false)
+                .show(ui, |ui| param_group_section(ui, state, ParamGroup::Post));
+        });
+}
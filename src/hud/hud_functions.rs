@@ -0,0 +1,265 @@
+use crate::{
+    debug::debug_functions::DEBUG_CHANNELS,
+    params::param_functions::{format_param, group_params},
+    state::{action_state::Action, app_state::State, control_state::KeyboardMode},
+    updates::update_functions::update_stats_params_buffer,
+    AgentModel, ParamGroup, SimStats,
+};
+
+const SPARKLINE_SIZE: egui::Vec2 = egui::vec2(180.0, 24.0);
+
+type StatField = fn(&SimStats) -> f32;
+
+// Label, value and plot for each row of the statistics window
+const STATS_ROWS: [(&str, StatField); 7] = [
+    ("total mass", |stats| stats.total_mass),
+    ("max mass", |stats| stats.max_mass),
+    ("coverage", |stats| stats.coverage),
+    ("mean speed", |stats| stats.mean_speed),
+    ("speed variance", |stats| stats.speed_variance),
+    ("heading entropy", |stats| stats.heading_entropy),
+    ("moved forward", |stats| stats.forward_fraction),
+];
+
+/// Keys for the current mode's actions followed by the global ones
+pub(crate) fn mode_bindings(state: &State) -> Vec<(String, String)> {
+    let mode = *state.controls.get_mode();
+    let agent_model = state.agent_model();
+    let bindings = state.controls.bindings();
+
+    let in_mode = Action::ALL.iter().filter(|action| {
+        action.mode() == Some(mode)
+            && action
+                .agent_model()
+                .is_none_or(|model| model == agent_model)
+    });
+    let global = Action::ALL.iter().filter(|action| action.mode().is_none());
+
+    in_mode
+        .chain(global)
+        .filter(|action| !bindings.get(**action).is_empty())
+        .map(|action| {
+            let keys = bindings
+                .get(*action)
+                .iter()
+                .map(|binding| binding.to_string())
+                .collect::<Vec<String>>()
+                .join(" / ");
+            (keys, action.description().to_string())
+        })
+        .collect()
+}
+
+fn group_values(state: &State, group: ParamGroup) -> Vec<(String, String)> {
+    group_params(group)
+        .map(|info| {
+            (
+                info.label.to_string(),
+                format_param(info, (info.get)(&state.params)),
+            )
+        })
+        .collect()
+}
+
+/// Live values of what the current mode edits
+pub(crate) fn mode_values(state: &State) -> Vec<(String, String)> {
+    let params = &state.params;
+
+    match *state.controls.get_mode() {
+        KeyboardMode::SLIME => match state.agent_model() {
+            AgentModel::Velocity => group_values(state, ParamGroup::Slime),
+            AgentModel::Jones => group_values(state, ParamGroup::Jones),
+        },
+        KeyboardMode::PHEREMONES => group_values(state, ParamGroup::Pheremone),
+        KeyboardMode::VIEW => [
+            ("palette".to_string(), format!("{:?}", state.palette_mode())),
+            (
+                "palette scale".to_string(),
+                format!("{:.3}", params.palette_params.scale),
+            ),
+            (
+                "agents / sensors".to_string(),
+                format!("{} / {}", state.show_agents, state.show_sensors),
+            ),
+        ]
+        .into_iter()
+        .chain(group_values(state, ParamGroup::View))
+        .collect(),
+        KeyboardMode::POST => {
+            let post = &params.post_params;
+            [
+                ("tonemap".to_string(), format!("{:?}", state.tonemap_mode())),
+                (
+                    "auto exposure".to_string(),
+                    format!("{}", post.auto_exposure != 0),
+                ),
+                ("bloom".to_string(), format!("{}", post.bloom_enabled != 0)),
+            ]
+            .into_iter()
+            .chain(group_values(state, ParamGroup::Post))
+            .collect()
+        }
+        KeyboardMode::DEBUG => {
+            let channel = &DEBUG_CHANNELS[state.debug_channel];
+            let overlay = &params.overlay_params;
+            vec![
+                (
+                    "channel".to_string(),
+                    format!(
+                        "{}.{}",
+                        channel.name, channel.labels[overlay.debug_component as usize]
+                    ),
+                ),
+                ("view".to_string(), format!("{:?}", state.debug_view())),
+                (
+                    "range".to_string(),
+                    format!(
+                        "{:.4} / {:.4}",
+                        overlay.debug_range[0], overlay.debug_range[1]
+                    ),
+                ),
+            ]
+        }
+        KeyboardMode::PRINT => vec![],
+    }
+}
+
+fn key_value_grid<K: AsRef<str>>(ui: &mut egui::Ui, id: &str, rows: &[(K, String)]) {
+    egui::Grid::new(id).num_columns(2).show(ui, |ui| {
+        for (key, value) in rows {
+            ui.label(key.as_ref());
+            ui.label(value);
+            ui.end_row();
+        }
+    });
+}
+
+pub(crate) fn draw_hud(ctx: &egui::Context, state: &State) {
+    let mode = *state.controls.get_mode();
+
+    egui::Area::new(egui::Id::new("hud"))
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(8.0, 8.0))
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style())
+                .fill(egui::Color32::from_black_alpha(180))
+                .show(ui, |ui| {
+                    ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+
+                    ui.heading(format!("{:?}", mode));
+                    key_value_grid(
+                        ui,
+                        "hud_stats",
+                        &[
+                            (
+                                "fps",
+                                format!(
+                                    "{:.0} ({:.2} ms)",
+                                    state.ui.fps(),
+                                    state.ui.frame_time_ms()
+                                ),
+                            ),
+                            ("agents", format!("{}", state.capabilities.agent_count)),
+                            ("step", format!("{}", state.step)),
+                            ("boundary", format!("{:?}", state.boundary_mode())),
+                            ("model", format!("{:?}", state.agent_model())),
+                        ],
+                    );
+
+                    let values = mode_values(state);
+                    if !values.is_empty() {
+                        ui.separator();
+                        key_value_grid(ui, "hud_values", &values);
+                    }
+
+                    let timings = state.profiler.averages();
+                    if state.profiler.enabled() && !timings.is_empty() {
+                        ui.separator();
+                        let rows = timings
+                            .iter()
+                            .map(|(name, ms)| (*name, format!("{:.3} ms", ms)))
+                            .collect::<Vec<_>>();
+                        key_value_grid(ui, "hud_timings", &rows);
+                    }
+
+                    ui.separator();
+                    key_value_grid(ui, "hud_bindings", &mode_bindings(state));
+                });
+        });
+}
+
+/// Line through `values` scaled to fill the plot, oldest on the left
+fn sparkline(ui: &mut egui::Ui, values: &[f32]) {
+    let (rect, _) = ui.allocate_exact_size(SPARKLINE_SIZE, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(120));
+
+    if values.len() < 2 {
+        return;
+    }
+
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = if max > min { max - min } else { 1.0 };
+
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let x = rect.left() + rect.width() * i as f32 / (values.len() - 1) as f32;
+            let y = rect.bottom() - rect.height() * (value - min) / range;
+            egui::pos2(x, y)
+        })
+        .collect::<Vec<_>>();
+
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.0, ui.visuals().strong_text_color()),
+    ));
+}
+
+pub(crate) fn draw_stats(ctx: &egui::Context, state: &mut State) {
+    egui::Window::new("Statistics")
+        .default_pos(egui::pos2(8.0, ctx.screen_rect().bottom() - 260.0))
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("every");
+                ui.add(egui::DragValue::new(&mut state.stats.interval).clamp_range(0..=10_000));
+                ui.label("steps");
+            });
+
+            let threshold = &mut state.params.stats_params.coverage_threshold;
+            let response = ui.add(
+                egui::Slider::new(threshold, 0.0..=10.0)
+                    .logarithmic(true)
+                    .text("coverage threshold"),
+            );
+            if response.changed() {
+                update_stats_params_buffer(state);
+            }
+
+            let history = state.stats.history();
+            let Some((step, latest)) = history.back() else {
+                ui.label("No samples yet");
+                return;
+            };
+            ui.label(format!("step {}", step));
+
+            egui::Grid::new("stats_plots")
+                .num_columns(3)
+                .show(ui, |ui| {
+                    for (label, value) in STATS_ROWS {
+                        let values = history
+                            .iter()
+                            .map(|(_, stats)| value(stats))
+                            .collect::<Vec<f32>>();
+
+                        ui.label(label);
+                        ui.monospace(format!("{:.4}", value(latest)));
+                        sparkline(ui, &values);
+                        ui.end_row();
+                    }
+                });
+        });
+}
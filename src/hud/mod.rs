@@ -0,0 +1 @@
+pub(crate) mod hud_functions;
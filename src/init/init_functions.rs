@@ -1,12 +1,224 @@
 use wgpu::util::DeviceExt;
 
 use crate::{
-    vertices_as_bytes, BindGroups, Buffers, ConstUniforms, DebugBuffer, Params, PheremoneParams,
-    Pipelines, ShaderModules, Slime, SlimeParams, Textures, TimeUniform, ViewParams, NUM_AGENTS,
-    SCREEN_HEIGHT, SCREEN_WIDTH, TEXTURE_BUF_SIZE, VERTICES,
+    debug::debug_functions::{debug_slots, debug_wgsl_constants},
+    palettes::palette_functions::default_palette_lut,
+    vertices_as_bytes, AgentModel, Args, BackendArg, BindGroups, BoundaryMode, Buffers,
+    Capabilities, ConstUniforms, DebugView, ExposureState, FieldPartial, JonesParams,
+    OverlayParams, PaletteMode, PaletteParams, Params, PheremoneParams, Pipelines, PostBindGroups,
+    PostParams, PostTextures, ShaderModules, SimParams, SimStats, Slime, SlimeParams, SourceTexel,
+    StatsParams, Textures, TimeUniform, TonemapMode, ViewParams, AGENT_WORKGROUP_SIZE,
+    DEFAULT_NUM_AGENTS, DEFAULT_SEED, EXPOSURE_HISTOGRAM_BINS, HDR_FORMAT, PALETTE_LUT_SIZE,
+    SCREEN_HEIGHT, SCREEN_WIDTH, STATS_TILE_SIZE, TEXTURE_BUF_SIZE, VERTICES,
 };
 
-pub(crate) fn init_shader_modules(device: &wgpu::Device) -> ShaderModules {
+pub(crate) fn init_instance(args: &Args) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: args
+            .backend
+            .map_or(wgpu::Backends::all(), BackendArg::backends),
+        ..Default::default()
+    })
+}
+
+pub(crate) fn list_adapters(instance: &wgpu::Instance) {
+    for adapter in instance.enumerate_adapters(wgpu::Backends::all()) {
+        let info = adapter.get_info();
+        println!(
+            "{:?}: {} ({:?}, {} {})",
+            info.backend, info.name, info.device_type, info.driver, info.driver_info
+        );
+    }
+}
+
+// An adapter named on the command line wins, otherwise wgpu picks one. Headless
+// runs have no surface to be compatible with.
+pub(crate) async fn init_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface<'_>>,
+    args: &Args,
+) -> Option<wgpu::Adapter> {
+    if let Some(name) = &args.adapter {
+        let name = name.to_lowercase();
+        return instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .into_iter()
+            .filter(|adapter| surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+            .find(|adapter| adapter.get_info().name.to_lowercase().contains(&name));
+    }
+
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: args.fallback_adapter,
+            compatible_surface: surface,
+        })
+        .await
+}
+
+pub(crate) fn init_capabilities(adapter: &wgpu::Adapter, args: &Args) -> Capabilities {
+    let features = adapter.features();
+    let rgba32_flags = adapter
+        .get_texture_format_features(wgpu::TextureFormat::Rgba32Float)
+        .flags;
+
+    // GLES only allows r32 formats to be read-write, whatever the adapter
+    // reports for Rgba32Float
+    let read_write_storage = !args.compat
+        && features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        && rgba32_flags.contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+        && adapter.get_info().backend != wgpu::Backend::Gl;
+
+    let float32_filterable = !args.compat && features.contains(wgpu::Features::FLOAT32_FILTERABLE);
+
+    let reason = |unavailable: &str| {
+        if args.compat {
+            "forced by --compat".to_string()
+        } else {
+            format!("{} is unavailable", unavailable)
+        }
+    };
+
+    if read_write_storage {
+        println!("Pheremone field: read-write storage texture");
+    } else {
+        println!(
+            "Pheremone field: ping-pong textures, {}",
+            reason("read-write storage")
+        );
+    }
+
+    if float32_filterable {
+        println!("Field sampling: hardware filtering");
+    } else {
+        println!(
+            "Field sampling: manual bilinear, {}",
+            reason("FLOAT32_FILTERABLE")
+        );
+    }
+
+    let limits = adapter.limits();
+    let agent_workgroup_size = AGENT_WORKGROUP_SIZE
+        .min(limits.max_compute_workgroup_size_x)
+        .min(limits.max_compute_invocations_per_workgroup)
+        .max(1);
+    let mut capabilities = Capabilities {
+        read_write_storage,
+        float32_filterable,
+        agent_workgroup_size,
+        agent_count: args.agents,
+    };
+    if let Err(e) = check_agent_count(capabilities, &limits) {
+        eprintln!("{}, running {} instead", e, DEFAULT_NUM_AGENTS);
+        capabilities.agent_count = DEFAULT_NUM_AGENTS as u32;
+    }
+    println!(
+        "Agent kernels: {} agents in {} workgroups of {}",
+        capabilities.agent_count,
+        capabilities.agent_workgroups(),
+        agent_workgroup_size
+    );
+
+    capabilities
+}
+
+/// Whether the agent buffers and dispatch fit in the adapter's limits
+pub(crate) fn check_agent_count(
+    capabilities: Capabilities,
+    limits: &wgpu::Limits,
+) -> Result<(), String> {
+    let agents = capabilities.agents();
+    let largest = (agents * std::mem::size_of::<Slime>())
+        .max(debug_slots(agents) * std::mem::size_of::<[f32; 4]>());
+
+    if capabilities.agent_workgroups() > limits.max_compute_workgroups_per_dimension {
+        Err(format!(
+            "{} agents need more than {} workgroups",
+            agents, limits.max_compute_workgroups_per_dimension
+        ))
+    } else if largest > limits.max_storage_buffer_binding_size as usize {
+        Err(format!(
+            "{} agents need a {} byte buffer, the adapter binds at most {}",
+            agents, largest, limits.max_storage_buffer_binding_size
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn init_surface_config(
+    surface: &wgpu::Surface<'_>,
+    adapter: &wgpu::Adapter,
+    size: winit::dpi::PhysicalSize<u32>,
+    present_mode: wgpu::PresentMode,
+) -> wgpu::SurfaceConfiguration {
+    let surface_caps = surface.get_capabilities(adapter);
+
+    // The tonemap pass writes linear values and relies on the target to encode
+    let format = surface_caps
+        .formats
+        .iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .unwrap_or(surface_caps.formats[0]);
+
+    // The Auto modes are always available, the others depend on the platform
+    let present_mode = match present_mode {
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => present_mode,
+        mode if surface_caps.present_modes.contains(&mode) => mode,
+        mode => {
+            eprintln!(
+                "Present mode {:?} is not supported, falling back to vsync. Supported: {:?}",
+                mode, surface_caps.present_modes
+            );
+            wgpu::PresentMode::AutoVsync
+        }
+    };
+
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: size.width,
+        height: size.height,
+        present_mode,
+        desired_maximum_frame_latency: 1,
+        view_formats: vec![],
+        alpha_mode: surface_caps.alpha_modes[0],
+    }
+}
+
+// Nothing is presented headless, the render pipelines still need a target format
+pub(crate) fn init_headless_config(
+    size: winit::dpi::PhysicalSize<u32>,
+) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width: size.width,
+        height: size.height,
+        present_mode: wgpu::PresentMode::AutoVsync,
+        desired_maximum_frame_latency: 1,
+        view_formats: vec![],
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+    }
+}
+
+pub(crate) fn init_shader_modules(
+    device: &wgpu::Device,
+    capabilities: Capabilities,
+) -> ShaderModules {
+    let phm_access = if capabilities.read_write_storage {
+        include_str!("../shaders/common/phm_read_write.wgsl")
+    } else {
+        include_str!("../shaders/common/phm_ping_pong.wgsl")
+    };
+
+    let field_sampling = if capabilities.float32_filterable {
+        include_str!("../shaders/common/field_filtered.wgsl")
+    } else {
+        include_str!("../shaders/common/field_manual.wgsl")
+    };
+
     let vdesc = wgpu::ShaderModuleDescriptor {
         label: Some("Vertex Shader"),
         source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/vertex/v2.wgsl").into()),
@@ -16,21 +228,53 @@ pub(crate) fn init_shader_modules(device: &wgpu::Device) -> ShaderModules {
     let fdesc = wgpu::ShaderModuleDescriptor {
         label: Some("Fragment Shader"),
         source: wgpu::ShaderSource::Wgsl(
-            include_str!("../shaders/fragment/slime_frag.wgsl").into(),
+            [
+                field_sampling,
+                include_str!("../shaders/fragment/slime_frag.wgsl"),
+            ]
+            .concat()
+            .into(),
         ),
     };
     let f_shader = device.create_shader_module(fdesc);
 
+    // The agent count, and the agent kernels' @workgroup_size picked from
+    // the adapter's limits
+    let agent_workgroup = format!(
+        "const NUM_AGENTS: u32 = {}u;\nconst AGENT_WORKGROUP_SIZE: u32 = {}u;\n",
+        capabilities.agent_count, capabilities.agent_workgroup_size
+    );
+
+    let debug_constants = debug_wgsl_constants(capabilities.agents());
+
     let init_slime_desc = wgpu::ShaderModuleDescriptor {
         label: Some("Initial Slime Position Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/compute/init_slime.wgsl").into()),
+        source: wgpu::ShaderSource::Wgsl(
+            [
+                &agent_workgroup,
+                include_str!("../shaders/common/rng.wgsl"),
+                include_str!("../shaders/compute/init_slime.wgsl"),
+            ]
+            .concat()
+            .into(),
+        ),
     };
     let init_slime_shader = device.create_shader_module(init_slime_desc);
 
     let update_slime_desc = wgpu::ShaderModuleDescriptor {
         label: Some("Update Slime Movement Shader"),
         source: wgpu::ShaderSource::Wgsl(
-            include_str!("../shaders/compute/slime_movement.wgsl").into(),
+            [
+                &agent_workgroup,
+                include_str!("../shaders/common/rng.wgsl"),
+                include_str!("../shaders/common/boundary.wgsl"),
+                &debug_constants,
+                include_str!("../shaders/common/debug.wgsl"),
+                phm_access,
+                include_str!("../shaders/compute/slime_movement.wgsl"),
+            ]
+            .concat()
+            .into(),
         ),
     };
     let update_slime_shader = device.create_shader_module(update_slime_desc);
@@ -38,17 +282,82 @@ pub(crate) fn init_shader_modules(device: &wgpu::Device) -> ShaderModules {
     let update_phm_desc = wgpu::ShaderModuleDescriptor {
         label: Some("Update Pheremone HeatMap Shader"),
         source: wgpu::ShaderSource::Wgsl(
-            include_str!("../shaders/compute/update_pheremone_texture.wgsl").into(),
+            [
+                include_str!("../shaders/common/boundary.wgsl"),
+                &debug_constants,
+                include_str!("../shaders/common/debug.wgsl"),
+                phm_access,
+                include_str!("../shaders/compute/update_pheremone_texture.wgsl"),
+            ]
+            .concat()
+            .into(),
         ),
     };
     let update_phm_shader = device.create_shader_module(update_phm_desc);
 
+    let agent_overlay_desc = wgpu::ShaderModuleDescriptor {
+        label: Some("Agent Overlay Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/overlay/agents.wgsl").into()),
+    };
+    let agent_overlay_shader = device.create_shader_module(agent_overlay_desc);
+
+    let exposure_desc = wgpu::ShaderModuleDescriptor {
+        label: Some("Auto Exposure Shader"),
+        source: wgpu::ShaderSource::Wgsl(
+            concat!(
+                include_str!("../shaders/post/common.wgsl"),
+                include_str!("../shaders/post/exposure.wgsl"),
+            )
+            .into(),
+        ),
+    };
+    let exposure_shader = device.create_shader_module(exposure_desc);
+
+    let bloom_desc = wgpu::ShaderModuleDescriptor {
+        label: Some("Bloom Shader"),
+        source: wgpu::ShaderSource::Wgsl(
+            concat!(
+                include_str!("../shaders/post/common.wgsl"),
+                include_str!("../shaders/post/bloom.wgsl"),
+            )
+            .into(),
+        ),
+    };
+    let bloom_shader = device.create_shader_module(bloom_desc);
+
+    let tonemap_desc = wgpu::ShaderModuleDescriptor {
+        label: Some("Tonemap Shader"),
+        source: wgpu::ShaderSource::Wgsl(
+            concat!(
+                include_str!("../shaders/post/common.wgsl"),
+                include_str!("../shaders/post/tonemap.wgsl"),
+            )
+            .into(),
+        ),
+    };
+    let tonemap_shader = device.create_shader_module(tonemap_desc);
+
+    let stats_desc = wgpu::ShaderModuleDescriptor {
+        label: Some("Simulation Stats Shader"),
+        source: wgpu::ShaderSource::Wgsl(
+            [phm_access, include_str!("../shaders/compute/stats.wgsl")]
+                .concat()
+                .into(),
+        ),
+    };
+    let stats_shader = device.create_shader_module(stats_desc);
+
     ShaderModules {
         v_shader,
         f_shader,
         init_slime_shader,
         update_slime_shader,
         update_phm_shader,
+        agent_overlay_shader,
+        exposure_shader,
+        bloom_shader,
+        tonemap_shader,
+        stats_shader,
     }
 }
 
@@ -67,7 +376,7 @@ pub(crate) fn init_params() -> Params {
         turn_factor: 9e-7f32,
         avoid_factor: 0.05,
         sensor_dist: 0.015,
-        sensor_offset: 1.0472, // 60degrees in Radians
+        sensor_offset: std::f32::consts::FRAC_PI_3, // 60degrees in Radians
         sensor_radius: 0.01,
     };
 
@@ -77,14 +386,79 @@ pub(crate) fn init_params() -> Params {
         decay_factor: 0.985,
     };
 
+    let sim_params = SimParams {
+        seed: DEFAULT_SEED,
+        boundary_mode: BoundaryMode::Wrap as u32,
+        agent_model: AgentModel::Velocity as u32,
+    };
+
+    let jones_params = JonesParams {
+        sensor_angle: std::f32::consts::FRAC_PI_4,
+        rotation_angle: std::f32::consts::FRAC_PI_4,
+        sensor_offset: 9.0,
+        sensor_width: 1.0,
+        step_size: 1.0,
+    };
+
+    let palette_params = PaletteParams {
+        a: [0.120, 0.618, 0.624, 0.0],
+        b: [0.878, 0.214, 0.229, 0.0],
+        c: [0.654, 0.772, 0.426, 0.0],
+        d: [0.937, 0.190, 0.152, 0.0],
+        mode: PaletteMode::Red as u32,
+        scale: 0.9,
+        _pad: [0; 2],
+    };
+
+    let overlay_params = OverlayParams {
+        target_size: [SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32],
+        agent_size: 1.5,
+        min_sensor_size: 1.5,
+        debug_view: DebugView::Off as u32,
+        debug_slot: 0,
+        debug_component: 0,
+        debug_stride: 1,
+        debug_range: [0.0, 1.0],
+        heatmap_size: 12.0,
+        _pad: 0,
+    };
+
+    // Linear with everything else off matches the image before the HDR chain
+    let post_params = PostParams {
+        exposure: 1.0,
+        adapt_rate: 0.05,
+        percentile: 0.95,
+        white_point: 16.0,
+        auto_exposure: 0,
+        tonemap: TonemapMode::Linear as u32,
+        bloom_enabled: 0,
+        bloom_threshold: 0.8,
+        bloom_intensity: 0.6,
+    };
+
+    let stats_params = StatsParams {
+        coverage_threshold: 0.1,
+    };
+
     Params {
         view_params,
         slime_params,
         pheremone_params,
+        sim_params,
+        jones_params,
+        palette_params,
+        overlay_params,
+        post_params,
+        stats_params,
+        palette_lut_path: None,
     }
 }
 
-pub(crate) fn init_buffers(device: &wgpu::Device, params: &Params) -> Buffers {
+pub(crate) fn init_buffers(
+    device: &wgpu::Device,
+    params: &Params,
+    capabilities: Capabilities,
+) -> Buffers {
     let vertices_bytes = vertices_as_bytes(&VERTICES[..]);
     let vertex_buf = wgpu::util::DeviceExt::create_buffer_init(
         device,
@@ -161,77 +535,173 @@ pub(crate) fn init_buffers(device: &wgpu::Device, params: &Params) -> Buffers {
         },
     );
 
-    // STORAGE/CPU-READABLE BUFFER PAIRS
-    let slime_pos_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Slimes Positions Buffer"),
-        size: (std::mem::size_of::<[Slime; NUM_AGENTS]>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_SRC
-            | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+    let sim_params_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Simulation Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.sim_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
 
-    let cpu_read_slime_pos_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("CPU Readable Buffer - Slimes"),
-        size: (std::mem::size_of::<[Slime; NUM_AGENTS]>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
+    let jones_params_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Jones Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.jones_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
 
-    let generic_debug_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Debug Shaders Buffer"),
-        size: (std::mem::size_of::<DebugBuffer>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_SRC
-            | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+    let palette_params_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Palette Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.palette_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
 
-    let cpu_read_generic_debug_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("CPU Readable Buffer - Debug Shaders"),
-        size: (std::mem::size_of::<DebugBuffer>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+    let overlay_params_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.overlay_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let post_params_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Post Processing Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.post_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let exposure_state_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Exposure State Storage Buffer"),
+            contents: bytemuck::cast_slice(&[ExposureState {
+                exposure: 1.0,
+                luminance: 1.0,
+                histogram: [0; EXPOSURE_HISTOGRAM_BINS],
+            }]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let stats_params_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Stats Parameters Storage Buffer"),
+            contents: bytemuck::cast_slice(&[params.stats_params]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let stats_tiles =
+        SCREEN_WIDTH.div_ceil(STATS_TILE_SIZE) * SCREEN_HEIGHT.div_ceil(STATS_TILE_SIZE);
+    let stats_partials_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Stats Partials Storage Buffer"),
+        size: stats_tiles as wgpu::BufferAddress
+            * std::mem::size_of::<FieldPartial>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE,
         mapped_at_creation: false,
     });
 
-    let generic_debug_array_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Debug Shaders Buffer - ARRAY"),
-        size: (std::mem::size_of::<[[f32; 4]; NUM_AGENTS]>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_SRC
-            | wgpu::BufferUsages::COPY_DST,
+    let stats_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Stats Storage Buffer"),
+        size: std::mem::size_of::<SimStats>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
         mapped_at_creation: false,
     });
 
-    let cpu_read_generic_debug_array_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("CPU Readable Buffer ARRAY - Debug Shaders"),
-        size: (std::mem::size_of::<[[f32; 4]; NUM_AGENTS]>()) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        mapped_at_creation: false,
+    let phm_deposit_buf = (!capabilities.read_write_storage).then(|| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pheremone Deposit Storage Buffer"),
+            size: (SCREEN_WIDTH * SCREEN_HEIGHT) as wgpu::BufferAddress
+                * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
     });
 
+    // No food and land everywhere until sources are loaded
+    let sources_buf = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Food Sources Storage Buffer"),
+            contents: bytemuck::cast_slice(&vec![
+                SourceTexel {
+                    attractant: 0.0,
+                    land: 1.0,
+                };
+                (SCREEN_WIDTH * SCREEN_HEIGHT) as usize
+            ]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        },
+    );
+
+    let (slime_pos_buf, debug_channels_buf) = init_agent_buffers(device, capabilities);
+
     Buffers {
         vertex_buf,
         time_uniform_buf,
         const_uniform_buf,
         view_params_buf,
         slime_pos_buf,
-        cpu_read_slime_pos_buf,
-        generic_debug_buf,
-        cpu_read_generic_debug_buf,
-        generic_debug_array_buf,
-        cpu_read_generic_debug_array_buf,
+        debug_channels_buf,
         slime_params_buf,
         pheremone_params_buf,
+        sim_params_buf,
+        jones_params_buf,
+        palette_params_buf,
+        overlay_params_buf,
+        post_params_buf,
+        exposure_state_buf,
+        stats_params_buf,
+        stats_partials_buf,
+        stats_buf,
+        phm_deposit_buf,
+        sources_buf,
     }
 }
 
+/// The buffers sized by the agent count, the agents and the debug channels.
+/// Both are read back through the readback pool.
+pub(crate) fn init_agent_buffers(
+    device: &wgpu::Device,
+    capabilities: Capabilities,
+) -> (wgpu::Buffer, wgpu::Buffer) {
+    let slime_pos_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Slimes Positions Buffer"),
+        size: (std::mem::size_of::<Slime>() * capabilities.agents()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // Every debug channel, read back only when asked for
+    let debug_channels_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Debug Channels Buffer"),
+        size: (std::mem::size_of::<[f32; 4]>() * debug_slots(capabilities.agents()))
+            as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    (slime_pos_buf, debug_channels_buf)
+}
+
 pub(crate) fn init_bind_groups(
     device: &wgpu::Device,
     buffers: &Buffers,
-    texture_view: &wgpu::TextureView,
-    phm_sampler: &wgpu::Sampler,
-    phm_extent: &wgpu::Extent3d,
+    textures: &Textures,
+    capabilities: Capabilities,
 ) -> BindGroups {
     let uniform_bgl =
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -280,25 +750,59 @@ pub(crate) fn init_bind_groups(
     });
 
     let param_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Storage { read_only: false },
-                has_dynamic_offset: false,
-                min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ViewParams>() as _),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ViewParams>() as _),
+                },
+                count: None,
             },
-            count: None,
-        }],
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<PaletteParams>() as _
+                    ),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D1,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ],
         label: Some("variable_bind_group_layout"),
     });
 
     let param_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
         layout: &param_bgl,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: buffers.view_params_buf.as_entire_binding(),
-        }],
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffers.view_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers.palette_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&textures.palette_lut_view),
+            },
+        ],
         label: Some("view_params_bind_group"),
     });
 
@@ -340,25 +844,47 @@ pub(crate) fn init_bind_groups(
                     count: None,
                 },
                 wgpu::BindGroupLayoutEntry {
-                    binding: 8,
+                    binding: 3,
                     visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage { read_only: false },
                         has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<
-                            [[f32; 4]; NUM_AGENTS],
-                        >() as _),
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<SimParams>() as _
+                        ),
                     },
                     count: None,
                 },
                 wgpu::BindGroupLayoutEntry {
-                    binding: 9,
+                    binding: 4,
                     visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage { read_only: false },
                         has_dynamic_offset: false,
                         min_binding_size: wgpu::BufferSize::new(
-                            std::mem::size_of::<DebugBuffer>() as _
+                            std::mem::size_of::<JonesParams>() as _
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<[f32; 4]>() as _
                         ),
                     },
                     count: None,
@@ -383,47 +909,298 @@ pub(crate) fn init_bind_groups(
                 resource: buffers.pheremone_params_buf.as_entire_binding(),
             },
             wgpu::BindGroupEntry {
-                binding: 8,
-                resource: buffers.generic_debug_array_buf.as_entire_binding(),
+                binding: 3,
+                resource: buffers.sim_params_buf.as_entire_binding(),
             },
             wgpu::BindGroupEntry {
-                binding: 9,
-                resource: buffers.generic_debug_buf.as_entire_binding(),
+                binding: 4,
+                resource: buffers.jones_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: buffers.sources_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: buffers.debug_channels_buf.as_entire_binding(),
             },
         ],
         label: Some("compute_bind_group"),
     });
 
-    let phm_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    let phm_bgl = if capabilities.read_write_storage {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::ReadWrite,
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+            label: Some("phm_bgl"),
+        })
+    } else {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("phm_bgl"),
+        })
+    };
+
+    // Without a back texture both bind groups point at the same field
+    let phm_views = [
+        &textures.phm_view,
+        textures
+            .phm_back_view
+            .as_ref()
+            .unwrap_or(&textures.phm_view),
+    ];
+
+    let phm_bg = |label: &str, front: &wgpu::TextureView, back: &wgpu::TextureView| match &buffers
+        .phm_deposit_buf
+    {
+        None => device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &phm_bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(front),
+            }],
+            label: Some(label),
+        }),
+        Some(phm_deposit_buf) => device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &phm_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(front),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(back),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: phm_deposit_buf.as_entire_binding(),
+                },
+            ],
+            label: Some(label),
+        }),
+    };
+
+    let phm_bgs = [
+        phm_bg("phm_bg_0", phm_views[0], phm_views[1]),
+        phm_bg("phm_bg_1", phm_views[1], phm_views[0]),
+    ];
+
+    let sampled_phm_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float {
+                        filterable: capabilities.float32_filterable,
+                    },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(if capabilities.float32_filterable {
+                    wgpu::SamplerBindingType::Filtering
+                } else {
+                    wgpu::SamplerBindingType::NonFiltering
+                }),
+                count: None,
+            },
+        ],
+        label: Some("sampled_phm_bgl"),
+    });
+
+    let sampled_phm_bg = |label: &str, view: &wgpu::TextureView| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &sampled_phm_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&textures.phm_sampler),
+                },
+            ],
+            label: Some(label),
+        })
+    };
+
+    let sampled_phm_bgs = [
+        sampled_phm_bg("sampled_texture_bg_0", phm_views[0]),
+        sampled_phm_bg("sampled_texture_bg_1", phm_views[1]),
+    ];
+
+    // The overlay only reads these, and read-write storage is not allowed
+    // in vertex shaders without VERTEX_WRITABLE_STORAGE
+    let read_only_vertex_storage = |binding: u32, size: usize| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(size as _),
+        },
+        count: None,
+    };
+
+    let agent_draw_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            read_only_vertex_storage(0, std::mem::size_of::<Slime>()),
+            read_only_vertex_storage(1, std::mem::size_of::<SlimeParams>()),
+            read_only_vertex_storage(2, std::mem::size_of::<JonesParams>()),
+            read_only_vertex_storage(3, std::mem::size_of::<SimParams>()),
+            read_only_vertex_storage(4, std::mem::size_of::<ViewParams>()),
+            read_only_vertex_storage(5, std::mem::size_of::<OverlayParams>()),
+            read_only_vertex_storage(6, std::mem::size_of::<[f32; 4]>()),
+        ],
+        label: Some("agent_draw_bgl"),
+    });
+
+    let agent_draw_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &agent_draw_bgl,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffers.slime_pos_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers.slime_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffers.jones_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: buffers.sim_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: buffers.view_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: buffers.overlay_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: buffers.debug_channels_buf.as_entire_binding(),
+            },
+        ],
+        label: Some("agent_draw_bg"),
+    });
+
+    let post_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<PostParams>() as _),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<ExposureState>() as _
+                    ),
+                },
+                count: None,
+            },
+        ],
+        label: Some("post_bgl"),
+    });
+
+    let post_composite_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[wgpu::BindGroupLayoutEntry {
             binding: 0,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-                access: wgpu::StorageTextureAccess::ReadWrite,
-                format: wgpu::TextureFormat::Rgba32Float,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
                 view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
             },
             count: None,
         }],
-        label: Some("phm_bgl"),
+        label: Some("post_composite_bgl"),
     });
 
-    let phm_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &phm_bgl,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: wgpu::BindingResource::TextureView(&texture_view),
-        }],
-        label: Some("phm_bg"),
-    });
-
-    let sampled_phm_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    let exposure_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
                     view_dimension: wgpu::TextureViewDimension::D2,
                     multisampled: false,
                 },
@@ -431,29 +1208,83 @@ pub(crate) fn init_bind_groups(
             },
             wgpu::BindGroupLayoutEntry {
                 binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<PostParams>() as _),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<ExposureState>() as _
+                    ),
+                },
                 count: None,
             },
         ],
-        label: Some("sampled_phm_bgl"),
+        label: Some("exposure_bgl"),
+    });
+
+    let storage_entry = |binding: u32, read_only: bool, size: usize| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(size as _),
+        },
+        count: None,
+    };
+
+    let stats_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            storage_entry(0, true, std::mem::size_of::<Slime>()),
+            storage_entry(1, true, std::mem::size_of::<StatsParams>()),
+            storage_entry(2, false, std::mem::size_of::<FieldPartial>()),
+            storage_entry(3, false, std::mem::size_of::<SimStats>()),
+        ],
+        label: Some("stats_bgl"),
     });
 
-    let sampled_phm_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &sampled_phm_bgl,
+    let stats_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &stats_bgl,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::TextureView(&texture_view),
+                resource: buffers.slime_pos_buf.as_entire_binding(),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::Sampler(phm_sampler),
+                resource: buffers.stats_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffers.stats_partials_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: buffers.stats_buf.as_entire_binding(),
             },
         ],
-        label: Some("sampled_texture_bg"),
+        label: Some("stats_bg"),
     });
 
+    let post = init_post_bind_groups(
+        device,
+        &post_bgl,
+        &post_composite_bgl,
+        &exposure_bgl,
+        buffers,
+        textures,
+    );
+
     BindGroups {
         uniform_bg,
         uniform_bgl,
@@ -461,10 +1292,95 @@ pub(crate) fn init_bind_groups(
         param_bgl,
         compute_bg,
         compute_bgl,
-        phm_bg,
+        phm_bgs,
         phm_bgl,
-        sampled_phm_bg,
+        sampled_phm_bgs,
         sampled_phm_bgl,
+        agent_draw_bg,
+        agent_draw_bgl,
+        post_bgl,
+        post_composite_bgl,
+        exposure_bgl,
+        stats_bg,
+        stats_bgl,
+        post,
+    }
+}
+
+// Everything here points at the size dependent post textures, so it is
+// rebuilt whenever they are
+pub(crate) fn init_post_bind_groups(
+    device: &wgpu::Device,
+    post_bgl: &wgpu::BindGroupLayout,
+    post_composite_bgl: &wgpu::BindGroupLayout,
+    exposure_bgl: &wgpu::BindGroupLayout,
+    buffers: &Buffers,
+    textures: &Textures,
+) -> PostBindGroups {
+    let source_bg = |label: &str, view: &wgpu::TextureView| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: post_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&textures.post_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers.post_params_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.exposure_state_buf.as_entire_binding(),
+                },
+            ],
+            label: Some(label),
+        })
+    };
+
+    let hdr_bg = source_bg("hdr_bg", &textures.post.hdr_view);
+    let bloom_bgs = [
+        source_bg("bloom_0_bg", &textures.post.bloom_views[0]),
+        source_bg("bloom_1_bg", &textures.post.bloom_views[1]),
+    ];
+
+    let composite_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: post_composite_bgl,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&textures.post.bloom_views[0]),
+        }],
+        label: Some("post_composite_bg"),
+    });
+
+    let exposure_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: exposure_bgl,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&textures.post.hdr_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers.post_params_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffers.exposure_state_buf.as_entire_binding(),
+            },
+        ],
+        label: Some("exposure_bg"),
+    });
+
+    PostBindGroups {
+        hdr_bg,
+        bloom_bgs,
+        composite_bg,
+        exposure_bg,
     }
 }
 
@@ -472,6 +1388,7 @@ pub(crate) fn init_pipelines(
     device: &wgpu::Device,
     bind_groups: &BindGroups,
     shader_modules: &ShaderModules,
+    surface_format: wgpu::TextureFormat,
 ) -> Pipelines {
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
@@ -493,17 +1410,14 @@ pub(crate) fn init_pipelines(
             buffers: &[wgpu::VertexBufferLayout {
                 array_stride: 8, // 2 * 4byte float
                 step_mode: wgpu::VertexStepMode::Vertex,
-                attributes: &wgpu::vertex_attr_array![
-                    0 => Float32x2,
-                    1 => Float32x2,
-                ],
+                attributes: &wgpu::vertex_attr_array![0 => Float32x2],
             }],
         },
         fragment: Some(wgpu::FragmentState {
             module: &shader_modules.f_shader,
             entry_point: "main",
             targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                format: HDR_FORMAT,
                 blend: Some(wgpu::BlendState::REPLACE),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
@@ -564,15 +1478,189 @@ pub(crate) fn init_pipelines(
         entry_point: "update_pheremone_heatmap",
     });
 
+    let agent_overlay_pipeline_layout =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Agent Overlay Pipeline Layout"),
+            bind_group_layouts: &[&bind_groups.agent_draw_bgl],
+            push_constant_ranges: &[],
+        });
+
+    let overlay_pipeline = |label: &str, vertex_entry_point: &str| {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&agent_overlay_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_modules.agent_overlay_shader,
+                entry_point: vertex_entry_point,
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_modules.agent_overlay_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    // Additive, so overlapping agents and sensors build up
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    };
+
+    let draw_agents_pipeline = overlay_pipeline("Draw Agents Pipeline", "vs_agent");
+    let draw_sensors_pipeline = overlay_pipeline("Draw Sensors Pipeline", "vs_sensor");
+
+    let exposure_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Auto Exposure Pipeline Layout"),
+        bind_group_layouts: &[&bind_groups.exposure_bgl],
+        push_constant_ranges: &[],
+    });
+
+    let exposure_histogram_pipeline =
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Exposure Histogram Pipeline"),
+            layout: Some(&exposure_pipeline_layout),
+            module: &shader_modules.exposure_shader,
+            entry_point: "build_histogram",
+        });
+
+    let exposure_adapt_pipeline =
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Exposure Adapt Pipeline"),
+            layout: Some(&exposure_pipeline_layout),
+            module: &shader_modules.exposure_shader,
+            entry_point: "adapt_exposure",
+        });
+
+    let stats_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Simulation Stats Pipeline Layout"),
+        bind_group_layouts: &[
+            &bind_groups.stats_bgl,
+            &bind_groups.uniform_bgl,
+            &bind_groups.phm_bgl,
+        ],
+        push_constant_ranges: &[],
+    });
+
+    let stats_field_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Stats Field Reduction Pipeline"),
+        layout: Some(&stats_pipeline_layout),
+        module: &shader_modules.stats_shader,
+        entry_point: "reduce_field",
+    });
+
+    let stats_finish_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Stats Finish Pipeline"),
+        layout: Some(&stats_pipeline_layout),
+        module: &shader_modules.stats_shader,
+        entry_point: "finish_stats",
+    });
+
+    let bloom_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Bloom Pipeline Layout"),
+        bind_group_layouts: &[&bind_groups.post_bgl],
+        push_constant_ranges: &[],
+    });
+
+    let tonemap_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Tonemap Pipeline Layout"),
+        bind_group_layouts: &[&bind_groups.post_bgl, &bind_groups.post_composite_bgl],
+        push_constant_ranges: &[],
+    });
+
+    let fullscreen_pipeline = |label: &str,
+                               layout: &wgpu::PipelineLayout,
+                               module: &wgpu::ShaderModule,
+                               fragment_entry_point: &str,
+                               format: wgpu::TextureFormat| {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: fragment_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    };
+
+    let bloom_bright_pipeline = fullscreen_pipeline(
+        "Bloom Bright Pass Pipeline",
+        &bloom_pipeline_layout,
+        &shader_modules.bloom_shader,
+        "fs_bright",
+        HDR_FORMAT,
+    );
+    let bloom_blur_h_pipeline = fullscreen_pipeline(
+        "Bloom Horizontal Blur Pipeline",
+        &bloom_pipeline_layout,
+        &shader_modules.bloom_shader,
+        "fs_blur_h",
+        HDR_FORMAT,
+    );
+    let bloom_blur_v_pipeline = fullscreen_pipeline(
+        "Bloom Vertical Blur Pipeline",
+        &bloom_pipeline_layout,
+        &shader_modules.bloom_shader,
+        "fs_blur_v",
+        HDR_FORMAT,
+    );
+    let tonemap_pipeline = fullscreen_pipeline(
+        "Tonemap Pipeline",
+        &tonemap_pipeline_layout,
+        &shader_modules.tonemap_shader,
+        "fs_tonemap",
+        surface_format,
+    );
+
     Pipelines {
         render: render_pipeline,
         init_slime: init_slime_pipeline,
         update_slime: update_slime_pipeline,
         update_phm: update_phm_pipeline,
+        draw_agents: draw_agents_pipeline,
+        draw_sensors: draw_sensors_pipeline,
+        exposure_histogram: exposure_histogram_pipeline,
+        exposure_adapt: exposure_adapt_pipeline,
+        bloom_bright: bloom_bright_pipeline,
+        bloom_blur_h: bloom_blur_h_pipeline,
+        bloom_blur_v: bloom_blur_v_pipeline,
+        tonemap: tonemap_pipeline,
+        stats_field: stats_field_pipeline,
+        stats_finish: stats_finish_pipeline,
     }
 }
 
-pub(crate) fn init_textures(device: &wgpu::Device, queue: &wgpu::Queue) -> Textures {
+pub(crate) fn init_textures(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: winit::dpi::PhysicalSize<u32>,
+    capabilities: Capabilities,
+) -> Textures {
     // let mut texture_size = 0;
 
     // for level in 0..MIP_LEVEL_COUNT {
@@ -612,7 +1700,8 @@ pub(crate) fn init_textures(device: &wgpu::Device, queue: &wgpu::Queue) -> Textu
             format: wgpu::TextureFormat::Rgba32Float,
             usage: wgpu::TextureUsages::STORAGE_BINDING
                 | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_DST,
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[wgpu::TextureFormat::Rgba32Float],
         },
         wgpu::util::TextureDataOrder::default(),
@@ -621,18 +1710,129 @@ pub(crate) fn init_textures(device: &wgpu::Device, queue: &wgpu::Queue) -> Textu
 
     let phm_view = phm.create_view(&phm_texture_view_desc);
 
+    // Starts zeroed, only written by the ping-pong diffusion pass
+    let phm_back = (!capabilities.read_write_storage).then(|| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Ping-Pong Storage Texture"),
+            size: phm_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        })
+    });
+
+    let phm_back_view = phm_back
+        .as_ref()
+        .map(|texture| texture.create_view(&phm_texture_view_desc));
+
+    // A filtering sampler can't be bound next to an unfilterable texture
+    let phm_filter = if capabilities.float32_filterable {
+        wgpu::FilterMode::Linear
+    } else {
+        wgpu::FilterMode::Nearest
+    };
+
     let phm_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
         label: Some("Pheremone Heat Map Sampler"),
+        mag_filter: phm_filter,
+        min_filter: phm_filter,
+        mipmap_filter: phm_filter,
+        ..Default::default()
+    });
+
+    // Starts as a grey ramp, replaced when a gradient strip is loaded
+    let palette_lut = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Palette LUT Texture"),
+            size: wgpu::Extent3d {
+                width: PALETTE_LUT_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D1,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::default(),
+        &default_palette_lut(),
+    );
+
+    let palette_lut_view = palette_lut.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Palette LUT Texture View"),
+        dimension: Some(wgpu::TextureViewDimension::D1),
+        ..Default::default()
+    });
+
+    let post_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Post Processing Sampler"),
         mag_filter: wgpu::FilterMode::Linear,
         min_filter: wgpu::FilterMode::Linear,
-        mipmap_filter: wgpu::FilterMode::Linear,
         ..Default::default()
     });
 
+    let post = init_post_textures(device, size);
+
     Textures {
         phm,
         phm_sampler,
         phm_view,
+        phm_back,
+        phm_back_view,
         phm_extent,
+        palette_lut,
+        palette_lut_view,
+        post_sampler,
+        post,
+    }
+}
+
+pub(crate) fn init_post_textures(
+    device: &wgpu::Device,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> PostTextures {
+    let render_target = |label: &str, width: u32, height: u32| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    };
+
+    let hdr = render_target("HDR Render Target", size.width, size.height);
+    let bloom = [
+        render_target("Bloom Target 0", size.width / 2, size.height / 2),
+        render_target("Bloom Target 1", size.width / 2, size.height / 2),
+    ];
+
+    let hdr_view = hdr.create_view(&wgpu::TextureViewDescriptor::default());
+    let bloom_views = [
+        bloom[0].create_view(&wgpu::TextureViewDescriptor::default()),
+        bloom[1].create_view(&wgpu::TextureViewDescriptor::default()),
+    ];
+
+    PostTextures {
+        hdr,
+        hdr_view,
+        bloom,
+        bloom_views,
     }
 }
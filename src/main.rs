@@ -2,9 +2,26 @@ mod state;
 use state::app_state::State;
 mod structs;
 use structs::*;
+mod benchmark;
+mod checkpoint;
+mod debug;
+mod export;
+mod graph;
+mod gui;
+mod hud;
 mod init;
+mod modulation;
+mod palettes;
+mod params;
+mod post;
+mod presets;
+mod sources;
 mod updates;
 
+use benchmark::benchmark_functions::run_benchmark;
+use clap::Parser;
+use init::init_functions::{init_instance, list_adapters};
+use std::sync::Arc;
 use winit::{
     dpi::PhysicalSize,
     event::{Event, WindowEvent},
@@ -13,65 +30,175 @@ use winit::{
 };
 
 fn main() {
+    let args = Args::parse();
+
+    if args.list_adapters {
+        list_adapters(&init_instance(&args));
+        return;
+    }
+
+    if args.headless {
+        run_headless(&args);
+        return;
+    }
+
     let event_loop = EventLoop::new().expect("event loop should init");
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let window = WindowBuilder::new()
-        .with_title("winit window")
-        .with_inner_size(PhysicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT))
-        .build(&event_loop)
-        .expect("window should open");
+    let window = Arc::new(
+        WindowBuilder::new()
+            .with_title("winit window")
+            .with_inner_size(PhysicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT))
+            .build(&event_loop)
+            .expect("window should open"),
+    );
 
-    let mut state = futures::executor::block_on(State::new(window.into()));
+    let mut state = futures::executor::block_on(State::new(Some(Arc::clone(&window)), &args));
 
-    state.init_slime();
+    start(&mut state, &args);
 
     event_loop
-        .run(move |event, elwt| match event {
-            Event::WindowEvent { ref event, .. } => match event {
-                WindowEvent::CloseRequested => elwt.exit(),
-                WindowEvent::RedrawRequested => {
-                    let elapsed_time = state.get_time();
-                    let time_bytes = elapsed_time.to_ne_bytes();
-                    state.queue.write_buffer(
-                        &state.buffers.time_uniform_buf,
-                        0,
-                        bytemuck::cast_slice(&[time_bytes]),
-                    );
-
-                    state.update();
-
-                    match state.render() {
-                        Ok(_) => {}
-                        // Reconfigure the surface if lost
-                        Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                        // The system is out of memory, we should probably quit
-                        Err(wgpu::SurfaceError::OutOfMemory) => {
-                            elwt.exit();
-                        }
-                        // All other errors (Outdated, Timeout) should be resolved by the next frame
-                        Err(e) => eprintln!("{:?}", e),
-                    };
-
-                    state.window.request_redraw();
-                }
-                WindowEvent::KeyboardInput { event, .. } => {
-                    state.controls.handle_keyboard_input(event);
-                }
-                WindowEvent::Focused(focused) => {
-                    if !focused {
+        .run(move |event, elwt| {
+            if let Event::WindowEvent { ref event, .. } = event {
+                let response = state.ui.on_window_event(&window, event);
+
+                match event {
+                    WindowEvent::CloseRequested => elwt.exit(),
+                    WindowEvent::RedrawRequested => {
+                        let encoder = state.update();
+
+                        match state.render(encoder) {
+                            Ok(_) => {}
+                            // Reconfigure the surface if lost
+                            Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
+                            // The system is out of memory, we should probably quit
+                            Err(wgpu::SurfaceError::OutOfMemory) => {
+                                elwt.exit();
+                            }
+                            // All other errors (Outdated, Timeout) should be resolved by the next frame
+                            Err(e) => eprintln!("{:?}", e),
+                        };
+
+                        window.request_redraw();
+                    }
+                    // Typing into the GUI shouldn't also drive the keyboard controls
+                    WindowEvent::KeyboardInput { event, .. } if !response.consumed => {
+                        state.controls.handle_keyboard_input(event);
+                    }
+                    WindowEvent::ModifiersChanged(modifiers) => {
+                        state.controls.set_modifiers(modifiers.state());
+                    }
+                    WindowEvent::Focused(false) => {
                         // Clear the keys HashSet when the window loses focus
                         state.controls.clear_keys();
                         println!("Window lost focus, cleared keys.");
                     }
+                    _ => {}
                 }
-                _ => {}
-            },
-            _ => {}
+            }
         })
         .expect("event loop should run");
 }
 
+// The starting preset has to be in place before the agents are seeded from it,
+// a checkpoint brings its own agents
+fn start(state: &mut State, args: &Args) {
+    if let Err(e) = state.load_sources(args) {
+        eprintln!("Error loading food sources: {}", e);
+    }
+
+    if let Some(path) = &args.resume {
+        match state.load_checkpoint(path) {
+            Ok(()) => {
+                load_lut(state, args);
+                return;
+            }
+            Err(e) => eprintln!("Error resuming from {}: {}", path.display(), e),
+        }
+    }
+
+    if let Some(path) = &args.preset {
+        if let Err(e) = state.load_initial_preset(path) {
+            eprintln!("Error loading preset {}: {}", path.display(), e);
+        }
+    }
+    load_lut(state, args);
+
+    state.init_slime();
+}
+
+// --lut wins over whatever LUT the preset or checkpoint brought
+fn load_lut(state: &mut State, args: &Args) {
+    if let Some(path) = &args.lut {
+        match state.load_palette_lut(path) {
+            Ok(()) => state.set_palette_mode(PaletteMode::Lut),
+            Err(e) => eprintln!("Error loading palette LUT {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Steps the simulation without a window, exporting the field as it goes
+fn run_headless(args: &Args) {
+    let mut state = futures::executor::block_on(State::new(None, args));
+
+    start(&mut state, args);
+
+    if args.benchmark {
+        run_benchmark(&mut state, args.steps);
+        return;
+    }
+
+    let export = |state: &State| {
+        if let Err(e) = state.export_fields() {
+            eprintln!("Error exporting field: {}", e);
+        }
+    };
+
+    let started = std::time::Instant::now();
+    let target = state.step + args.steps;
+    while state.step < target {
+        // Frames end on export steps
+        let mut steps = state.steps_per_frame.min(target - state.step);
+        if args.export_interval > 0 {
+            steps = steps.min(args.export_interval - state.step % args.export_interval);
+        }
+        state.step_headless(steps);
+
+        if args.export_interval > 0 && state.step.is_multiple_of(args.export_interval) {
+            export(&state);
+        }
+    }
+
+    // The last step is always exported
+    if args.export_interval == 0 || !state.step.is_multiple_of(args.export_interval) {
+        export(&state);
+    }
+
+    if args.graph {
+        if let Err(e) = state.extract_graph() {
+            eprintln!("Error extracting graph: {}", e);
+        }
+    }
+
+    if let Some(path) = &args.checkpoint {
+        if let Err(e) = state.save_checkpoint(path) {
+            eprintln!("Error saving checkpoint: {}", e);
+        }
+    }
+
+    // Let the last statistics samples land
+    state.device.poll(wgpu::Maintain::Wait);
+    state.poll_readbacks();
+
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "Ran {} steps in {:.2}s, {:.1} steps/s",
+        args.steps,
+        elapsed,
+        args.steps as f64 / elapsed.max(f64::EPSILON)
+    );
+}
+
 fn vertices_as_bytes(data: &[Vertex]) -> &[u8] {
     bytemuck::cast_slice(data)
 }
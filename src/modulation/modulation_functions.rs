@@ -0,0 +1,312 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    params::param_functions::{clamp_param, find_param},
+    state::app_state::State,
+    updates::update_functions::update_param_group_buffer,
+    ActiveModulation, KeyframeInterpolation, LfoShape, Modulation, ModulationSource, ParamGroup,
+    Params,
+};
+
+/// Matches each modulation to its registered parameter, taking the LFO
+/// centres from `params`. Modulations that can't run are reported and skipped.
+pub(crate) fn resolve_modulations(
+    modulations: &[Modulation],
+    params: &Params,
+) -> Vec<ActiveModulation> {
+    modulations
+        .iter()
+        .filter_map(|modulation| {
+            let Some(param) = find_param(&modulation.param) else {
+                eprintln!(
+                    "Modulation: ignoring unknown parameter {}",
+                    modulation.param
+                );
+                return None;
+            };
+
+            let problem = match &modulation.source {
+                ModulationSource::Lfo { period, .. } if *period <= 0.0 => {
+                    Some("the period must be positive")
+                }
+                ModulationSource::Keyframes { keys, .. } if keys.is_empty() => {
+                    Some("there are no keys")
+                }
+                ModulationSource::Keyframes { keys, .. }
+                    if keys.windows(2).any(|pair| pair[1].0 <= pair[0].0) =>
+                {
+                    Some("the keys must be in increasing step order")
+                }
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                eprintln!("Modulation: ignoring {}, {}", modulation.param, problem);
+                return None;
+            }
+
+            Some(ActiveModulation {
+                modulation: modulation.clone(),
+                param,
+                base: (param.get)(params),
+            })
+        })
+        .collect()
+}
+
+// Deterministic value in [-1, 1] for an integer lattice point
+fn lattice_noise(salt: u32, index: i64) -> f32 {
+    // PCG hash, as in rng.wgsl
+    let mut state = (index as u32 ^ salt)
+        .wrapping_mul(747796405)
+        .wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    state = (word >> 22) ^ word;
+    state as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+fn name_salt(name: &str) -> u32 {
+    // FNV-1a, so each parameter gets its own noise
+    name.bytes().fold(2166136261u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(16777619)
+    })
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// `shape` at `cycles` periods in, between -1 and 1
+pub(crate) fn lfo_wave(shape: LfoShape, cycles: f32, salt: u32) -> f32 {
+    match shape {
+        LfoShape::Sine => (cycles * TAU).sin(),
+        // Starts at 0 heading up, like the sine
+        LfoShape::Triangle => 4.0 * ((cycles - 0.25).rem_euclid(1.0) - 0.5).abs() - 1.0,
+        LfoShape::Noise => {
+            let index = cycles.floor();
+            let from = lattice_noise(salt, index as i64);
+            let to = lattice_noise(salt, index as i64 + 1);
+            from + (to - from) * smoothstep(cycles - index)
+        }
+    }
+}
+
+/// Value of the keyframe curve at `step`
+pub(crate) fn keyframe_value(
+    keys: &[(f32, f32)],
+    interpolation: KeyframeInterpolation,
+    repeat: bool,
+    step: f32,
+) -> f32 {
+    let (first, last) = (keys[0], keys[keys.len() - 1]);
+    let length = last.0 - first.0;
+
+    let step = if repeat && length > 0.0 {
+        first.0 + (step - first.0).rem_euclid(length)
+    } else {
+        step
+    };
+
+    if step <= first.0 {
+        return first.1;
+    }
+    if step >= last.0 {
+        return last.1;
+    }
+
+    let next = keys.partition_point(|key| key.0 <= step);
+    let (from, to) = (keys[next - 1], keys[next]);
+    let t = (step - from.0) / (to.0 - from.0);
+
+    match interpolation {
+        KeyframeInterpolation::Step => from.1,
+        KeyframeInterpolation::Linear => from.1 + (to.1 - from.1) * t,
+        KeyframeInterpolation::Smooth => from.1 + (to.1 - from.1) * smoothstep(t),
+    }
+}
+
+/// The modulated parameter's value at `step`, clamped into its range
+pub(crate) fn modulation_value(active: &ActiveModulation, step: f32) -> f32 {
+    let value = match &active.modulation.source {
+        ModulationSource::Lfo {
+            shape,
+            period,
+            amplitude,
+            phase,
+            center,
+        } => {
+            let salt = name_salt(&active.modulation.param);
+            let wave = lfo_wave(*shape, step / period + phase, salt);
+            center.unwrap_or(active.base) + amplitude * wave
+        }
+        ModulationSource::Keyframes {
+            keys,
+            interpolation,
+            repeat,
+        } => keyframe_value(keys, *interpolation, *repeat, step),
+    };
+    clamp_param(active.param, value)
+}
+
+/// Writes every modulated parameter for the current step and uploads the
+/// groups that changed. Runs before each simulation step.
+pub(crate) fn modulate_params(state: &mut State) {
+    if state.modulations.is_empty() {
+        return;
+    }
+
+    let step = state.step as f32;
+    let mut groups: Vec<ParamGroup> = Vec::new();
+
+    for active in &state.modulations {
+        let value = modulation_value(active, step);
+        // The modulation owns the parameter, drop any easing towards an edit
+        state.smoother.cancel(active.param);
+        *(active.param.get_mut)(&mut state.params) = value;
+
+        if !groups.contains(&active.param.group) {
+            groups.push(active.param.group);
+        }
+    }
+
+    for group in groups {
+        update_param_group_buffer(state, group);
+    }
+}
+
+/// `params` with each modulated parameter back at its base, for saving
+pub(crate) fn unmodulated_params(modulations: &[ActiveModulation], params: &Params) -> Params {
+    let mut params = params.clone();
+    for active in modulations {
+        *(active.param.get_mut)(&mut params) = active.base;
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init::init_functions::init_params, presets::preset_functions::preset_from_params};
+
+    fn assert_near(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < 1e-5,
+            "{} is not {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn sine_and_triangle_at_quarter_periods() {
+        for (cycles, expected) in [
+            (0.0, 0.0),
+            (0.25, 1.0),
+            (0.5, 0.0),
+            (0.75, -1.0),
+            (1.0, 0.0),
+        ] {
+            assert_near(lfo_wave(LfoShape::Sine, cycles, 0), expected);
+            assert_near(lfo_wave(LfoShape::Triangle, cycles, 0), expected);
+        }
+        // The triangle is straight between its peaks
+        assert_near(lfo_wave(LfoShape::Triangle, 0.125, 0), 0.5);
+        assert_near(lfo_wave(LfoShape::Triangle, -0.125, 0), -0.5);
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let salt = name_salt("slime.sensor_offset");
+        for i in -200..200 {
+            let value = lfo_wave(LfoShape::Noise, i as f32 * 0.37, salt);
+            assert!((-1.0..=1.0).contains(&value), "{}", value);
+        }
+        // Passes through the lattice values
+        assert_eq!(lfo_wave(LfoShape::Noise, 3.0, salt), lattice_noise(salt, 3));
+    }
+
+    #[test]
+    fn keyframes_hold_outside_their_range() {
+        let keys = [(100.0, 1.0), (200.0, 3.0), (300.0, 2.0)];
+        let value = |step| keyframe_value(&keys, KeyframeInterpolation::Linear, false, step);
+
+        assert_eq!(value(0.0), 1.0);
+        assert_eq!(value(100.0), 1.0);
+        assert_eq!(value(150.0), 2.0);
+        assert_eq!(value(250.0), 2.5);
+        assert_eq!(value(300.0), 2.0);
+        assert_eq!(value(1000.0), 2.0);
+
+        assert_eq!(
+            keyframe_value(&keys, KeyframeInterpolation::Step, false, 250.0),
+            3.0
+        );
+        assert_eq!(
+            keyframe_value(&keys, KeyframeInterpolation::Smooth, false, 150.0),
+            2.0
+        );
+        // Repeating plays 100..300 again from 300
+        assert_eq!(
+            keyframe_value(&keys, KeyframeInterpolation::Linear, true, 350.0),
+            2.0
+        );
+    }
+
+    fn modulation(source: ModulationSource) -> Modulation {
+        Modulation {
+            param: "slime.sensor_offset".to_string(),
+            source,
+        }
+    }
+
+    fn keyframes(keys: Vec<(f32, f32)>) -> ModulationSource {
+        ModulationSource::Keyframes {
+            keys,
+            interpolation: KeyframeInterpolation::Smooth,
+            repeat: true,
+        }
+    }
+
+    #[test]
+    fn unsorted_keys_are_skipped() {
+        let params = init_params();
+        let modulations = [
+            modulation(keyframes(vec![(0.0, 1.0), (10.0, 2.0)])),
+            modulation(keyframes(vec![(10.0, 1.0), (0.0, 2.0)])),
+            modulation(keyframes(vec![(0.0, 1.0), (0.0, 2.0)])),
+            modulation(keyframes(Vec::new())),
+        ];
+
+        let active = resolve_modulations(&modulations, &params);
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].modulation, modulations[0]);
+    }
+
+    #[test]
+    fn presets_round_trip_both_kinds() {
+        let mut preset = preset_from_params(&init_params());
+        preset.modulations = vec![
+            modulation(ModulationSource::Lfo {
+                shape: LfoShape::Triangle,
+                period: 500.0,
+                amplitude: 2.0,
+                phase: 0.25,
+                center: Some(10.0),
+            }),
+            modulation(ModulationSource::Lfo {
+                shape: LfoShape::Noise,
+                period: 80.0,
+                amplitude: 1.0,
+                phase: 0.0,
+                center: None,
+            }),
+            modulation(keyframes(vec![(0.0, 1.0), (2000.0, 4.5)])),
+        ];
+
+        let text = toml::to_string_pretty(&preset).unwrap();
+        assert!(text.contains("[[modulation]]"));
+        assert!(text.contains("kind = \"keyframes\""));
+
+        let read: crate::Preset = toml::from_str(&text).unwrap();
+        assert_eq!(read.modulations, preset.modulations);
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use crate::PALETTE_LUT_SIZE;
+
+/// Reads a horizontal gradient strip (any height, left is low intensity) and
+/// resamples its middle row to `PALETTE_LUT_SIZE` RGBA8 texels.
+pub(crate) fn load_palette_lut(path: &Path) -> Result<Vec<u8>, image::ImageError> {
+    let strip = image::open(path)?.to_rgba8();
+    let row = strip.height() / 2;
+    let texels = (0..strip.width())
+        .map(|x| strip.get_pixel(x, row).0)
+        .collect::<Vec<[u8; 4]>>();
+
+    Ok(resample_strip(&texels, PALETTE_LUT_SIZE))
+}
+
+/// The grey ramp the LUT starts as, and goes back to for presets without one
+pub(crate) fn default_palette_lut() -> Vec<u8> {
+    (0..PALETTE_LUT_SIZE)
+        .flat_map(|i| {
+            let v = (i * 255 / (PALETTE_LUT_SIZE - 1)) as u8;
+            [v, v, v, 255]
+        })
+        .collect()
+}
+
+fn resample_strip(texels: &[[u8; 4]], size: u32) -> Vec<u8> {
+    let last = texels.len().saturating_sub(1) as f32;
+
+    (0..size)
+        .flat_map(|i| {
+            let x = i as f32 / (size - 1) as f32 * last;
+            let i0 = x.floor() as usize;
+            let i1 = usize::min(i0 + 1, texels.len() - 1);
+            let t = x.fract();
+
+            let mut out = [0u8; 4];
+            for (c, o) in out.iter_mut().enumerate() {
+                let a = texels[i0][c] as f32;
+                let b = texels[i1][c] as f32;
+                *o = (a + (b - a) * t).round() as u8;
+            }
+            out
+        })
+        .collect()
+}
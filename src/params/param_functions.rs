@@ -0,0 +1,416 @@
+use std::f32::consts::PI;
+
+use crate::{
+    init::init_functions::init_params,
+    state::{app_state::State, history_state::ParamChange},
+    updates::update_functions::update_param_group_buffer,
+    ParamGroup, ParamInfo, ParamScale, Params,
+};
+
+macro_rules! param {
+    (
+        $group:ident, $params:ident . $field:ident, $label:expr, $description:expr,
+        $min:expr, $max:expr, $step:expr, $scale:ident $(,)?
+    ) => {
+        ParamInfo {
+            field: stringify!($field),
+            label: $label,
+            description: $description,
+            group: ParamGroup::$group,
+            min: $min,
+            max: $max,
+            step: $step,
+            scale: ParamScale::$scale,
+            get: |params: &Params| params.$params.$field,
+            get_mut: |params: &mut Params| &mut params.$params.$field,
+        }
+    };
+}
+
+/// Every parameter the keyboard, GUI, HUD and presets can edit. The
+/// defaults come from `init_params`.
+pub(crate) const PARAMS: &[ParamInfo] = &[
+    // SLIME
+    param!(
+        Slime,
+        slime_params.max_velocity,
+        "max velocity",
+        "Fastest an agent moves per step, in field coordinates",
+        0.0,
+        0.01,
+        1e-5,
+        Linear,
+    ),
+    param!(
+        Slime,
+        slime_params.min_velocity,
+        "min velocity",
+        "Slowest an agent moves per step, negative lets it reverse",
+        -0.01,
+        0.01,
+        1e-5,
+        Linear,
+    ),
+    param!(
+        Slime,
+        slime_params.turn_factor,
+        "turn factor",
+        "How hard an agent steers towards its strongest sensor",
+        0.0,
+        1e-3,
+        1e-6,
+        Linear,
+    ),
+    param!(
+        Slime,
+        slime_params.avoid_factor,
+        "avoid factor",
+        "Distance within which agents jitter away from each other",
+        0.0,
+        1.0,
+        0.01,
+        Linear,
+    ),
+    param!(
+        Slime,
+        slime_params.sensor_dist,
+        "sensor distance",
+        "Distance of the sensors ahead of the agent, in field coordinates",
+        0.0,
+        0.2,
+        0.001,
+        Linear,
+    ),
+    param!(
+        Slime,
+        slime_params.sensor_offset,
+        "sensor offset",
+        "Angle between the centre and side sensors, in radians",
+        0.0,
+        PI,
+        0.1,
+        Linear,
+    ),
+    param!(
+        Slime,
+        slime_params.sensor_radius,
+        "sensor radius",
+        "Radius each sensor sums over, in field heights",
+        0.0,
+        0.1,
+        0.001,
+        Linear,
+    ),
+    // JONES
+    param!(
+        Jones,
+        jones_params.sensor_angle,
+        "sensor angle",
+        "Angle between the centre and side sensors, in radians",
+        0.0,
+        PI,
+        0.01,
+        Linear,
+    ),
+    param!(
+        Jones,
+        jones_params.rotation_angle,
+        "rotation angle",
+        "How far an agent turns each step, in radians",
+        0.0,
+        PI,
+        0.01,
+        Linear,
+    ),
+    param!(
+        Jones,
+        jones_params.sensor_offset,
+        "sensor offset",
+        "Distance of the sensors ahead of the agent, in texels",
+        0.0,
+        64.0,
+        0.1,
+        Linear,
+    ),
+    param!(
+        Jones,
+        jones_params.sensor_width,
+        "sensor width",
+        "Width of the square each sensor sums over, in texels",
+        1.0,
+        9.0,
+        0.1,
+        Linear,
+    ),
+    param!(
+        Jones,
+        jones_params.step_size,
+        "step size",
+        "Distance an agent moves each step, in texels",
+        0.0,
+        10.0,
+        0.01,
+        Linear,
+    ),
+    // PHEREMONE
+    param!(
+        Pheremone,
+        pheremone_params.deposition_amount,
+        "deposition",
+        "Pheremone an agent leaves behind each step",
+        0.0,
+        1.0,
+        0.003,
+        Linear,
+    ),
+    param!(
+        Pheremone,
+        pheremone_params.diffusion_factor,
+        "diffusion",
+        "Fraction of each texel blurred into its neighbours each step",
+        0.0,
+        1.0,
+        0.03,
+        Linear,
+    ),
+    param!(
+        Pheremone,
+        pheremone_params.decay_factor,
+        "decay",
+        "Fraction of the pheremone kept each step",
+        0.0,
+        1.0,
+        0.003,
+        Linear,
+    ),
+    // VIEW
+    param!(
+        View,
+        view_params.zoom,
+        "zoom",
+        "Magnification of the field",
+        0.01,
+        1000.0,
+        0.1,
+        Log,
+    ),
+    param!(
+        View,
+        view_params.x_shift,
+        "x shift",
+        "Horizontal pan, in field coordinates",
+        -10.0,
+        10.0,
+        0.01,
+        Linear,
+    ),
+    param!(
+        View,
+        view_params.y_shift,
+        "y shift",
+        "Vertical pan, in field coordinates",
+        -10.0,
+        10.0,
+        0.01,
+        Linear,
+    ),
+    param!(
+        View,
+        view_params.shift_modifier,
+        "pan speed",
+        "Scale of each pan step",
+        0.1,
+        10.0,
+        0.1,
+        Linear,
+    ),
+    param!(
+        View,
+        view_params.time_modifier,
+        "time modifier",
+        "Speed of time based colour effects",
+        0.0,
+        1.0,
+        0.001,
+        Linear,
+    ),
+    // POST
+    param!(
+        Post,
+        post_params.exposure,
+        "exposure",
+        "Manual exposure, or compensation on top of auto exposure",
+        0.01,
+        100.0,
+        0.02,
+        Log,
+    ),
+    param!(
+        Post,
+        post_params.adapt_rate,
+        "adapt rate",
+        "Fraction of the way to the target exposure moved each frame",
+        0.0,
+        1.0,
+        0.01,
+        Linear,
+    ),
+    param!(
+        Post,
+        post_params.percentile,
+        "percentile",
+        "Fraction of lit pixels that auto exposure keeps below 1.0",
+        0.5,
+        1.0,
+        0.002,
+        Linear,
+    ),
+    param!(
+        Post,
+        post_params.white_point,
+        "white point",
+        "Brightness that maps to 1.0 under the log curve",
+        1.0,
+        1000.0,
+        0.02,
+        Log,
+    ),
+    param!(
+        Post,
+        post_params.bloom_threshold,
+        "bloom threshold",
+        "Brightness above which pixels bloom",
+        0.0,
+        10.0,
+        0.01,
+        Linear,
+    ),
+    param!(
+        Post,
+        post_params.bloom_intensity,
+        "bloom intensity",
+        "Strength of the bloom added back to the image",
+        0.0,
+        10.0,
+        0.01,
+        Linear,
+    ),
+];
+
+/// Looks a parameter up by its "<group>.<field>" name, e.g. "slime.max_velocity"
+pub(crate) fn find_param(name: &str) -> Option<&'static ParamInfo> {
+    let (group, field) = name.split_once('.')?;
+    PARAMS
+        .iter()
+        .find(|info| info.group.name() == group && info.field == field)
+}
+
+pub(crate) fn param_name(info: &ParamInfo) -> String {
+    format!("{}.{}", info.group.name(), info.field)
+}
+
+pub(crate) fn group_params(group: ParamGroup) -> impl Iterator<Item = &'static ParamInfo> {
+    PARAMS.iter().filter(move |info| info.group == group)
+}
+
+pub(crate) fn param_default(info: &ParamInfo) -> f32 {
+    (info.get)(&init_params())
+}
+
+pub(crate) fn clamp_param(info: &ParamInfo, value: f32) -> f32 {
+    value.clamp(info.min, info.max)
+}
+
+/// `value` moved by `steps` of the parameter's step, negative steps go down
+pub(crate) fn step_param(info: &ParamInfo, value: f32, steps: f32) -> f32 {
+    let stepped = match info.scale {
+        ParamScale::Linear => value + info.step * steps,
+        ParamScale::Log => value * (1.0 + info.step).powf(steps),
+    };
+    clamp_param(info, stepped)
+}
+
+/// Enough decimals to see a single step
+pub(crate) fn format_param(info: &ParamInfo, value: f32) -> String {
+    let decimals = match info.scale {
+        ParamScale::Linear => (-info.step.log10()).ceil().max(0.0) as usize + 1,
+        ParamScale::Log => 3,
+    };
+    format!("{:.*}", decimals, value)
+}
+
+/// Where the parameter is, or is easing towards
+pub(crate) fn param_target(state: &State, info: &ParamInfo) -> f32 {
+    state
+        .smoother
+        .target(info)
+        .unwrap_or_else(|| (info.get)(&state.params))
+}
+
+/// Stores `value` without recording it, easing it in if smoothing is on
+fn apply_param(state: &mut State, info: &'static ParamInfo, value: f32) {
+    let value = clamp_param(info, value);
+    if state.smoother.enabled() {
+        state.smoother.set_target(info, value);
+    } else {
+        state.smoother.cancel(info);
+        *(info.get_mut)(&mut state.params) = value;
+        update_param_group_buffer(state, info.group);
+    }
+}
+
+/// Sets several parameters as one undo step
+pub(crate) fn set_params(state: &mut State, values: &[(&'static ParamInfo, f32)]) {
+    let changes = values
+        .iter()
+        .map(|&(param, value)| ParamChange {
+            param,
+            from: param_target(state, param),
+            to: clamp_param(param, value),
+        })
+        .collect();
+    state.history.record(changes);
+
+    for &(info, value) in values {
+        apply_param(state, info, value);
+    }
+}
+
+/// Clamps `value` into range and applies it as an undoable edit
+pub(crate) fn set_param(state: &mut State, info: &'static ParamInfo, value: f32) {
+    set_params(state, &[(info, value)]);
+}
+
+pub(crate) fn nudge_param(state: &mut State, info: &'static ParamInfo, steps: f32) {
+    let value = step_param(info, param_target(state, info), steps);
+    set_param(state, info, value);
+}
+
+pub(crate) fn undo_params(state: &mut State) {
+    match state.history.undo() {
+        Some(values) => {
+            for (info, value) in values {
+                apply_param(state, info, value);
+            }
+        }
+        None => println!("Nothing to undo"),
+    }
+}
+
+pub(crate) fn redo_params(state: &mut State) {
+    match state.history.redo() {
+        Some(values) => {
+            for (info, value) in values {
+                apply_param(state, info, value);
+            }
+        }
+        None => println!("Nothing to redo"),
+    }
+}
+
+/// Eases any smoothed parameters towards their targets, once per frame
+pub(crate) fn advance_params(state: &mut State) {
+    for group in state.smoother.advance(&mut state.params) {
+        update_param_group_buffer(state, group);
+    }
+}
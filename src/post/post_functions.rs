@@ -0,0 +1,97 @@
+use crate::state::{app_state::State, profiler_state::ProfiledPass};
+
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    target: &wgpu::TextureView,
+    pipeline: &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        timestamp_writes,
+        ..Default::default()
+    });
+
+    render_pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(index as u32, bind_group, &[]);
+    }
+    render_pass.draw(0..3, 0..1);
+}
+
+// Runs after the field has been drawn into the HDR target and leaves the
+// tonemapped image in `target`
+pub(crate) fn encode_post_processing(
+    state: &State,
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+) {
+    let post_params = &state.params.post_params;
+    let post_bgs = &state.bind_groups.post;
+    let profiler = &state.profiler;
+
+    if post_params.auto_exposure != 0 {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Auto Exposure Pass"),
+            timestamp_writes: profiler.compute_timestamps(ProfiledPass::Exposure),
+        });
+
+        compute_pass.set_bind_group(0, &post_bgs.exposure_bg, &[]);
+        compute_pass.set_pipeline(&state.pipelines.exposure_histogram);
+        compute_pass.dispatch_workgroups(
+            state.size.width.div_ceil(16),
+            state.size.height.div_ceil(16),
+            1,
+        );
+        compute_pass.set_pipeline(&state.pipelines.exposure_adapt);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    if post_params.bloom_enabled != 0 {
+        let bloom_views = &state.textures.post.bloom_views;
+
+        fullscreen_pass(
+            encoder,
+            "Bloom Bright Pass",
+            &bloom_views[0],
+            &state.pipelines.bloom_bright,
+            &[&post_bgs.hdr_bg],
+            profiler.render_timestamps(ProfiledPass::Bloom, true, false),
+        );
+        fullscreen_pass(
+            encoder,
+            "Bloom Horizontal Blur Pass",
+            &bloom_views[1],
+            &state.pipelines.bloom_blur_h,
+            &[&post_bgs.bloom_bgs[0]],
+            None,
+        );
+        fullscreen_pass(
+            encoder,
+            "Bloom Vertical Blur Pass",
+            &bloom_views[0],
+            &state.pipelines.bloom_blur_v,
+            &[&post_bgs.bloom_bgs[1]],
+            profiler.render_timestamps(ProfiledPass::Bloom, false, true),
+        );
+    }
+
+    fullscreen_pass(
+        encoder,
+        "Tonemap Pass",
+        target,
+        &state.pipelines.tonemap,
+        &[&post_bgs.hdr_bg, &post_bgs.composite_bg],
+        profiler.render_timestamps(ProfiledPass::Tonemap, true, true),
+    );
+}
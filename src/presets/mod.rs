@@ -0,0 +1 @@
+pub(crate) mod preset_functions;
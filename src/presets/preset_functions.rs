@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::{
+    params::param_functions::{clamp_param, PARAMS},
+    AgentModel, BoundaryMode, PaletteMode, Params, Preset, PresetPalette,
+};
+
+pub(crate) fn preset_from_params(params: &Params) -> Preset {
+    let pal = &params.palette_params;
+
+    let mut table: BTreeMap<String, BTreeMap<String, f32>> = BTreeMap::new();
+    for info in PARAMS {
+        table
+            .entry(info.group.name().to_string())
+            .or_default()
+            .insert(info.field.to_string(), (info.get)(params));
+    }
+
+    Preset {
+        seed: params.sim_params.seed,
+        boundary_mode: BoundaryMode::from_u32(params.sim_params.boundary_mode),
+        agent_model: AgentModel::from_u32(params.sim_params.agent_model),
+        params: table,
+        view_params: None,
+        slime_params: None,
+        jones_params: None,
+        pheremone_params: None,
+        palette: PresetPalette {
+            mode: PaletteMode::from_u32(pal.mode),
+            scale: pal.scale,
+            a: [pal.a[0], pal.a[1], pal.a[2]],
+            b: [pal.b[0], pal.b[1], pal.b[2]],
+            c: [pal.c[0], pal.c[1], pal.c[2]],
+            d: [pal.d[0], pal.d[1], pal.d[2]],
+            lut_path: params.palette_lut_path.clone(),
+        },
+        modulations: Vec::new(),
+    }
+}
+
+/// Copies a preset into `params`, clamping each registered parameter into its
+/// range. Parameters the preset doesn't mention keep their current value. The
+/// caller is responsible for uploading the changed parameters and loading the
+/// palette LUT.
+pub(crate) fn apply_preset_to_params(preset: &Preset, params: &mut Params) {
+    let pal = &preset.palette;
+
+    params.sim_params.seed = preset.seed;
+    params.sim_params.boundary_mode = preset.boundary_mode as u32;
+    params.sim_params.agent_model = preset.agent_model as u32;
+
+    if let Some(view_params) = preset.view_params {
+        params.view_params = view_params;
+    }
+    if let Some(slime_params) = preset.slime_params {
+        params.slime_params = slime_params;
+    }
+    if let Some(jones_params) = preset.jones_params {
+        params.jones_params = jones_params;
+    }
+    if let Some(pheremone_params) = preset.pheremone_params {
+        params.pheremone_params = pheremone_params;
+    }
+
+    for (group, fields) in &preset.params {
+        for (field, value) in fields {
+            match PARAMS
+                .iter()
+                .find(|info| info.group.name() == group && info.field == field)
+            {
+                Some(info) => *(info.get_mut)(params) = clamp_param(info, *value),
+                None => eprintln!("Preset: ignoring unknown parameter {}.{}", group, field),
+            }
+        }
+    }
+
+    params.palette_params.mode = pal.mode as u32;
+    params.palette_params.scale = pal.scale;
+    params.palette_params.a = [pal.a[0], pal.a[1], pal.a[2], 0.0];
+    params.palette_params.b = [pal.b[0], pal.b[1], pal.b[2], 0.0];
+    params.palette_params.c = [pal.c[0], pal.c[1], pal.c[2], 0.0];
+    params.palette_params.d = [pal.d[0], pal.d[1], pal.d[2], 0.0];
+    params.palette_lut_path = pal.lut_path.clone();
+}
+
+pub(crate) fn save_preset(path: &Path, preset: &Preset) -> Result<(), Box<dyn Error>> {
+    std::fs::write(path, toml::to_string_pretty(preset)?)?;
+    Ok(())
+}
+
+pub(crate) fn load_preset(path: &Path) -> Result<Preset, Box<dyn Error>> {
+    Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+}
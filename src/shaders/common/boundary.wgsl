@@ -0,0 +1,69 @@
+// BOUNDARY CONDITIONS
+// Prepended to every shader that moves agents or reads the pheremone field, so
+// movement, sensing, deposition and diffusion all agree on what lies past an edge.
+// Keep in sync with BoundaryMode in structs.rs
+const BOUNDARY_WRAP: u32 = 0u;
+const BOUNDARY_REFLECT: u32 = 1u;
+const BOUNDARY_CLAMP: u32 = 2u;
+const BOUNDARY_ABSORB: u32 = 3u;
+
+// Agents live in 0.0 -> 1.0, keep them strictly below the upper edge
+const EDGE_EPSILON: f32 = 0.00001;
+
+struct BoundedTexel {
+  coord: vec2<i32>,
+  // 0.0 when the texel lies outside an absorbing boundary
+  inside: f32,
+}
+
+struct BoundedAgent {
+  pos: vec2<f32>,
+  vel: vec2<f32>,
+  // Set when an absorbing boundary swallowed the agent
+  absorbed: bool,
+}
+
+fn bound_texel(coord: vec2<i32>, dims: vec2<i32>, mode: u32) -> BoundedTexel {
+  switch mode {
+    case BOUNDARY_WRAP: {
+      return BoundedTexel(((coord % dims) + dims) % dims, 1.0);
+    }
+    case BOUNDARY_REFLECT: {
+      let period = dims * 2;
+      let m = ((coord % period) + period) % period;
+      return BoundedTexel(select(m, period - 1 - m, m >= dims), 1.0);
+    }
+    case BOUNDARY_ABSORB: {
+      let inside = all(coord >= vec2(0)) && all(coord < dims);
+      return BoundedTexel(clamp(coord, vec2(0), dims - 1), select(0.0, 1.0, inside));
+    }
+    default: {
+      return BoundedTexel(clamp(coord, vec2(0), dims - 1), 1.0);
+    }
+  }
+}
+
+fn bound_agent(pos: vec2<f32>, vel: vec2<f32>, mode: u32) -> BoundedAgent {
+  switch mode {
+    case BOUNDARY_WRAP: {
+      return BoundedAgent(fract(pos), vel, false);
+    }
+    case BOUNDARY_REFLECT: {
+      var p = pos;
+      var v = vel;
+      let below = p < vec2(0.0);
+      let above = p >= vec2(1.0);
+      p = select(p, -p, below);
+      p = select(p, 2.0 - p, above);
+      v = select(v, -v, below | above);
+      return BoundedAgent(clamp(p, vec2(0.0), vec2(1.0 - EDGE_EPSILON)), v, false);
+    }
+    case BOUNDARY_ABSORB: {
+      let outside = any(pos < vec2(0.0)) || any(pos >= vec2(1.0));
+      return BoundedAgent(pos, vel, outside);
+    }
+    default: {
+      return BoundedAgent(clamp(pos, vec2(0.0), vec2(1.0 - EDGE_EPSILON)), vel, false);
+    }
+  }
+}
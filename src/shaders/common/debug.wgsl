@@ -0,0 +1,16 @@
+// DEBUG CHANNELS
+// Named values for the host to read back on request. A channel is a run of
+// vec4s in one buffer, one per agent or a single one. The DEBUG_* slot
+// constants, and DEBUG_AGENT_SLOTS, are generated from DEBUG_CHANNELS in
+// debug/debug_functions.rs and prepended when the shaders are built.
+@group(0) @binding(8) var<storage, read_write> debug_channels: array<vec4<f32>>;
+
+fn debug_agent(channel: u32, agent: u32, value: vec4<f32>) {
+  if (agent < DEBUG_AGENT_SLOTS) {
+    debug_channels[channel + agent] = value;
+  }
+}
+
+fn debug_value(channel: u32, value: vec4<f32>) {
+  debug_channels[channel] = value;
+}
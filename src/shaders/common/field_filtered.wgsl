@@ -0,0 +1,10 @@
+// PHEREMONE FIELD SAMPLING, HARDWARE FILTERED
+@group(3) @binding(0)
+var phm: texture_2d<f32>;
+@group(3) @binding(1)
+var phm_sampler: sampler;
+
+fn sample_field(uv: vec2<f32>) -> vec4<f32> {
+  return textureSample(phm, phm_sampler, uv);
+}
+
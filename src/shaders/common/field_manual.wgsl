@@ -0,0 +1,21 @@
+// PHEREMONE FIELD SAMPLING, MANUAL BILINEAR
+// For adapters without FLOAT32_FILTERABLE. Matches a linear, clamp to edge
+// sampler.
+@group(3) @binding(0)
+var phm: texture_2d<f32>;
+
+fn sample_field(uv: vec2<f32>) -> vec4<f32> {
+  let dims = vec2<i32>(textureDimensions(phm));
+  let texel = uv * vec2<f32>(dims) - 0.5;
+  let base = vec2<i32>(floor(texel));
+  let f = fract(texel);
+  let max_coord = dims - 1;
+
+  let t00 = textureLoad(phm, clamp(base, vec2(0), max_coord), 0);
+  let t10 = textureLoad(phm, clamp(base + vec2(1, 0), vec2(0), max_coord), 0);
+  let t01 = textureLoad(phm, clamp(base + vec2(0, 1), vec2(0), max_coord), 0);
+  let t11 = textureLoad(phm, clamp(base + vec2(1, 1), vec2(0), max_coord), 0);
+
+  return mix(mix(t00, t10, f.x), mix(t01, t11, f.x), f.y);
+}
+
@@ -0,0 +1,35 @@
+// PHEREMONE FIELD, PING-PONG
+// For adapters without read-write storage textures. The field is read from
+// one texture and written to the other, the Rust side swaps them each frame.
+// Agents can't write the field they read, so deposits are accumulated as
+// fixed point in a buffer and folded in by the diffusion pass.
+@group(2) @binding(0) var phm: texture_2d<f32>;
+@group(2) @binding(1) var phm_out: texture_storage_2d<rgba32float, write>;
+@group(2) @binding(2) var<storage, read_write> phm_deposits: array<atomic<u32>>;
+
+const PHM_DEPOSIT_SCALE: f32 = 65536.0;
+
+fn phm_deposit_index(coord: vec2<i32>) -> u32 {
+  return u32(coord.y) * textureDimensions(phm).x + u32(coord.x);
+}
+
+fn phm_load(coord: vec2<i32>) -> vec4<f32> {
+  return textureLoad(phm, coord, 0);
+}
+
+fn phm_deposit(coord: vec2<i32>, amount: f32) {
+  atomicAdd(&phm_deposits[phm_deposit_index(coord)], u32(amount * PHM_DEPOSIT_SCALE));
+}
+
+// Consumes the deposits made at coord since the last diffusion pass
+fn phm_load_with_deposits(coord: vec2<i32>) -> vec4<f32> {
+  let deposit = atomicExchange(&phm_deposits[phm_deposit_index(coord)], 0u);
+  var texel = textureLoad(phm, coord, 0);
+  texel.r += f32(deposit) / PHM_DEPOSIT_SCALE;
+  return texel;
+}
+
+fn phm_store(coord: vec2<i32>, value: vec4<f32>) {
+  textureStore(phm_out, coord, value);
+}
+
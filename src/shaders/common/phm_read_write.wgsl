@@ -0,0 +1,23 @@
+// PHEREMONE FIELD, READ-WRITE STORAGE
+// The field is updated in place, deposits land straight in the texture.
+@group(2) @binding(0) var phm: texture_storage_2d<rgba32float, read_write>;
+
+fn phm_load(coord: vec2<i32>) -> vec4<f32> {
+  return textureLoad(phm, coord);
+}
+
+fn phm_deposit(coord: vec2<i32>, amount: f32) {
+  var texel = textureLoad(phm, coord);
+  texel.r += amount;
+  textureStore(phm, coord, texel);
+}
+
+// Deposits are already part of the field
+fn phm_load_with_deposits(coord: vec2<i32>) -> vec4<f32> {
+  return textureLoad(phm, coord);
+}
+
+fn phm_store(coord: vec2<i32>, value: vec4<f32>) {
+  textureStore(phm, coord, value);
+}
+
@@ -0,0 +1,56 @@
+// RANDOM NUMBERS
+// Hybrid Tausworthe generator shared by every shader that needs randomness.
+// Each agent carries its own state in Slime.rng, so draws are decorrelated
+// between agents and reproducible from SimParams.seed.
+struct RandomResult {
+    state: vec4<u32>,
+    value: f32,
+};
+
+fn taus_step(z: u32, S1: u32, S2: u32, S3: u32, M: u32) -> u32 {
+    let b = (((z << S1) ^ z) >> S2);
+    return ((z & M) << S3) ^ b;
+}
+
+fn lcg_step(z: u32, A: u32, C: u32) -> u32 {
+    return A * z + C;
+}
+
+fn hybrid_taus(st: vec4<u32>) -> RandomResult {
+    var state = st; 
+    state.x = taus_step(state.x, 13u, 19u, 12u, 4294967294u);
+    state.y = taus_step(state.y, 2u, 25u, 4u, 4294967288u);
+    state.z = taus_step(state.z, 3u, 11u, 17u, 4294967280u);
+    state.w = lcg_step(state.w, 1664525u, 1013904223u);
+
+    var rand: RandomResult;
+    rand.state = state;
+    rand.value = f32(state.x ^ state.y ^ state.z ^ state.w) / f32(0xFFFFFFFFu);
+
+    return rand;
+}
+
+fn pcg_hash(v: u32) -> u32 {
+  let state = v * 747796405u + 2891336453u;
+  let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
+}
+
+// Initial generator state for one agent. The Tausworthe components
+// degenerate below 2, 8 and 16, so keep them above that.
+fn rng_seed(id: u32, seed: u32) -> vec4<u32> {
+  let h = pcg_hash(id ^ pcg_hash(seed));
+  return vec4<u32>(
+    max(pcg_hash(h), 128u),
+    max(pcg_hash(h + 1u), 128u),
+    max(pcg_hash(h + 2u), 128u),
+    pcg_hash(h + 3u),
+  );
+}
+
+// Uniform 0.0 -> 1.0, advances the state in place
+fn rng_next(state: ptr<function, vec4<u32>>) -> f32 {
+  let r = hybrid_taus(*state);
+  *state = r.state;
+  return r.value;
+}
@@ -1,28 +1,24 @@
-const NUM_AGENTS: u32 = 256u;
-const INT_NUM_AGENTS: i32 = 256;
 const NUM_PREDATORS: u32 = 4u;
 
-const MAX_SCREEN: f32 = 1.0;
-const MIN_SCREEN: f32 = 0.0;
 const SCREEN_WIDTH: f32 = 1376.0;
 const SCREEN_HEIGHT: f32 = 768.0;
 const I_SCREEN_WIDTH: i32 = 1376;
 const I_SCREEN_HEIGHT: i32 = 768;
-const SCREEN_BUFFER: f32 = 0.001;
-const MIN_POSITIVE_F32: f32 = 0x1.0p-126f;
+const I_SCREEN: vec2<i32> = vec2(I_SCREEN_WIDTH, I_SCREEN_HEIGHT);
+const SCREEN: vec2<f32> = vec2(SCREEN_WIDTH, SCREEN_HEIGHT);
+
+// Keep in sync with AgentModel in structs.rs
+const AGENT_MODEL_VELOCITY: u32 = 0u;
+const AGENT_MODEL_JONES: u32 = 1u;
 
-struct Debug {
-  d1: vec4<f32>,
-  d2: vec4<f32>,
-  d3: vec4<f32>,
-  d4: vec4<f32>,
-}
 struct Slime {
   pos: vec2<f32>,
   vel: vec2<f32>,
   s1_pos: vec2<f32>,
   s2_pos: vec2<f32>,
   s3_pos: vec2<f32>,
+  moved_forward: f32,
+  rng: vec4<u32>,
 }
 struct SlimeParams {
   max_velocity: f32,
@@ -38,6 +34,22 @@ struct PheremoneParams {
   diffusion_factor: f32,
   decay_factor: f32,
 }
+struct JonesParams {
+  sensor_angle: f32,
+  rotation_angle: f32,
+  sensor_offset: f32,
+  sensor_width: f32,
+  step_size: f32,
+}
+struct SimParams {
+  seed: u32,
+  boundary_mode: u32,
+  agent_model: u32,
+}
+struct SourceTexel {
+  attractant: f32,
+  land: f32,
+}
 struct TimeUniform {
   time: f32,
 }
@@ -49,13 +61,15 @@ struct ConstsUniform {
 @group(0) @binding(0) var<storage, read_write> agents: array<Slime>;
 @group(0) @binding(1) var<storage, read_write> sp: SlimeParams;
 @group(0) @binding(2) var<storage, read_write> pp: PheremoneParams;
-@group(0) @binding(8) var<storage, read_write> debug_arr: array<vec4<f32>, NUM_AGENTS>;
-@group(0) @binding(9) var<storage, read_write> debug: Debug;
+@group(0) @binding(3) var<storage, read_write> sim: SimParams;
+@group(0) @binding(4) var<storage, read_write> jp: JonesParams;
+@group(0) @binding(5) var<storage, read> sources: array<SourceTexel>;
+// group(0) binding(8) is the debug channels, see common/debug.wgsl
 
 @group(1) @binding(0) var<uniform> tu: TimeUniform;
 @group(1) @binding(1) var<uniform> cu: ConstsUniform;
 
-@group(2) @binding(0) var phm: texture_storage_2d<rgba32float, read_write>;
+// group(2) is the pheremone field, see common/phm_*.wgsl
 
 fn clamp_and_scale_velocity(agent: Slime) -> vec2<f32> {
   let magnitude: f32 = length(agent.vel);
@@ -71,18 +85,15 @@ fn calculate_heading(vel: vec2<f32>) -> f32 {
  return atan2(norm.y, norm.x);
 }
 
-fn clamp_coord(tex_coord: vec2<i32>, i: i32, j: i32) -> vec2<i32> {
-  return vec2<i32>(
-    max(0, min(tex_coord.x + i, I_SCREEN_WIDTH)),
-    max(0, min(tex_coord.y + j, I_SCREEN_HEIGHT)),
-  );
+fn sensor_coord(tex_coord: vec2<i32>, i: i32, j: i32) -> BoundedTexel {
+  return bound_texel(tex_coord + vec2(i, j), I_SCREEN, sim.boundary_mode);
 }
 
 fn map_to_screen_coords(agent_pos: vec2<f32>) -> vec2<i32> {
   // Convert normalized coordinates to screen coordinates
-  let screen_pos: vec2<f32> = agent_pos * vec2<f32>(SCREEN_WIDTH, SCREEN_HEIGHT);
+  let screen_pos: vec2<f32> = floor(agent_pos * vec2<f32>(SCREEN_WIDTH, SCREEN_HEIGHT));
   
-  return vec2(i32(screen_pos.x), i32(screen_pos.y));
+  return bound_texel(vec2(i32(screen_pos.x), i32(screen_pos.y)), I_SCREEN, sim.boundary_mode).coord;
 }
 // SLIME SENSORS
 fn sensor_position(agent: Slime, heading: f32, offset: f32) -> vec2<f32> {
@@ -95,27 +106,28 @@ fn sensor_position(agent: Slime, heading: f32, offset: f32) -> vec2<f32> {
   );
 }
 
-fn calculate_sensor_positions(agent: Slime, id: u32) {
-  let heading = calculate_heading(agent.vel);
-  agents[id].s1_pos = sensor_position(agent, heading, -sp.sensor_offset);
-  agents[id].s2_pos = sensor_position(agent, heading, 0.0);
-  agents[id].s3_pos = sensor_position(agent, heading, sp.sensor_offset);
+fn calculate_sensor_positions(agent: Slime) -> Slime {
+  var a = agent;
+  let heading = calculate_heading(a.vel);
+  a.s1_pos = sensor_position(a, heading, -sp.sensor_offset);
+  a.s2_pos = sensor_position(a, heading, 0.0);
+  a.s3_pos = sensor_position(a, heading, sp.sensor_offset);
+  return a;
 }
 
 
 fn pheremone_deposition(agent_pos: vec2<f32>, moved_forward: f32) {
   let agent_sc = map_to_screen_coords(agent_pos);
-  var texel = textureLoad(phm, agent_sc);
-  texel.r += pp.deposition_amount;
-  textureStore(phm, agent_sc, texel);
+  phm_deposit(agent_sc, pp.deposition_amount);
 }
 
 struct QuiescenceResult {
   direction: vec2<f32>,
   moved_forward: f32,
+  totals: vec3<f32>,
 }
 
-fn quiescence(agent: Slime, id: u32) -> QuiescenceResult {
+fn quiescence(agent: Slime, rng: ptr<function, vec4<u32>>) -> QuiescenceResult {
   var s1_total: f32 = 0.0;
   var s2_total: f32 = 0.0;
   var s3_total: f32 = 0.0;
@@ -123,25 +135,25 @@ fn quiescence(agent: Slime, id: u32) -> QuiescenceResult {
   let s_radius = i32(sp.sensor_radius*SCREEN_HEIGHT);
 
   // Calculate the positions to sample
-  let s1_tex_coord = vec2<i32>(agent.s1_pos * vec2(SCREEN_WIDTH, SCREEN_HEIGHT));
-  let s2_tex_coord = vec2<i32>(agent.s2_pos * vec2(SCREEN_WIDTH, SCREEN_HEIGHT));
-  let s3_tex_coord = vec2<i32>(agent.s3_pos * vec2(SCREEN_WIDTH, SCREEN_HEIGHT));
+  let s1_tex_coord = vec2<i32>(floor(agent.s1_pos * vec2(SCREEN_WIDTH, SCREEN_HEIGHT)));
+  let s2_tex_coord = vec2<i32>(floor(agent.s2_pos * vec2(SCREEN_WIDTH, SCREEN_HEIGHT)));
+  let s3_tex_coord = vec2<i32>(floor(agent.s3_pos * vec2(SCREEN_WIDTH, SCREEN_HEIGHT)));
 
   for (var i: i32 = -s_radius; i <= s_radius; i++) {
     for (var j: i32 = -s_radius; j <= s_radius; j++) {
-      let s1_coord: vec2<i32> = clamp_coord(s1_tex_coord, i, j);
-      let s2_coord: vec2<i32> = clamp_coord(s2_tex_coord, i, j);
-      let s3_coord: vec2<i32> = clamp_coord(s3_tex_coord, i, j);
+      let s1_coord = sensor_coord(s1_tex_coord, i, j);
+      let s2_coord = sensor_coord(s2_tex_coord, i, j);
+      let s3_coord = sensor_coord(s3_tex_coord, i, j);
 
       // Sample the texture
-      let s1_sample = textureLoad(phm, s1_coord);
-      let s2_sample = textureLoad(phm, s2_coord);
-      let s3_sample = textureLoad(phm, s3_coord);
-
-      // Add to totals
-      s1_total += s1_sample.r;
-      s2_total += s2_sample.r;
-      s3_total += s3_sample.r;
+      let s1_sample = phm_load(s1_coord.coord);
+      let s2_sample = phm_load(s2_coord.coord);
+      let s3_sample = phm_load(s3_coord.coord);
+
+      // Add to totals, nothing is sensed past an absorbing edge
+      s1_total += s1_sample.r * s1_coord.inside;
+      s2_total += s2_sample.r * s2_coord.inside;
+      s3_total += s3_sample.r * s3_coord.inside;
     }
   }
 
@@ -155,7 +167,7 @@ fn quiescence(agent: Slime, id: u32) -> QuiescenceResult {
   let s3_dir = agent.s3_pos - agent.pos;
 
   if (max_total == s1_total && s1_total == s3_total) {
-    dv = mix(mix(s1_dir, s2_dir, sin(tu.time*0.3179)), s3_dir, sin(tu.time*0.3432));
+    dv = mix(mix(s1_dir, s2_dir, rng_next(rng)), s3_dir, rng_next(rng));
   } else if (max_total == s1_total) {
     dv = normalize(s1_dir);
   } else if (max_total == s2_total) {
@@ -168,16 +180,17 @@ fn quiescence(agent: Slime, id: u32) -> QuiescenceResult {
   return QuiescenceResult(
     dv*sp.turn_factor,
     moved_forward,
+    vec3(s1_total, s2_total, s3_total),
   );
 }
 
-fn avoid_collisions(agent: Slime, agent_id: u32) -> vec2<f32> {
+fn avoid_collisions(agent: Slime, agent_id: u32, rng: ptr<function, vec4<u32>>) -> vec2<f32> {
   var dv: vec2<f32> = vec2(0.0);
   let int_agent_id = i32(agent_id);
 
-  for (var i: i32 = 0; i < INT_NUM_AGENTS; i++) {
+  for (var i: i32 = 0; i < i32(NUM_AGENTS); i++) {
     let dist: f32 = distance(agent.pos, agents[i].pos);
-    let rnd: f32 = 2.0 * sin((tu.time * 0.1) + dot(agent.vel, agents[i].vel)) - 1.0;
+    let rnd: f32 = 2.0 * rng_next(rng) - 1.0;
     let in_range: f32 = step(dist, sp.avoid_factor);
     let not_self: f32 = step(0.0, f32(abs(int_agent_id - i)));
      
@@ -187,45 +200,157 @@ fn avoid_collisions(agent: Slime, agent_id: u32) -> vec2<f32> {
   return dv;
 }
 
-fn respect_screen_edges(agent: Slime, id: vec2<u32>) -> vec2<f32> {
-  var dp = agent.pos;
+// Absorbed agents are re-emitted at a random position with a random heading
+fn respawn(agent: Slime, rng: ptr<function, vec4<u32>>) -> Slime {
+  var a = agent;
+  let angle = rng_next(rng) * 6.2831853;
+  a.pos = vec2(rng_next(rng), rng_next(rng)) * (1.0 - EDGE_EPSILON);
+  a.vel = vec2(cos(angle), sin(angle)) * sp.max_velocity;
+  return a;
+}
 
-  let minx = step(dp.x, MIN_SCREEN);
-  let maxx = step(MAX_SCREEN, dp.x);
+fn on_land(pos: vec2<f32>) -> bool {
+  let coord = map_to_screen_coords(pos);
+  return sources[coord.y * I_SCREEN_WIDTH + coord.x].land > 0.0;
+}
 
-  let miny = step(dp.y, MIN_SCREEN);
-  let maxy = step(MAX_SCREEN, dp.y);
-  
-  let dpx = minx*(MAX_SCREEN - 0.001)
-    + maxx*(MIN_SCREEN + 0.001)
-    + dp.x*step(minx + maxx, MIN_POSITIVE_F32);
+// Agents turn back at water, one that is already stranded is re-emitted
+fn respect_land(agent: Slime, previous: Slime, rng: ptr<function, vec4<u32>>) -> Slime {
+  if (on_land(agent.pos)) {
+    return agent;
+  }
+  if (!on_land(previous.pos)) {
+    return respawn(agent, rng);
+  }
 
-  let dpy = miny*(MAX_SCREEN - 0.001)
-    + maxy*(MIN_SCREEN + 0.001)
-    + dp.y*step(miny + maxy, MIN_POSITIVE_F32);
+  var a = agent;
+  a.pos = previous.pos;
+  a.vel = -agent.vel;
+  return a;
+}
+
+fn respect_screen_edges(agent: Slime, rng: ptr<function, vec4<u32>>) -> Slime {
+  var a = agent;
+  let bounded = bound_agent(a.pos, a.vel, sim.boundary_mode);
+
+  if (bounded.absorbed) {
+    return respawn(a, rng);
+  }
 
-  return vec2<f32>(dpx, dpy);
+  a.pos = bounded.pos;
+  a.vel = bounded.vel;
+  return a;
 }
 
-@compute 
-@workgroup_size(16, 16, 1) 
-fn update_slime_positions(@builtin(global_invocation_id) id: vec3<u32>) {
-  var agent = agents[id.x];
-  calculate_sensor_positions(agent, id.x);
+// JONES (2010) MODEL
+// Headings and distances are measured in texels so the sensor pattern stays
+// isotropic on a non-square field, then converted back to 0.0 -> 1.0 space.
+fn jones_sense(pos: vec2<f32>, heading: f32, angle: f32) -> f32 {
+  let dir = vec2(cos(heading + angle), sin(heading + angle));
+  let centre = vec2<i32>(floor(pos * SCREEN + dir * jp.sensor_offset));
+  let half_width = i32(max(jp.sensor_width, 1.0) * 0.5);
+
+  var total: f32 = 0.0;
+  for (var i: i32 = -half_width; i <= half_width; i++) {
+    for (var j: i32 = -half_width; j <= half_width; j++) {
+      let coord = sensor_coord(centre, i, j);
+      total += phm_load(coord.coord).r * coord.inside;
+    }
+  }
+
+  return total;
+}
+
+fn jones_sensor_position(pos: vec2<f32>, heading: f32, angle: f32) -> vec2<f32> {
+  let dir = vec2(cos(heading + angle), sin(heading + angle));
+  return pos + dir * jp.sensor_offset / SCREEN;
+}
+
+struct StepResult {
+  agent: Slime,
+  moved_forward: f32,
+  // What the s1, s2 and s3 sensors picked up
+  sensed: vec3<f32>,
+}
+
+fn jones_step(agent: Slime, rng: ptr<function, vec4<u32>>) -> StepResult {
+  var a = agent;
+  var moved_forward = 0.0;
+  var heading = calculate_heading(a.vel * SCREEN);
+
+  let fl = jones_sense(a.pos, heading, jp.sensor_angle);
+  let f = jones_sense(a.pos, heading, 0.0);
+  let fr = jones_sense(a.pos, heading, -jp.sensor_angle);
+
+  if (f > fl && f > fr) {
+    // Keep heading
+    moved_forward = 1.0;
+  } else if (f < fl && f < fr) {
+    // Both sides beat the front, pick one at random
+    heading += select(-jp.rotation_angle, jp.rotation_angle, rng_next(rng) < 0.5);
+  } else if (fl < fr) {
+    heading -= jp.rotation_angle;
+  } else if (fr < fl) {
+    heading += jp.rotation_angle;
+  }
+
+  a.s1_pos = jones_sensor_position(a.pos, heading, -jp.sensor_angle);
+  a.s2_pos = jones_sensor_position(a.pos, heading, 0.0);
+  a.s3_pos = jones_sensor_position(a.pos, heading, jp.sensor_angle);
+  a.vel = vec2(cos(heading), sin(heading)) * jp.step_size / SCREEN;
+  a.pos += a.vel;
+
+  return StepResult(a, moved_forward, vec3(fr, f, fl));
+}
+
+fn velocity_step(agent: Slime, rng: ptr<function, vec4<u32>>) -> StepResult {
+  var a = calculate_sensor_positions(agent);
   
   // Sense pheremones
-  let qr = quiescence(agent, id.x);
-  agent.vel += qr.direction;
+  let qr = quiescence(a, rng);
+  a.vel += qr.direction;
   //agents[id.x].vel += avoid_collisions(agents[id.x], id.x);
 
-  agent.vel = clamp_and_scale_velocity(agent);
+  a.vel = clamp_and_scale_velocity(a);
 
   // Move
-  agent.pos += agent.vel;
-  agent.pos = respect_screen_edges(agent, id.xy);
+  a.pos += a.vel;
+
+  return StepResult(a, qr.moved_forward, qr.totals);
+}
+
+@compute 
+@workgroup_size(AGENT_WORKGROUP_SIZE, 1, 1)
+fn update_slime_positions(@builtin(global_invocation_id) id: vec3<u32>) {
+  // The last workgroup can run past the end of the agents
+  if (id.x >= NUM_AGENTS) {
+    return;
+  }
+
+  var step: StepResult;
+  var rng = agents[id.x].rng;
+
+  if (sim.agent_model == AGENT_MODEL_JONES) {
+    step = jones_step(agents[id.x], &rng);
+  } else {
+    step = velocity_step(agents[id.x], &rng);
+  }
+
+  var agent = respect_land(respect_screen_edges(step.agent, &rng), agents[id.x], &rng);
+  agent.moved_forward = step.moved_forward;
+  agent.rng = rng;
 
   // Deposit Pheremones
-  pheremone_deposition(agent.pos, qr.moved_forward);
+  pheremone_deposition(agent.pos, step.moved_forward);
+
+  let motion = agent.vel * SCREEN;
+  let moved = distance(agent.pos * SCREEN, agents[id.x].pos * SCREEN);
+  debug_agent(DEBUG_SENSOR_TOTALS, id.x, vec4(step.sensed, step.moved_forward));
+  debug_agent(
+    DEBUG_AGENT_MOTION,
+    id.x,
+    vec4(atan2(motion.y, motion.x), length(motion), moved, select(0.0, 1.0, on_land(agent.pos))),
+  );
 
   agents[id.x] = agent;
 }
@@ -0,0 +1,166 @@
+// SIMULATION STATISTICS
+// Two pass reduction. reduce_field sums each 16x16 tile of the field into a
+// partial, finish_stats folds the partials together and reduces the agents in
+// a single workgroup.
+
+const WORKGROUP_SIZE: u32 = 256u;
+// Keep in sync with SimStats in structs.rs
+const HEADING_BINS: u32 = 16u;
+
+struct Slime {
+  pos: vec2<f32>,
+  vel: vec2<f32>,
+  s1_pos: vec2<f32>,
+  s2_pos: vec2<f32>,
+  s3_pos: vec2<f32>,
+  moved_forward: f32,
+  rng: vec4<u32>,
+}
+struct StatsParams {
+  coverage_threshold: f32,
+}
+struct FieldPartial {
+  mass: f32,
+  max_mass: f32,
+  covered: f32,
+}
+struct SimStats {
+  total_mass: f32,
+  max_mass: f32,
+  coverage: f32,
+  mean_speed: f32,
+  speed_variance: f32,
+  heading_entropy: f32,
+  forward_fraction: f32,
+}
+
+@group(0) @binding(0) var<storage, read> agents: array<Slime>;
+@group(0) @binding(1) var<storage, read> stp: StatsParams;
+@group(0) @binding(2) var<storage, read_write> partials: array<FieldPartial>;
+@group(0) @binding(3) var<storage, read_write> stats: SimStats;
+
+// group(1) is the uniforms, unused here but part of the layout
+// group(2) is the pheremone field, see common/phm_*.wgsl
+
+var<workgroup> wg_sum: array<f32, WORKGROUP_SIZE>;
+var<workgroup> wg_max: array<f32, WORKGROUP_SIZE>;
+var<workgroup> wg_count: array<f32, WORKGROUP_SIZE>;
+var<workgroup> wg_speed: array<f32, WORKGROUP_SIZE>;
+var<workgroup> wg_speed_sq: array<f32, WORKGROUP_SIZE>;
+var<workgroup> wg_forward: array<f32, WORKGROUP_SIZE>;
+var<workgroup> wg_headings: array<atomic<u32>, HEADING_BINS>;
+
+// Tree reduction of the wg_* arrays into slot 0
+fn reduce_workgroup(local_index: u32, with_agents: bool) {
+  for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+    workgroupBarrier();
+    if (local_index < stride) {
+      let other = local_index + stride;
+      wg_sum[local_index] += wg_sum[other];
+      wg_max[local_index] = max(wg_max[local_index], wg_max[other]);
+      wg_count[local_index] += wg_count[other];
+      if (with_agents) {
+        wg_speed[local_index] += wg_speed[other];
+        wg_speed_sq[local_index] += wg_speed_sq[other];
+        wg_forward[local_index] += wg_forward[other];
+      }
+    }
+  }
+  workgroupBarrier();
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn reduce_field(
+  @builtin(global_invocation_id) id: vec3<u32>,
+  @builtin(workgroup_id) wg_id: vec3<u32>,
+  @builtin(num_workgroups) num_wg: vec3<u32>,
+  @builtin(local_invocation_index) local_index: u32,
+) {
+  let dims = textureDimensions(phm);
+
+  var mass = 0.0;
+  if (all(id.xy < dims)) {
+    mass = phm_load(vec2<i32>(id.xy)).r;
+  }
+
+  wg_sum[local_index] = mass;
+  wg_max[local_index] = mass;
+  wg_count[local_index] = select(0.0, 1.0, mass > stp.coverage_threshold);
+
+  reduce_workgroup(local_index, false);
+
+  if (local_index == 0u) {
+    partials[wg_id.y * num_wg.x + wg_id.x] = FieldPartial(wg_sum[0], wg_max[0], wg_count[0]);
+  }
+}
+
+@compute
+@workgroup_size(256, 1, 1)
+fn finish_stats(@builtin(local_invocation_index) local_index: u32) {
+  var mass = 0.0;
+  var max_mass = 0.0;
+  var covered = 0.0;
+  for (var i = local_index; i < arrayLength(&partials); i += WORKGROUP_SIZE) {
+    let partial = partials[i];
+    mass += partial.mass;
+    max_mass = max(max_mass, partial.max_mass);
+    covered += partial.covered;
+  }
+
+  if (local_index < HEADING_BINS) {
+    atomicStore(&wg_headings[local_index], 0u);
+  }
+  workgroupBarrier();
+
+  // Speeds and headings in texels per step, so both agent models compare
+  let dims = vec2<f32>(textureDimensions(phm));
+  var speed = 0.0;
+  var speed_sq = 0.0;
+  var forward = 0.0;
+  for (var i = local_index; i < arrayLength(&agents); i += WORKGROUP_SIZE) {
+    let vel = agents[i].vel * dims;
+    let s = length(vel);
+    speed += s;
+    speed_sq += s * s;
+    forward += agents[i].moved_forward;
+
+    let turn = (atan2(vel.y, vel.x) + 3.14159265) / 6.2831853;
+    let bin = min(u32(turn * f32(HEADING_BINS)), HEADING_BINS - 1u);
+    atomicAdd(&wg_headings[bin], 1u);
+  }
+
+  wg_sum[local_index] = mass;
+  wg_max[local_index] = max_mass;
+  wg_count[local_index] = covered;
+  wg_speed[local_index] = speed;
+  wg_speed_sq[local_index] = speed_sq;
+  wg_forward[local_index] = forward;
+
+  reduce_workgroup(local_index, true);
+
+  if (local_index == 0u) {
+    let texels = dims.x * dims.y;
+    let count = f32(arrayLength(&agents));
+    let mean_speed = wg_speed[0] / count;
+
+    // Shannon entropy of the heading histogram, 1.0 when headings are uniform
+    var entropy = 0.0;
+    for (var i = 0u; i < HEADING_BINS; i++) {
+      let p = f32(atomicLoad(&wg_headings[i])) / count;
+      if (p > 0.0) {
+        entropy -= p * log(p);
+      }
+    }
+
+    stats = SimStats(
+      wg_sum[0],
+      wg_max[0],
+      wg_count[0] / texels,
+      mean_speed,
+      max(wg_speed_sq[0] / count - mean_speed * mean_speed, 0.0),
+      entropy / log(f32(HEADING_BINS)),
+      wg_forward[0] / count,
+    );
+  }
+}
@@ -1,15 +1,9 @@
-const NUM_AGENTS: u32 = 256u;
 const SCREEN_WIDTH: f32 = 1376.0;
 const SCREEN_HEIGHT: f32 = 768.0;
 const I_SCREEN_WIDTH: i32 = 1376;
 const I_SCREEN_HEIGHT: i32 = 768;
+const I_SCREEN: vec2<i32> = vec2(I_SCREEN_WIDTH, I_SCREEN_HEIGHT);
 
-struct Debug {
-    d1: vec4<f32>,
-    d2: vec4<f32>,
-    d3: vec4<f32>,
-    d4: vec4<f32>,
-};
 struct TimeUniform {
   time: f32,
 }
@@ -26,6 +20,8 @@ struct Slime {
   s1_pos: vec2<f32>,
   s2_pos: vec2<f32>,
   s3_pos: vec2<f32>,
+  moved_forward: f32,
+  rng: vec4<u32>,
 }
 struct SlimeParams {
   max_velocity: f32,
@@ -41,20 +37,29 @@ struct PheremoneParams {
   diffusion_factor: f32,
   decay_factor: f32,
 }
+struct SimParams {
+  seed: u32,
+  boundary_mode: u32,
+  agent_model: u32,
+}
+struct SourceTexel {
+  attractant: f32,
+  land: f32,
+}
 
 // COMPUTE GROUP
 @group(0) @binding(0) var<storage, read_write> agents: array<Slime>;
 @group(0) @binding(1) var<storage, read_write> sp: SlimeParams;
 @group(0) @binding(2) var<storage, read_write> pp: PheremoneParams;
-@group(0) @binding(8) var<storage, read_write> debug_arr: array<vec4<f32>>;
-@group(0) @binding(9)
-var<storage, read_write> debug: Debug;
+@group(0) @binding(3) var<storage, read_write> sim: SimParams;
+@group(0) @binding(5) var<storage, read> sources: array<SourceTexel>;
+// group(0) binding(8) is the debug channels, see common/debug.wgsl
 
 @group(1) @binding(0) var<uniform> tu: TimeUniform;
 @group(1) @binding(1) var<uniform> cu: ConstsUniform;
 
 // TEXTURE GROUP
-@group(2) @binding(0) var phm: texture_storage_2d<rgba32float, read_write>;
+// group(2) is the pheremone field, see common/phm_*.wgsl
 
 // ASPECT RATIO
 const SCREEN: vec2<f32> = vec2(SCREEN_WIDTH, SCREEN_HEIGHT);
@@ -65,14 +70,11 @@ fn scale_tex_aspect(fc: vec2<f32>) -> vec2<f32> {
 }
 
 
-fn get_neighbour_coords(tex_coords: vec2<i32>, x: i32, y: i32) -> vec2<i32> {
-  return vec2<i32>(
-      max(0, min(I_SCREEN_WIDTH, tex_coords.x + x)),
-      max(0, min(I_SCREEN_HEIGHT, tex_coords.y + y)),
-  );
+fn get_neighbour_coords(tex_coords: vec2<i32>, x: i32, y: i32) -> BoundedTexel {
+  return bound_texel(tex_coords + vec2(x, y), I_SCREEN, sim.boundary_mode);
 }
 
-fn pheremone_diffusion(tex_coords: vec2<u32>) {
+fn pheremone_diffusion(tex_coords: vec2<u32>) -> f32 {
   var total_intensity: f32 = 0.0;
   var total_weight: f32 = 0.0;
   let txc_int = vec2<i32>(i32(tex_coords.x), i32(tex_coords.y));
@@ -80,13 +82,14 @@ fn pheremone_diffusion(tex_coords: vec2<u32>) {
   // Define the range of neighboring pixels to consider
   let range: i32 = 3; // Expensive
 
-  let tex_color: vec4<f32> = textureLoad(phm, tex_coords);
+  let tex_color: vec4<f32> = phm_load_with_deposits(txc_int);
 
   // Iterate over a range around the current pixel
   for (var x: i32 = -range; x <= range; x++) {
     for (var y: i32 = -range; y <= range; y++) {
       let neighbor_coords = get_neighbour_coords(txc_int, x, y);
-      let neighbor_color: vec4<f32> = textureLoad(phm, neighbor_coords);
+      // An absorbing edge has an empty field behind it
+      let neighbor_color: vec4<f32> = phm_load(neighbor_coords.coord) * neighbor_coords.inside;
 
       let distance_weight: f32 = 1.0 / (1.0 + distance(vec2<f32>(f32(x), f32(y)), vec2<f32>(0.0, 0.0)));
 
@@ -97,22 +100,31 @@ fn pheremone_diffusion(tex_coords: vec2<u32>) {
     
   let avg_intensity: f32 = total_intensity / total_weight;
 
-  textureStore(phm, tex_coords, vec4(max(tex_color.r, avg_intensity), 0.0, 0.0, 1.0));
+  return max(tex_color.r, avg_intensity);
 }
 
-fn pheremone_decay(tex_coords: vec2<u32>) {
-  let current_clr: vec4<f32> = textureLoad(phm, tex_coords);
-  let new_clr: f32 = max(0.0, current_clr.r*pp.decay_factor);
-
-  textureStore(phm, tex_coords, vec4(new_clr, 0.0, 0.0, 1.0));
+fn pheremone_decay(intensity: f32) -> f32 {
+  return max(0.0, intensity*pp.decay_factor);
 }
 
 @compute 
 @workgroup_size(32, 32, 1) 
 fn update_pheremone_heatmap(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (any(vec2<i32>(id.xy) >= I_SCREEN)) {
+    return;
+  }
+
   let tcf: vec2<f32> = vec2<f32>(f32(id.x), f32(id.y)); 
   var tex_uv: vec2<f32> = scale_tex_aspect(tcf);
   
-  pheremone_diffusion(id.xy);
-  pheremone_decay(id.xy);
+  // Food keeps adding attractant, water holds none
+  let source = sources[id.y * u32(I_SCREEN_WIDTH) + id.x];
+  let diffused = pheremone_diffusion(id.xy);
+  let decayed = pheremone_decay(diffused);
+  let intensity = (decayed + source.attractant) * source.land;
+
+  if (all(vec2<i32>(id.xy) == I_SCREEN / 2)) {
+    debug_value(DEBUG_TRAIL_CENTRE, vec4(diffused, decayed, source.attractant, source.land));
+  }
+  phm_store(vec2<i32>(id.xy), vec4(intensity, 0.0, 0.0, 1.0));
 }
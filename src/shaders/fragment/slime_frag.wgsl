@@ -1,21 +1,20 @@
 // CONSTANTS
 const PI: f32 = 3.14159265;
-const NUM_AGENTS: u32 = 256u;
 const NUM_PREDATORS: u32 = 4u;
 const SCREEN_WIDTH: f32 = 1376.0;
 const SCREEN_HEIGHT: f32 = 768.0;
 
-// STRUCTS
-struct Debug {
-    d1: vec4<f32>,
-    d2: vec4<f32>,
-    d3: vec4<f32>,
-    d4: vec4<f32>,
-};
+// Keep in sync with PaletteMode in structs.rs
+const PALETTE_RED: u32 = 0u;
+const PALETTE_COSINE: u32 = 1u;
+const PALETTE_VIRIDIS: u32 = 2u;
+const PALETTE_MAGMA: u32 = 3u;
+const PALETTE_INFERNO: u32 = 4u;
+const PALETTE_LUT: u32 = 5u;
 
+// STRUCTS
 struct VertexOutput {
     @builtin(position) frag_coord: vec4<f32>,
-    @location(0) tex_coord: vec2<f32>,
 };
 struct TimeUniform {
     time: f32,
@@ -31,12 +30,22 @@ struct ViewParams {
   zoom: f32,
   time_modifier: f32,
 }
+struct PaletteParams {
+  a: vec4<f32>,
+  b: vec4<f32>,
+  c: vec4<f32>,
+  d: vec4<f32>,
+  mode: u32,
+  scale: f32,
+}
 struct Slime {
   pos: vec2<f32>,
   vel: vec2<f32>,
   s1_pos: vec2<f32>,
   s2_pos: vec2<f32>,
   s3_pos: vec2<f32>,
+  moved_forward: f32,
+  rng: vec4<u32>,
 }
 struct SlimeParams {
   max_velocity: f32,
@@ -64,8 +73,6 @@ var<storage, read_write> agents: array<Slime>;
 var<storage, read_write> sp: SlimeParams;
 @group(0) @binding(2)
 var<storage, read_write> pp: PheremoneParams;
-@group(0) @binding(9)
-var<storage, read_write> debug: Debug;
 
 @group(1) @binding(0)
 var<uniform> tu: TimeUniform;
@@ -74,12 +81,12 @@ var<uniform> tu: TimeUniform;
 
 @group(2) @binding(0)
 var<storage, read_write> vp: ViewParams;
+@group(2) @binding(1)
+var<storage, read_write> pal: PaletteParams;
+@group(2) @binding(2)
+var palette_lut: texture_1d<f32>;
 
-@group(3) @binding(0)
-var phm: texture_2d<f32>;
-@group(3) @binding(1)
-var phm_sampler: sampler;
-
+// group(3) is the pheremone field, see common/field_*.wgsl
 
 // ASPECT RATIO
 const screen: vec2<f32> = vec2(SCREEN_WIDTH, SCREEN_HEIGHT);
@@ -91,13 +98,98 @@ fn scale_aspect(fc: vec2<f32>) -> vec2<f32> {
 }
 
 // COLORS
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+  let lo = c / 12.92;
+  let hi = pow((c + 0.055) / 1.055, vec3(2.4));
+  return select(hi, lo, c <= vec3(0.04045));
+}
+
 fn palette(t: f32) -> vec3<f32> {
-  let a: vec3<f32> = vec3<f32>(0.120, 0.618, 0.624); 
-  let b: vec3<f32> = vec3<f32>(0.878, 0.214, 0.229);
-  let c: vec3<f32> = vec3<f32>(0.654, 0.772, 0.426);
-  let d: vec3<f32> = vec3<f32>(0.937, 0.190, 0.152);
+  return pal.a.rgb + pal.b.rgb * cos(PI * 2.0 * (pal.c.rgb * t + pal.d.rgb));
+}
+
+// Polynomial fits of the matplotlib colormaps, after Matt Zucker's
+// https://www.shadertoy.com/view/WlfXRN
+fn colormap_poly(t: f32, c0: vec3<f32>, c1: vec3<f32>, c2: vec3<f32>, c3: vec3<f32>,
+                 c4: vec3<f32>, c5: vec3<f32>, c6: vec3<f32>) -> vec3<f32> {
+  return c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+}
+
+fn viridis(t: f32) -> vec3<f32> {
+  return colormap_poly(t,
+    vec3(0.2777273272234177, 0.005407344544966578, 0.3340998053353061),
+    vec3(0.1050930431085774, 1.404613529898575, 1.384590162594685),
+    vec3(-0.3308618287255563, 0.214847559468213, 0.09509516302823659),
+    vec3(-4.634230498983486, -5.799100973351585, -19.33244095627987),
+    vec3(6.228269936347081, 14.17993336680509, 56.69055260068105),
+    vec3(4.776384997670288, -13.74514537774601, -65.35303263337234),
+    vec3(-5.435455855934631, 4.645852612178535, 26.3124352495832),
+  );
+}
+
+fn magma(t: f32) -> vec3<f32> {
+  return colormap_poly(t,
+    vec3(-0.002136485053939582, -0.000749655052795221, -0.005386127855323933),
+    vec3(0.2516605407371642, 0.6775232436837668, 2.494026599312351),
+    vec3(8.353717279216625, -3.577719514958484, 0.3144679030132573),
+    vec3(-27.66873308576866, 14.26473078096533, -13.64921318813922),
+    vec3(52.17613981234068, -27.94360607168351, 12.94416944238394),
+    vec3(-50.76852536473588, 29.04658282127291, 4.23415299384598),
+    vec3(18.65570506591883, -11.48977351997711, -5.601961508734096),
+  );
+}
+
+fn inferno(t: f32) -> vec3<f32> {
+  return colormap_poly(t,
+    vec3(0.0002189403691192265, 0.001651004631001012, -0.01948089843709184),
+    vec3(0.1065134194856116, 0.5639564367884091, 3.932712388889277),
+    vec3(11.60249308247187, -3.972853965665698, -15.9423941062914),
+    vec3(-41.70399613139459, 17.43639888205313, 44.35414519872813),
+    vec3(77.162935699427, -33.40235894210092, -81.80730925738993),
+    vec3(-71.31942824499214, 32.62606426397723, 73.20951985803202),
+    vec3(25.13112622477341, -12.24266895238567, -23.07032500287172),
+  );
+}
+
+// Linear interpolation between LUT entries. Loads rather than samples so it
+// can be called from non-uniform control flow. The LUT is an sRGB texture,
+// so the result is already linear.
+fn sample_lut(t: f32) -> vec3<f32> {
+  let size = textureDimensions(palette_lut);
+  let x = t * f32(size - 1u);
+  let i0 = u32(floor(x));
+  let i1 = min(i0 + 1u, size - 1u);
+  return mix(textureLoad(palette_lut, i0, 0).rgb, textureLoad(palette_lut, i1, 0).rgb, fract(x));
+}
+
+// Maps a pheremone intensity to a linear colour for the sRGB target. The
+// palette is looked up with t clamped, intensity past the top of it scales
+// the colour up so bright trails keep their headroom for bloom and tonemapping
+fn colorize(intensity: f32) -> vec3<f32> {
+  let level = max(intensity * pal.scale, 0.0);
+  let t = min(level, 1.0);
+  let headroom = max(level, 1.0);
 
-  return a * b * cos(PI * 2.0 * (c * t + d));
+  switch pal.mode {
+    case PALETTE_COSINE: {
+      return srgb_to_linear(clamp(palette(t), vec3(0.0), vec3(1.0))) * headroom;
+    }
+    case PALETTE_VIRIDIS: {
+      return srgb_to_linear(clamp(viridis(t), vec3(0.0), vec3(1.0))) * headroom;
+    }
+    case PALETTE_MAGMA: {
+      return srgb_to_linear(clamp(magma(t), vec3(0.0), vec3(1.0))) * headroom;
+    }
+    case PALETTE_INFERNO: {
+      return srgb_to_linear(clamp(inferno(t), vec3(0.0), vec3(1.0))) * headroom;
+    }
+    case PALETTE_LUT: {
+      return sample_lut(t) * headroom;
+    }
+    default: {
+      return vec3(intensity * pal.scale, 0.0, 0.0);
+    }
+  }
 }
 
 // HASHING
@@ -123,23 +215,8 @@ fn main(in: VertexOutput) -> @location(0) vec4<f32> {
   var color = vec3(0.0);
 // -----------------------------------------------------------------------------------------------
 
-  //for (var i: u32 = 0u; i < NUM_AGENTS; i++) {
-  //  // Show Agents
-  //  let sd = distance(uv, agents[i].pos);
-  //  color += 1.0 - smoothstep(0.0, 0.003, sd);
-  //  
-  //  // Show Sensors
-  //  //let sd1 = distance(uv, agents[i].s1_pos);
-  //  //let sd2 = distance(uv, agents[i].s2_pos);
-  //  //let sd3 = distance(uv, agents[i].s3_pos);
-  //  //color += vec3<f32>(0.3, 0.0, 0.0) * (1.0 - smoothstep(0.0, sp.sensor_radius*2.0, sd1));
-  //  //color += vec3<f32>(0.0, 0.3, 0.0) * (1.0 - smoothstep(0.0, sp.sensor_radius*2.0, sd2));
-  //  //color += vec3<f32>(0.0, 0.0, 0.3) * (1.0 - smoothstep(0.0, sp.sensor_radius*2.0, sd3));
-  //}
-
-  var tex_sample = textureSample(phm, phm_sampler, uv);
-  tex_sample.r *= 0.9;
-  color += tex_sample.rgb;
+  let tex_sample = sample_field(uv);
+  color += colorize(tex_sample.r);
   
 // -----------------------------------------------------------------------------------------------
   return vec4<f32>(color, 1.0);
@@ -0,0 +1,168 @@
+// Instanced overlay of agents and their sensors, drawn on top of the
+// pheremone field. One quad per agent (or per sensor), positions read
+// straight from the agent buffer.
+const SCREEN_WIDTH: f32 = 1376.0;
+const SCREEN_HEIGHT: f32 = 768.0;
+
+// Keep in sync with AgentModel in structs.rs
+const AGENT_MODEL_JONES: u32 = 1u;
+// Keep in sync with DebugView in structs.rs
+const DEBUG_VIEW_OFF: u32 = 0u;
+const DEBUG_VIEW_HEATMAP: u32 = 2u;
+
+struct Slime {
+  pos: vec2<f32>,
+  vel: vec2<f32>,
+  s1_pos: vec2<f32>,
+  s2_pos: vec2<f32>,
+  s3_pos: vec2<f32>,
+  moved_forward: f32,
+  rng: vec4<u32>,
+}
+struct SlimeParams {
+  max_velocity: f32,
+  min_velocity: f32,
+  turn_factor: f32,
+  avoid_factor: f32,
+  sensor_dist: f32,
+  sensor_offset: f32,
+  sensor_radius: f32,
+}
+struct JonesParams {
+  sensor_angle: f32,
+  rotation_angle: f32,
+  sensor_offset: f32,
+  sensor_width: f32,
+  step_size: f32,
+}
+struct SimParams {
+  seed: u32,
+  boundary_mode: u32,
+  agent_model: u32,
+}
+struct ViewParams {
+  shift_modifier: f32,
+  x_shift: f32,
+  y_shift: f32,
+  zoom: f32,
+  time_modifier: f32,
+}
+struct OverlayParams {
+  target_size: vec2<f32>,
+  agent_size: f32,
+  min_sensor_size: f32,
+  debug_view: u32,
+  debug_slot: u32,
+  debug_component: u32,
+  debug_stride: u32,
+  debug_range: vec2<f32>,
+  heatmap_size: f32,
+}
+
+struct VertexOutput {
+  @builtin(position) clip: vec4<f32>,
+  // -1.0 -> 1.0 across the quad
+  @location(0) local: vec2<f32>,
+  @location(1) color: vec3<f32>,
+  // 1 fades out from the centre, 0 is a solid dot
+  @location(2) soft: f32,
+}
+
+@group(0) @binding(0) var<storage, read> agents: array<Slime>;
+@group(0) @binding(1) var<storage, read> sp: SlimeParams;
+@group(0) @binding(2) var<storage, read> jp: JonesParams;
+@group(0) @binding(3) var<storage, read> sim: SimParams;
+@group(0) @binding(4) var<storage, read> vp: ViewParams;
+@group(0) @binding(5) var<storage, read> op: OverlayParams;
+// See common/debug.wgsl
+@group(0) @binding(6) var<storage, read> debug_channels: array<vec4<f32>>;
+
+const CORNERS: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+  vec2(-1.0, -1.0),
+  vec2(1.0, -1.0),
+  vec2(-1.0, 1.0),
+  vec2(1.0, -1.0),
+  vec2(1.0, 1.0),
+  vec2(-1.0, 1.0),
+);
+
+// Inverse of the view transform in slime_frag.wgsl
+fn world_to_clip(pos: vec2<f32>) -> vec2<f32> {
+  let uv = (pos - vec2(vp.x_shift, vp.y_shift)) * vp.zoom;
+  return uv * 2.0 - 1.0;
+}
+
+// half_size is in pixels, so quads keep their size whatever the zoom
+fn quad(centre: vec2<f32>, half_size: vec2<f32>, corner: u32, color: vec3<f32>) -> VertexOutput {
+  var corners = CORNERS;
+  let local = corners[corner];
+  let offset = local * half_size * 2.0 / op.target_size;
+
+  var out: VertexOutput;
+  out.clip = vec4(world_to_clip(centre) + offset, 0.0, 1.0);
+  out.local = local;
+  out.color = color;
+  out.soft = 0.0;
+  return out;
+}
+
+// Blue through green to red across the debug range
+fn debug_ramp(value: f32) -> vec3<f32> {
+  let t = clamp((value - op.debug_range.x) / max(op.debug_range.y - op.debug_range.x, 1e-20), 0.0, 1.0);
+  return clamp(vec3(2.0 * t - 0.5, 1.5 - abs(4.0 * t - 2.0), 1.5 - 2.0 * t), vec3(0.0), vec3(1.0));
+}
+
+@vertex
+fn vs_agent(@builtin(vertex_index) vi: u32, @builtin(instance_index) ii: u32) -> VertexOutput {
+  let agent = agents[ii];
+  if (op.debug_view == DEBUG_VIEW_OFF) {
+    return quad(agent.pos, vec2(op.agent_size), vi, vec3(0.9, 0.9, 0.9));
+  }
+
+  let value = debug_channels[op.debug_slot + ii * op.debug_stride][op.debug_component];
+  let color = debug_ramp(value);
+  if (op.debug_view == DEBUG_VIEW_HEATMAP) {
+    // Dim, wide and soft, so the splats add up where agents crowd
+    var out = quad(agent.pos, vec2(op.heatmap_size), vi, color * 0.15);
+    out.soft = 1.0;
+    return out;
+  }
+  return quad(agent.pos, vec2(op.agent_size * 1.5), vi, color);
+}
+
+@vertex
+fn vs_sensor(@builtin(vertex_index) vi: u32, @builtin(instance_index) ii: u32) -> VertexOutput {
+  let agent = agents[ii / 3u];
+  let sensor = ii % 3u;
+
+  var centre = agent.s1_pos;
+  var color = vec3(0.3, 0.0, 0.0);
+  if (sensor == 1u) {
+    centre = agent.s2_pos;
+    color = vec3(0.0, 0.3, 0.0);
+  } else if (sensor == 2u) {
+    centre = agent.s3_pos;
+    color = vec3(0.0, 0.0, 0.3);
+  }
+
+  // Sensor footprint in texels, as sampled by the movement shader
+  var radius_texels = sp.sensor_radius * SCREEN_HEIGHT;
+  if (sim.agent_model == AGENT_MODEL_JONES) {
+    radius_texels = max(jp.sensor_width, 1.0) * 0.5;
+  }
+
+  let texel_px = op.target_size / vec2(SCREEN_WIDTH, SCREEN_HEIGHT) * vp.zoom;
+  let half_size = max(vec2(radius_texels) * texel_px, vec2(op.min_sensor_size));
+
+  return quad(centre, half_size, vi, color);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let d = length(in.local);
+  let alpha = mix(1.0 - smoothstep(0.7, 1.0, d), max(1.0 - d * d, 0.0), in.soft);
+  if (alpha <= 0.0) {
+    discard;
+  }
+  return vec4(in.color * alpha, alpha);
+}
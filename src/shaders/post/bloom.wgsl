@@ -0,0 +1,58 @@
+// STRUCTS
+struct ExposureState {
+  exposure: f32,
+  luminance: f32,
+  histogram: array<u32, HISTOGRAM_BINS>,
+}
+
+// GROUPS AND BINDINGS
+@group(0) @binding(0)
+var src: texture_2d<f32>;
+
+@group(0) @binding(1)
+var src_sampler: sampler;
+
+@group(0) @binding(2)
+var<storage, read> pp: PostParams;
+
+@group(0) @binding(3)
+var<storage, read> es: ExposureState;
+
+fn exposure() -> f32 {
+  return pp.exposure * select(1.0, es.exposure, pp.auto_exposure != 0u);
+}
+
+// Keeps only what would end up brighter than the threshold after exposure.
+// Renders into the half resolution bloom target, so this also downsamples.
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = textureSample(src, src_sampler, in.uv).rgb * exposure();
+  let excess = max(luminance(color) - pp.bloom_threshold, 0.0);
+  let bright = color * (excess / max(luminance(color), 1e-4));
+  return vec4(bright, 1.0);
+}
+
+// 9 tap gaussian using bilinear filtering to read pairs of texels per tap
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+  let texel = direction / vec2<f32>(textureDimensions(src));
+  let offset1 = texel * 1.3846153846;
+  let offset2 = texel * 3.2307692308;
+
+  var color = textureSample(src, src_sampler, uv).rgb * 0.2270270270;
+  color += textureSample(src, src_sampler, uv + offset1).rgb * 0.3162162162;
+  color += textureSample(src, src_sampler, uv - offset1).rgb * 0.3162162162;
+  color += textureSample(src, src_sampler, uv + offset2).rgb * 0.0702702703;
+  color += textureSample(src, src_sampler, uv - offset2).rgb * 0.0702702703;
+
+  return vec4(color, 1.0);
+}
+
+@fragment
+fn fs_blur_h(in: VertexOutput) -> @location(0) vec4<f32> {
+  return blur(in.uv, vec2(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_v(in: VertexOutput) -> @location(0) vec4<f32> {
+  return blur(in.uv, vec2(0.0, 1.0));
+}
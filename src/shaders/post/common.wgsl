@@ -0,0 +1,44 @@
+// CONSTANTS
+// Keep in sync with TonemapMode in structs.rs
+const TONEMAP_LINEAR: u32 = 0u;
+const TONEMAP_LOG: u32 = 1u;
+const TONEMAP_REINHARD: u32 = 2u;
+const TONEMAP_ACES: u32 = 3u;
+
+// Keep in sync with EXPOSURE_HISTOGRAM_BINS in structs.rs
+const HISTOGRAM_BINS: u32 = 64u;
+const LOG_LUM_MIN: f32 = -10.0;
+const LOG_LUM_MAX: f32 = 6.0;
+
+// STRUCTS
+struct PostParams {
+  exposure: f32,
+  adapt_rate: f32,
+  percentile: f32,
+  white_point: f32,
+  auto_exposure: u32,
+  tonemap: u32,
+  bloom_enabled: u32,
+  bloom_threshold: f32,
+  bloom_intensity: f32,
+}
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+  return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Single triangle covering the screen, no vertex buffer needed
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+  let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+  var out: VertexOutput;
+  out.position = vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+  out.uv = uv;
+  return out;
+}
+
@@ -0,0 +1,78 @@
+// STRUCTS
+struct ExposureState {
+  exposure: f32,
+  luminance: f32,
+  histogram: array<atomic<u32>, HISTOGRAM_BINS>,
+}
+
+// GROUPS AND BINDINGS
+@group(0) @binding(0)
+var hdr: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read> pp: PostParams;
+
+@group(0) @binding(2)
+var<storage, read_write> es: ExposureState;
+
+var<workgroup> local_histogram: array<atomic<u32>, HISTOGRAM_BINS>;
+
+// Log2 luminance histogram of every lit pixel. Black pixels are skipped,
+// otherwise the empty background would decide the exposure.
+@compute
+@workgroup_size(16, 16, 1)
+fn build_histogram(
+  @builtin(global_invocation_id) id: vec3<u32>,
+  @builtin(local_invocation_index) local_index: u32,
+) {
+  let dims = textureDimensions(hdr);
+
+  if (all(id.xy < dims)) {
+    let lum = luminance(textureLoad(hdr, id.xy, 0).rgb);
+
+    if (lum > exp2(LOG_LUM_MIN)) {
+      let t = (log2(lum) - LOG_LUM_MIN) / (LOG_LUM_MAX - LOG_LUM_MIN);
+      let bin = u32(clamp(t * f32(HISTOGRAM_BINS), 0.0, f32(HISTOGRAM_BINS - 1u)));
+      atomicAdd(&local_histogram[bin], 1u);
+    }
+  }
+
+  workgroupBarrier();
+
+  if (local_index < HISTOGRAM_BINS) {
+    atomicAdd(&es.histogram[local_index], atomicLoad(&local_histogram[local_index]));
+  }
+}
+
+// Moves the exposure towards the value that maps the chosen percentile of the
+// histogram to 1.0, then clears the histogram for the next frame.
+@compute
+@workgroup_size(1, 1, 1)
+fn adapt_exposure() {
+  var total = 0u;
+  for (var i = 0u; i < HISTOGRAM_BINS; i++) {
+    total += atomicLoad(&es.histogram[i]);
+  }
+
+  if (total > 0u) {
+    let target_count = f32(total) * pp.percentile;
+    var cumulative = 0u;
+    var bin = HISTOGRAM_BINS - 1u;
+
+    for (var i = 0u; i < HISTOGRAM_BINS; i++) {
+      cumulative += atomicLoad(&es.histogram[i]);
+      if (f32(cumulative) >= target_count) {
+        bin = i;
+        break;
+      }
+    }
+
+    let t = (f32(bin) + 0.5) / f32(HISTOGRAM_BINS);
+    es.luminance = exp2(mix(LOG_LUM_MIN, LOG_LUM_MAX, t));
+    es.exposure = mix(es.exposure, 1.0 / es.luminance, pp.adapt_rate);
+  }
+
+  for (var i = 0u; i < HISTOGRAM_BINS; i++) {
+    atomicStore(&es.histogram[i], 0u);
+  }
+}
@@ -0,0 +1,66 @@
+// STRUCTS
+struct ExposureState {
+  exposure: f32,
+  luminance: f32,
+  histogram: array<u32, HISTOGRAM_BINS>,
+}
+
+// GROUPS AND BINDINGS
+@group(0) @binding(0)
+var hdr: texture_2d<f32>;
+
+@group(0) @binding(1)
+var hdr_sampler: sampler;
+
+@group(0) @binding(2)
+var<storage, read> pp: PostParams;
+
+@group(0) @binding(3)
+var<storage, read> es: ExposureState;
+
+@group(1) @binding(0)
+var bloom: texture_2d<f32>;
+
+fn exposure() -> f32 {
+  return pp.exposure * select(1.0, es.exposure, pp.auto_exposure != 0u);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+  let a = 2.51;
+  let b = 0.03;
+  let c = 2.43;
+  let d = 0.59;
+  let e = 0.14;
+  return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3(0.0), vec3(1.0));
+}
+
+fn tonemap(color: vec3<f32>) -> vec3<f32> {
+  let c = max(color, vec3(0.0));
+
+  switch pp.tonemap {
+    case TONEMAP_LOG: {
+      return clamp(log2(1.0 + c) / log2(1.0 + pp.white_point), vec3(0.0), vec3(1.0));
+    }
+    case TONEMAP_REINHARD: {
+      return c / (1.0 + c);
+    }
+    case TONEMAP_ACES: {
+      return aces(c);
+    }
+    default: {
+      return clamp(c, vec3(0.0), vec3(1.0));
+    }
+  }
+}
+
+@fragment
+fn fs_tonemap(in: VertexOutput) -> @location(0) vec4<f32> {
+  var color = textureSample(hdr, hdr_sampler, in.uv).rgb * exposure();
+
+  if (pp.bloom_enabled != 0u) {
+    color += textureSample(bloom, hdr_sampler, in.uv).rgb * pp.bloom_intensity;
+  }
+
+  return vec4(tonemap(color), 1.0);
+}
@@ -0,0 +1,360 @@
+use std::error::Error;
+use std::path::Path;
+
+use crate::{FoodSources, GeoBounds, PointOfInterest, SourceTexel};
+
+// Room left around points a projection is fitted to, as a share of their spread
+const FIT_MARGIN: f64 = 0.1;
+
+enum Location {
+    Texel([f32; 2]),
+    // Latitude, longitude
+    Geo([f64; 2]),
+}
+
+/// Splits a CSV line into trimmed fields. Quoted fields can hold commas and
+/// `""` for a quote, they can't run over several lines.
+fn csv_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| *c != ',' && c.is_whitespace()).is_some() {}
+
+        let mut field = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted field".into()),
+                }
+            }
+            while chars.next_if(|c| *c != ',').is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                field.push(c);
+            }
+        }
+        fields.push(field.trim().to_string());
+
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// Reads named points from a CSV with a header row. Lat/lon points are
+/// projected onto the field through `bounds`, or through bounds fitted around
+/// them.
+pub(crate) fn read_points(
+    path: &Path,
+    bounds: Option<GeoBounds>,
+    extent: wgpu::Extent3d,
+) -> Result<Vec<PointOfInterest>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+
+    let (_, header) = lines.next().ok_or("no header row")?;
+    let header = csv_fields(header)
+        .map_err(|e| format!("header: {}", e))?
+        .into_iter()
+        .map(|column| column.to_lowercase())
+        .collect::<Vec<String>>();
+    let column = |names: &[&str]| header.iter().position(|column| names.contains(&&**column));
+
+    let name = column(&["name"]).ok_or("no name column")?;
+    let weight = column(&["weight"]);
+    let (first, second, geo) = match (
+        column(&["x"]),
+        column(&["y"]),
+        column(&["lat", "latitude"]),
+        column(&["lon", "lng", "longitude"]),
+    ) {
+        (Some(x), Some(y), _, _) => (x, y, false),
+        (_, _, Some(lat), Some(lon)) => (lat, lon, true),
+        _ => return Err("expected x and y or lat and lon columns".into()),
+    };
+
+    let mut rows = Vec::new();
+    for (number, line) in lines {
+        let fields = csv_fields(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .map(String::as_str)
+                .ok_or_else(|| format!("line {}: missing {}", number + 1, header[index]))
+        };
+        let number = |index: usize| -> Result<f64, String> {
+            field(index)?
+                .parse::<f64>()
+                .map_err(|e| format!("line {}: {}: {}", number + 1, header[index], e))
+        };
+
+        let location = if geo {
+            Location::Geo([number(first)?, number(second)?])
+        } else {
+            Location::Texel([number(first)? as f32, number(second)? as f32])
+        };
+        let weight = match weight {
+            Some(index) => number(index)? as f32,
+            None => 1.0,
+        };
+        rows.push((field(name)?.to_string(), location, weight));
+    }
+
+    let geo_points = rows
+        .iter()
+        .filter_map(|(_, location, _)| match location {
+            Location::Geo(coord) => Some(*coord),
+            Location::Texel(_) => None,
+        })
+        .collect::<Vec<[f64; 2]>>();
+    let bounds = match bounds {
+        Some(bounds) => bounds,
+        None if geo_points.is_empty() => fit_bounds(&[[0.0, 0.0]]),
+        None => fit_bounds(&geo_points),
+    };
+
+    Ok(rows
+        .into_iter()
+        .map(|(name, location, weight)| PointOfInterest {
+            name,
+            position: match location {
+                Location::Texel(position) => position,
+                Location::Geo(coord) => project(coord, bounds, extent),
+            },
+            weight,
+        })
+        .collect())
+}
+
+/// The box around `coords`, latitude and longitude, with a margin
+pub(crate) fn fit_bounds(coords: &[[f64; 2]]) -> GeoBounds {
+    let fold = |index: usize| {
+        coords
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), coord| {
+                (low.min(coord[index]), high.max(coord[index]))
+            })
+    };
+    let ((south, north), (west, east)) = (fold(0), fold(1));
+
+    // A single point or a line of them still needs a box with some area
+    let spread = (north - south).max(east - west).max(1e-3);
+    let margin = spread * FIT_MARGIN;
+    GeoBounds {
+        south: south - margin,
+        west: west - margin,
+        north: north + margin,
+        east: east + margin,
+    }
+}
+
+/// Equirectangular projection of latitude and longitude onto the field,
+/// north at the top. Longitude is scaled by the cosine of the middle
+/// latitude so distances keep their proportions, and the box is fitted
+/// into the field without stretching.
+pub(crate) fn project(coord: [f64; 2], bounds: GeoBounds, extent: wgpu::Extent3d) -> [f32; 2] {
+    let [lat, lon] = coord;
+    let x_scale = ((bounds.south + bounds.north) * 0.5).to_radians().cos();
+
+    let box_width = (bounds.east - bounds.west) * x_scale;
+    let box_height = bounds.north - bounds.south;
+    let (width, height) = (extent.width as f64, extent.height as f64);
+    let scale = (width / box_width).min(height / box_height);
+
+    // Centred, with whatever the aspect ratio leaves over split either side
+    let x = (width - box_width * scale) * 0.5 + (lon - bounds.west) * x_scale * scale;
+    let y = (height - box_height * scale) * 0.5 + (bounds.north - lat) * scale;
+    [x as f32, y as f32]
+}
+
+/// Land texels of an image stretched over the field, anything brighter than
+/// mid grey is land
+pub(crate) fn read_land_mask(
+    path: &Path,
+    extent: wgpu::Extent3d,
+) -> Result<Vec<bool>, Box<dyn Error>> {
+    let image = image::open(path)?.into_luma8();
+    let image = image::imageops::resize(
+        &image,
+        extent.width,
+        extent.height,
+        image::imageops::FilterType::Nearest,
+    );
+
+    Ok(image.pixels().map(|pixel| pixel.0[0] > 127).collect())
+}
+
+/// Stamps each point as a disc of attractant, scaled by its weight, over the
+/// land mask
+pub(crate) fn source_texels(sources: &FoodSources, extent: wgpu::Extent3d) -> Vec<SourceTexel> {
+    let (width, height) = (extent.width as i32, extent.height as i32);
+    let mut texels = match &sources.land {
+        Some(land) => land
+            .iter()
+            .map(|&land| SourceTexel {
+                attractant: 0.0,
+                land: if land { 1.0 } else { 0.0 },
+            })
+            .collect(),
+        None => vec![
+            SourceTexel {
+                attractant: 0.0,
+                land: 1.0,
+            };
+            (width * height) as usize
+        ],
+    };
+
+    let radius = sources.radius.max(0.5);
+    let reach = radius.ceil() as i32;
+    for point in &sources.points {
+        let [px, py] = point.position;
+        let (cx, cy) = (px.floor() as i32, py.floor() as i32);
+
+        for y in (cy - reach).max(0)..=(cy + reach).min(height - 1) {
+            for x in (cx - reach).max(0)..=(cx + reach).min(width - 1) {
+                let (dx, dy) = (x as f32 + 0.5 - px, y as f32 + 0.5 - py);
+                if dx.hypot(dy) <= radius {
+                    texels[(y * width + x) as usize].attractant += sources.strength * point.weight;
+                }
+            }
+        }
+    }
+
+    texels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    fn assert_near(position: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            (position[0] - expected[0]).abs() < 1e-3 && (position[1] - expected[1]).abs() < 1e-3,
+            "{:?} is not {:?}",
+            position,
+            expected
+        );
+    }
+
+    #[test]
+    fn projects_corners_to_field_edges() {
+        // On the equator a degree is as wide as it's tall, a 40x20 box fills
+        // an 80x40 field
+        let bounds = GeoBounds {
+            south: -10.0,
+            west: -20.0,
+            north: 10.0,
+            east: 20.0,
+        };
+        assert_near(project([10.0, -20.0], bounds, extent(80, 40)), [0.0, 0.0]);
+        assert_near(project([-10.0, 20.0], bounds, extent(80, 40)), [80.0, 40.0]);
+        assert_near(project([0.0, 0.0], bounds, extent(80, 40)), [40.0, 20.0]);
+
+        // A square field leaves the spare height above and below
+        assert_near(project([10.0, -20.0], bounds, extent(80, 80)), [0.0, 20.0]);
+        assert_near(project([-10.0, 20.0], bounds, extent(80, 80)), [80.0, 60.0]);
+    }
+
+    fn sources(points: &[([f32; 2], f32)], radius: f32) -> FoodSources {
+        FoodSources {
+            points: points
+                .iter()
+                .map(|&(position, weight)| PointOfInterest {
+                    name: String::new(),
+                    position,
+                    weight,
+                })
+                .collect(),
+            radius,
+            strength: 0.5,
+            land: None,
+        }
+    }
+
+    #[test]
+    fn stamps_weighted_discs() {
+        let texels = source_texels(&sources(&[([8.0, 8.0], 2.0)], 2.0), extent(16, 16));
+
+        let stamped = texels
+            .iter()
+            .enumerate()
+            .filter(|(_, texel)| texel.attractant > 0.0)
+            .collect::<Vec<_>>();
+        // Texel centres within 2 of a texel corner, the middle two rows of 4
+        // and 2 in each row either side
+        assert_eq!(stamped.len(), 12);
+        for (index, texel) in stamped {
+            let (x, y) = ((index % 16) as f32 + 0.5, (index / 16) as f32 + 0.5);
+            assert!((x - 8.0).hypot(y - 8.0) <= 2.0);
+            assert_eq!(texel.attractant, 1.0);
+        }
+        assert!(texels.iter().all(|texel| texel.land == 1.0));
+    }
+
+    #[test]
+    fn clips_discs_at_the_border() {
+        let texels = source_texels(
+            &sources(&[([0.0, 0.0], 1.0), ([15.5, 15.5], 1.0)], 2.0),
+            extent(16, 16),
+        );
+
+        let stamped = |x: usize, y: usize| texels[y * 16 + x].attractant > 0.0;
+        // A quarter of the first disc is left and a little more of the second
+        assert!(stamped(0, 0) && stamped(1, 0) && stamped(0, 1));
+        assert!(stamped(15, 15) && stamped(13, 15) && stamped(15, 13) && stamped(14, 14));
+        assert_eq!(
+            texels.iter().filter(|texel| texel.attractant > 0.0).count(),
+            3 + 6
+        );
+        // Nothing wraps round to the far side
+        assert!(!stamped(15, 0) && !stamped(0, 15));
+    }
+
+    #[test]
+    fn splits_quoted_fields() {
+        let fields = csv_fields(r#"name, "Shinjuku, Tokyo" ,"say ""hi""",,3.5"#).unwrap();
+        assert_eq!(
+            fields,
+            ["name", "Shinjuku, Tokyo", r#"say "hi""#, "", "3.5"]
+        );
+        assert!(csv_fields(r#"a,"open"#).is_err());
+    }
+
+    #[test]
+    fn reads_names_with_commas() {
+        let path = std::env::temp_dir().join(format!("slime_points_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "name,x,y\n\"Shinjuku, Tokyo\",10,20\nShibuya,30,40\n",
+        )
+        .unwrap();
+        let extent = wgpu::Extent3d {
+            width: 64,
+            height: 64,
+            depth_or_array_layers: 1,
+        };
+        let points = read_points(&path, None, extent);
+        std::fs::remove_file(&path).unwrap();
+
+        let points = points.unwrap();
+        assert_eq!(points[0].name, "Shinjuku, Tokyo");
+        assert_eq!(points[0].position, [10.0, 20.0]);
+        assert_eq!(points[1].name, "Shibuya");
+    }
+}
@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use serde::Deserialize;
+use winit::keyboard::{KeyCode, ModifiersState};
+
+use super::control_state::KeyboardMode;
+use crate::AgentModel;
+
+// Every action's default keys, a user bindings file only needs the ones it changes
+const DEFAULT_BINDINGS: &str = include_str!("default_bindings.toml");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Action {
+    // GLOBAL
+    ModeDebug,
+    ModeView,
+    ModeSlime,
+    ModePheremones,
+    ModePrint,
+    ModePost,
+    ToggleHud,
+    ToggleGui,
+    ToggleStats,
+    ToggleProfiler,
+    SavePreset,
+    LoadPreset,
+    RevertPreset,
+    SaveCheckpoint,
+    LoadCheckpoint,
+    Undo,
+    Redo,
+    Reseed,
+    ReseedNew,
+    ClearField,
+    Reset,
+    CycleBoundary,
+    CycleAgentModel,
+
+    // DEBUG
+    PrintDebugChannel,
+    CycleDebugChannel,
+    CycleDebugComponent,
+    CycleDebugView,
+    FitDebugRange,
+
+    // SLIME, velocity model
+    MaxVelocityUp,
+    MaxVelocityDown,
+    MinVelocityUp,
+    MinVelocityDown,
+    TurnFactorUp,
+    TurnFactorDown,
+    SensorDistUp,
+    SensorDistDown,
+    SensorOffsetUp,
+    SensorOffsetDown,
+    SensorRadiusUp,
+    SensorRadiusDown,
+
+    // SLIME, Jones model
+    JonesSensorAngleUp,
+    JonesSensorAngleDown,
+    JonesSensorOffsetUp,
+    JonesSensorOffsetDown,
+    JonesSensorWidthUp,
+    JonesSensorWidthDown,
+    JonesRotationAngleUp,
+    JonesRotationAngleDown,
+    JonesStepSizeUp,
+    JonesStepSizeDown,
+
+    // PHEREMONES
+    DepositionUp,
+    DepositionDown,
+    DiffusionUp,
+    DiffusionDown,
+    DecayUp,
+    DecayDown,
+
+    // VIEW
+    CyclePalette,
+    ToggleAgents,
+    ToggleSensors,
+    PaletteScaleUp,
+    PaletteScaleDown,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    PanSpeedUp,
+    PanSpeedDown,
+    ZoomIn,
+    ZoomOut,
+
+    // PRINT
+    CaptureFrame,
+    ExportField,
+    ExtractGraph,
+    PrintViewParams,
+    PrintSlimeParams,
+    PrintJonesParams,
+    PrintPostParams,
+    PrintPheremoneParams,
+    PrintAgents,
+
+    // POST
+    CycleTonemap,
+    ToggleAutoExposure,
+    ToggleBloom,
+    ExposureUp,
+    ExposureDown,
+    WhitePointUp,
+    WhitePointDown,
+    BloomIntensityUp,
+    BloomIntensityDown,
+    BloomThresholdUp,
+    BloomThresholdDown,
+    PercentileUp,
+    PercentileDown,
+}
+
+impl Action {
+    pub(crate) const ALL: [Action; 91] = [
+        Action::ModeDebug,
+        Action::ModeView,
+        Action::ModeSlime,
+        Action::ModePheremones,
+        Action::ModePrint,
+        Action::ModePost,
+        Action::ToggleHud,
+        Action::ToggleGui,
+        Action::ToggleStats,
+        Action::ToggleProfiler,
+        Action::SavePreset,
+        Action::LoadPreset,
+        Action::RevertPreset,
+        Action::SaveCheckpoint,
+        Action::LoadCheckpoint,
+        Action::Undo,
+        Action::Redo,
+        Action::Reseed,
+        Action::ReseedNew,
+        Action::ClearField,
+        Action::Reset,
+        Action::CycleBoundary,
+        Action::CycleAgentModel,
+        Action::PrintDebugChannel,
+        Action::CycleDebugChannel,
+        Action::CycleDebugComponent,
+        Action::CycleDebugView,
+        Action::FitDebugRange,
+        Action::MaxVelocityUp,
+        Action::MaxVelocityDown,
+        Action::MinVelocityUp,
+        Action::MinVelocityDown,
+        Action::TurnFactorUp,
+        Action::TurnFactorDown,
+        Action::SensorDistUp,
+        Action::SensorDistDown,
+        Action::SensorOffsetUp,
+        Action::SensorOffsetDown,
+        Action::SensorRadiusUp,
+        Action::SensorRadiusDown,
+        Action::JonesSensorAngleUp,
+        Action::JonesSensorAngleDown,
+        Action::JonesSensorOffsetUp,
+        Action::JonesSensorOffsetDown,
+        Action::JonesSensorWidthUp,
+        Action::JonesSensorWidthDown,
+        Action::JonesRotationAngleUp,
+        Action::JonesRotationAngleDown,
+        Action::JonesStepSizeUp,
+        Action::JonesStepSizeDown,
+        Action::DepositionUp,
+        Action::DepositionDown,
+        Action::DiffusionUp,
+        Action::DiffusionDown,
+        Action::DecayUp,
+        Action::DecayDown,
+        Action::CyclePalette,
+        Action::ToggleAgents,
+        Action::ToggleSensors,
+        Action::PaletteScaleUp,
+        Action::PaletteScaleDown,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanSpeedUp,
+        Action::PanSpeedDown,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::CaptureFrame,
+        Action::ExportField,
+        Action::ExtractGraph,
+        Action::PrintViewParams,
+        Action::PrintSlimeParams,
+        Action::PrintJonesParams,
+        Action::PrintPostParams,
+        Action::PrintPheremoneParams,
+        Action::PrintAgents,
+        Action::CycleTonemap,
+        Action::ToggleAutoExposure,
+        Action::ToggleBloom,
+        Action::ExposureUp,
+        Action::ExposureDown,
+        Action::WhitePointUp,
+        Action::WhitePointDown,
+        Action::BloomIntensityUp,
+        Action::BloomIntensityDown,
+        Action::BloomThresholdUp,
+        Action::BloomThresholdDown,
+        Action::PercentileUp,
+        Action::PercentileDown,
+    ];
+
+    /// The mode the action is available in, `None` for every mode
+    pub(crate) fn mode(self) -> Option<KeyboardMode> {
+        use Action::*;
+
+        match self {
+            ModeDebug | ModeView | ModeSlime | ModePheremones | ModePrint | ModePost
+            | ToggleHud | ToggleGui | ToggleStats | ToggleProfiler | SavePreset | LoadPreset
+            | RevertPreset | SaveCheckpoint | LoadCheckpoint | Undo | Redo | Reseed | ReseedNew
+            | ClearField | Reset | CycleBoundary | CycleAgentModel => None,
+            PrintDebugChannel | CycleDebugChannel | CycleDebugComponent | CycleDebugView
+            | FitDebugRange => Some(KeyboardMode::DEBUG),
+            MaxVelocityUp
+            | MaxVelocityDown
+            | MinVelocityUp
+            | MinVelocityDown
+            | TurnFactorUp
+            | TurnFactorDown
+            | SensorDistUp
+            | SensorDistDown
+            | SensorOffsetUp
+            | SensorOffsetDown
+            | SensorRadiusUp
+            | SensorRadiusDown
+            | JonesSensorAngleUp
+            | JonesSensorAngleDown
+            | JonesSensorOffsetUp
+            | JonesSensorOffsetDown
+            | JonesSensorWidthUp
+            | JonesSensorWidthDown
+            | JonesRotationAngleUp
+            | JonesRotationAngleDown
+            | JonesStepSizeUp
+            | JonesStepSizeDown => Some(KeyboardMode::SLIME),
+            DepositionUp | DepositionDown | DiffusionUp | DiffusionDown | DecayUp | DecayDown => {
+                Some(KeyboardMode::PHEREMONES)
+            }
+            CyclePalette | ToggleAgents | ToggleSensors | PaletteScaleUp | PaletteScaleDown
+            | PanLeft | PanRight | PanUp | PanDown | PanSpeedUp | PanSpeedDown | ZoomIn
+            | ZoomOut => Some(KeyboardMode::VIEW),
+            CaptureFrame | ExportField | ExtractGraph | PrintViewParams | PrintSlimeParams
+            | PrintJonesParams | PrintPostParams | PrintPheremoneParams | PrintAgents => {
+                Some(KeyboardMode::PRINT)
+            }
+            CycleTonemap | ToggleAutoExposure | ToggleBloom | ExposureUp | ExposureDown
+            | WhitePointUp | WhitePointDown | BloomIntensityUp | BloomIntensityDown
+            | BloomThresholdUp | BloomThresholdDown | PercentileUp | PercentileDown => {
+                Some(KeyboardMode::POST)
+            }
+        }
+    }
+
+    /// The agent model a SLIME mode action edits, `None` for other modes
+    pub(crate) fn agent_model(self) -> Option<AgentModel> {
+        use Action::*;
+
+        match self.mode() {
+            Some(KeyboardMode::SLIME) => match self {
+                JonesSensorAngleUp
+                | JonesSensorAngleDown
+                | JonesSensorOffsetUp
+                | JonesSensorOffsetDown
+                | JonesSensorWidthUp
+                | JonesSensorWidthDown
+                | JonesRotationAngleUp
+                | JonesRotationAngleDown
+                | JonesStepSizeUp
+                | JonesStepSizeDown => Some(AgentModel::Jones),
+                _ => Some(AgentModel::Velocity),
+            },
+            _ => None,
+        }
+    }
+
+    /// The registered parameter an up/down action steps, and which way
+    pub(crate) fn param(self) -> Option<(&'static str, f32)> {
+        use Action::*;
+
+        let param = match self {
+            MaxVelocityUp => ("slime.max_velocity", 1.0),
+            MaxVelocityDown => ("slime.max_velocity", -1.0),
+            MinVelocityUp => ("slime.min_velocity", 1.0),
+            MinVelocityDown => ("slime.min_velocity", -1.0),
+            TurnFactorUp => ("slime.turn_factor", 1.0),
+            TurnFactorDown => ("slime.turn_factor", -1.0),
+            SensorDistUp => ("slime.sensor_dist", 1.0),
+            SensorDistDown => ("slime.sensor_dist", -1.0),
+            SensorOffsetUp => ("slime.sensor_offset", 1.0),
+            SensorOffsetDown => ("slime.sensor_offset", -1.0),
+            SensorRadiusUp => ("slime.sensor_radius", 1.0),
+            SensorRadiusDown => ("slime.sensor_radius", -1.0),
+            JonesSensorAngleUp => ("jones.sensor_angle", 1.0),
+            JonesSensorAngleDown => ("jones.sensor_angle", -1.0),
+            JonesSensorOffsetUp => ("jones.sensor_offset", 1.0),
+            JonesSensorOffsetDown => ("jones.sensor_offset", -1.0),
+            JonesSensorWidthUp => ("jones.sensor_width", 1.0),
+            JonesSensorWidthDown => ("jones.sensor_width", -1.0),
+            JonesRotationAngleUp => ("jones.rotation_angle", 1.0),
+            JonesRotationAngleDown => ("jones.rotation_angle", -1.0),
+            JonesStepSizeUp => ("jones.step_size", 1.0),
+            JonesStepSizeDown => ("jones.step_size", -1.0),
+            DepositionUp => ("pheremone.deposition_amount", 1.0),
+            DepositionDown => ("pheremone.deposition_amount", -1.0),
+            DiffusionUp => ("pheremone.diffusion_factor", 1.0),
+            DiffusionDown => ("pheremone.diffusion_factor", -1.0),
+            DecayUp => ("pheremone.decay_factor", 1.0),
+            DecayDown => ("pheremone.decay_factor", -1.0),
+            PanSpeedUp => ("view.shift_modifier", 1.0),
+            PanSpeedDown => ("view.shift_modifier", -1.0),
+            ZoomIn => ("view.zoom", 1.0),
+            ZoomOut => ("view.zoom", -1.0),
+            ExposureUp => ("post.exposure", 1.0),
+            ExposureDown => ("post.exposure", -1.0),
+            WhitePointUp => ("post.white_point", 1.0),
+            WhitePointDown => ("post.white_point", -1.0),
+            BloomIntensityUp => ("post.bloom_intensity", 1.0),
+            BloomIntensityDown => ("post.bloom_intensity", -1.0),
+            BloomThresholdUp => ("post.bloom_threshold", 1.0),
+            BloomThresholdDown => ("post.bloom_threshold", -1.0),
+            PercentileUp => ("post.percentile", 1.0),
+            PercentileDown => ("post.percentile", -1.0),
+            _ => return None,
+        };
+
+        Some(param)
+    }
+
+    pub(crate) fn description(self) -> &'static str {
+        use Action::*;
+
+        match self {
+            ModeDebug => "debug mode",
+            ModeView => "view mode",
+            ModeSlime => "slime mode",
+            ModePheremones => "pheremone mode",
+            ModePrint => "print mode",
+            ModePost => "post mode",
+            ToggleHud => "toggle HUD",
+            ToggleGui => "toggle parameter panel",
+            ToggleStats => "toggle statistics",
+            ToggleProfiler => "toggle profiler",
+            SavePreset => "save preset",
+            LoadPreset => "load preset",
+            RevertPreset => "revert to saved preset",
+            SaveCheckpoint => "save checkpoint",
+            LoadCheckpoint => "load checkpoint",
+            Undo => "undo parameter edit",
+            Redo => "redo parameter edit",
+            Reseed => "re-seed",
+            ReseedNew => "re-seed with new seed",
+            ClearField => "clear field",
+            Reset => "clear and re-seed",
+            CycleBoundary => "boundary mode",
+            CycleAgentModel => "agent model",
+            PrintDebugChannel => "print debug channel",
+            CycleDebugChannel => "next debug channel",
+            CycleDebugComponent => "next debug value",
+            CycleDebugView => "cycle debug view",
+            FitDebugRange => "fit debug range",
+            MaxVelocityUp => "max velocity +",
+            MaxVelocityDown => "max velocity -",
+            MinVelocityUp => "min velocity +",
+            MinVelocityDown => "min velocity -",
+            TurnFactorUp => "turn factor +",
+            TurnFactorDown => "turn factor -",
+            SensorDistUp => "sensor distance +",
+            SensorDistDown => "sensor distance -",
+            SensorOffsetUp => "sensor offset +",
+            SensorOffsetDown => "sensor offset -",
+            SensorRadiusUp => "sensor radius +",
+            SensorRadiusDown => "sensor radius -",
+            JonesSensorAngleUp => "sensor angle +",
+            JonesSensorAngleDown => "sensor angle -",
+            JonesSensorOffsetUp => "sensor offset +",
+            JonesSensorOffsetDown => "sensor offset -",
+            JonesSensorWidthUp => "sensor width +",
+            JonesSensorWidthDown => "sensor width -",
+            JonesRotationAngleUp => "rotation angle +",
+            JonesRotationAngleDown => "rotation angle -",
+            JonesStepSizeUp => "step size +",
+            JonesStepSizeDown => "step size -",
+            DepositionUp => "deposition +",
+            DepositionDown => "deposition -",
+            DiffusionUp => "diffusion +",
+            DiffusionDown => "diffusion -",
+            DecayUp => "decay +",
+            DecayDown => "decay -",
+            CyclePalette => "cycle palette",
+            ToggleAgents => "show agents",
+            ToggleSensors => "show sensors",
+            PaletteScaleUp => "palette scale +",
+            PaletteScaleDown => "palette scale -",
+            PanLeft => "pan left",
+            PanRight => "pan right",
+            PanUp => "pan up",
+            PanDown => "pan down",
+            PanSpeedUp => "pan speed +",
+            PanSpeedDown => "pan speed -",
+            ZoomIn => "zoom in",
+            ZoomOut => "zoom out",
+            CaptureFrame => "capture frame",
+            ExportField => "export field",
+            ExtractGraph => "extract network graph",
+            PrintViewParams => "print view params",
+            PrintSlimeParams => "print slime params",
+            PrintJonesParams => "print jones params",
+            PrintPostParams => "print post params",
+            PrintPheremoneParams => "print pheremone params",
+            PrintAgents => "print agents",
+            CycleTonemap => "cycle tonemap",
+            ToggleAutoExposure => "auto exposure",
+            ToggleBloom => "bloom",
+            ExposureUp => "exposure +",
+            ExposureDown => "exposure -",
+            WhitePointUp => "white point +",
+            WhitePointDown => "white point -",
+            BloomIntensityUp => "bloom intensity +",
+            BloomIntensityDown => "bloom intensity -",
+            BloomThresholdUp => "bloom threshold +",
+            BloomThresholdDown => "bloom threshold -",
+            PercentileUp => "exposure percentile +",
+            PercentileDown => "exposure percentile -",
+        }
+    }
+}
+
+/// A chord, every key held with exactly these modifiers. Written as
+/// `"Ctrl+KeyS"` or `"KeyS+KeyD+ArrowUp"` using winit's `KeyCode` names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Binding {
+    pub(crate) keys: Vec<KeyCode>,
+    pub(crate) modifiers: ModifiersState,
+}
+
+impl Binding {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut keys = Vec::new();
+        let mut modifiers = ModifiersState::empty();
+
+        for token in text.split('+').map(str::trim) {
+            match token {
+                "Ctrl" => modifiers |= ModifiersState::CONTROL,
+                "Shift" => modifiers |= ModifiersState::SHIFT,
+                "Alt" => modifiers |= ModifiersState::ALT,
+                "Super" => modifiers |= ModifiersState::SUPER,
+                _ => {
+                    let key = KeyCode::deserialize(serde::de::value::StrDeserializer::<
+                        serde::de::value::Error,
+                    >::new(token))
+                    .map_err(|_| format!("unknown key {:?} in {:?}", token, text))?;
+                    keys.push(key);
+                }
+            }
+        }
+
+        if keys.is_empty() {
+            return Err(format!("no key in {:?}", text));
+        }
+
+        Ok(Self { keys, modifiers })
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let modifiers = [
+            (ModifiersState::CONTROL, "Ctrl"),
+            (ModifiersState::SHIFT, "Shift"),
+            (ModifiersState::ALT, "Alt"),
+            (ModifiersState::SUPER, "Super"),
+        ];
+
+        let mut tokens = modifiers
+            .iter()
+            .filter(|(modifier, _)| self.modifiers.contains(*modifier))
+            .map(|(_, name)| name.to_string())
+            .collect::<Vec<String>>();
+        tokens.extend(self.keys.iter().map(|key| format!("{:?}", key)));
+
+        write!(f, "{}", tokens.join("+"))
+    }
+}
+
+// A single chord or a list of alternatives
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BindingList {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Bindings {
+    map: HashMap<Action, Vec<Binding>>,
+}
+
+impl Bindings {
+    pub(crate) fn get(&self, action: Action) -> &[Binding] {
+        self.map.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    fn merge_toml(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let table: HashMap<Action, BindingList> = toml::from_str(text)?;
+
+        // Parse everything first so a bad entry leaves the bindings untouched
+        let mut parsed = HashMap::new();
+        for (action, list) in table {
+            let texts = match list {
+                BindingList::One(text) => vec![text],
+                BindingList::Many(texts) => texts,
+            };
+
+            let bindings = texts
+                .iter()
+                .map(|text| Binding::parse(text))
+                .collect::<Result<Vec<Binding>, String>>()?;
+            parsed.insert(action, bindings);
+        }
+
+        self.map.extend(parsed);
+        Ok(())
+    }
+}
+
+/// The built-in bindings, with any set in `path` replacing them action by action.
+/// An empty list unbinds an action.
+pub(crate) fn load_bindings(path: &Path) -> Bindings {
+    let mut bindings = Bindings {
+        map: HashMap::new(),
+    };
+    bindings
+        .merge_toml(DEFAULT_BINDINGS)
+        .expect("default bindings should parse");
+
+    if path.exists() {
+        match std::fs::read_to_string(path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|text| bindings.merge_toml(&text))
+        {
+            Ok(()) => println!("Loaded key bindings from {}", path.display()),
+            Err(e) => eprintln!("Error loading key bindings from {}: {}", path.display(), e),
+        }
+    }
+
+    bindings
+}
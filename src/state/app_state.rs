@@ -1,70 +1,163 @@
 use crate::{
+    checkpoint::checkpoint_functions::{
+        capture_checkpoint, read_checkpoint, restore_checkpoint, write_checkpoint,
+    },
+    debug::debug_functions::{
+        component_range, debug_channel_values, debug_slot, format_debug_channel, DEBUG_CHANNELS,
+    },
+    export::export_functions::export_fields,
+    graph::graph_functions::export_graph,
+    gui::gui_functions::draw_gui,
+    hud::hud_functions::{draw_hud, draw_stats},
     init::init_functions::{
-        init_bind_groups, init_buffers, init_params, init_pipelines, init_shader_modules,
+        check_agent_count, init_adapter, init_agent_buffers, init_bind_groups, init_buffers,
+        init_capabilities, init_headless_config, init_instance, init_params, init_pipelines,
+        init_post_bind_groups, init_post_textures, init_shader_modules, init_surface_config,
         init_textures,
     },
+    modulation::modulation_functions::{modulate_params, resolve_modulations, unmodulated_params},
+    palettes::palette_functions::{default_palette_lut, load_palette_lut},
+    params::param_functions::{advance_params, set_params, PARAMS},
+    post::post_functions::encode_post_processing,
+    presets::preset_functions::{
+        apply_preset_to_params, load_preset, preset_from_params, save_preset,
+    },
+    sources::source_functions::{read_land_mask, read_points, source_texels},
     updates::update_functions::{
-        update_agent_position, update_cpu_read_buffers, update_pheremone_trails,
+        clear_pheremone_texture, update_agent_position, update_jones_params_buffer,
+        update_overlay_params_buffer, update_palette_lut_texture, update_palette_params_buffer,
+        update_pheremone_params_buffer, update_pheremone_trails, update_post_params_buffer,
+        update_sim_params_buffer, update_sim_stats, update_sim_steps, update_slime_params_buffer,
+        update_sources_buffer, update_time_uniform_buffer, update_view_params_buffer,
     },
-    BindGroups, Buffers, Params, Pipelines, ShaderModules, Textures, VERTICES,
+    ActiveModulation, AgentModel, Args, BindGroups, BoundaryMode, Buffers, Capabilities,
+    DebugScope, DebugView, ExportSettings, FoodSources, PaletteMode, Params, Pipelines, Preset,
+    ShaderModules, Slime, Textures, TonemapMode, DEFAULT_CHECKPOINT_PATH, SCREEN_HEIGHT,
+    SCREEN_WIDTH, VERTICES,
+};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::sync::Arc;
 
+use super::action_state::load_bindings;
 use super::control_state::{print_gpu_data, update_controls, KeyboardState};
+use super::history_state::ParamHistory;
+use super::profiler_state::{ProfiledPass, Profiler};
+use super::readback_state::{ReadbackCallback, ReadbackPool, ReadbackRequest};
+use super::smoothing_state::ParamSmoother;
+use super::stats_state::StatsState;
+use super::ui_state::UiState;
 
 #[derive(Debug)]
 pub(crate) struct State<'a> {
+    #[allow(dead_code)]
     pub(crate) instance: wgpu::Instance,
+    #[allow(dead_code)]
     pub(crate) adapter: wgpu::Adapter,
     pub(crate) device: wgpu::Device,
     pub(crate) queue: wgpu::Queue,
-    pub(crate) surface: wgpu::Surface<'a>,
+    // None headless
+    pub(crate) surface: Option<wgpu::Surface<'a>>,
     pub(crate) surface_config: wgpu::SurfaceConfiguration,
     pub(crate) size: winit::dpi::PhysicalSize<u32>,
+    #[allow(dead_code)]
     pub(crate) shader_modules: ShaderModules,
     pub(crate) params: Params,
     pub(crate) buffers: Buffers,
     pub(crate) bind_groups: BindGroups,
     pub(crate) pipelines: Pipelines,
     pub(crate) textures: Textures,
+    pub(crate) capabilities: Capabilities,
+    // Which of the ping-pong bind groups holds the current field
+    pub(crate) phm_front: usize,
     pub(crate) controls: KeyboardState,
+    pub(crate) profiler: Profiler,
+    pub(crate) stats: StatsState,
+    pub(crate) readback: ReadbackPool,
+    // Asked for since the last frame was recorded
+    readback_requests: Vec<ReadbackRequest>,
+    pub(crate) ui: UiState,
+    pub(crate) smoother: ParamSmoother,
+    pub(crate) history: ParamHistory,
+    // The last preset saved or loaded, what revert goes back to
+    pub(crate) saved_preset: Option<Preset>,
+    // The loaded preset's LFOs and keyframe curves
+    pub(crate) modulations: Vec<ActiveModulation>,
+    pub(crate) export: ExportSettings,
+    // Where the checkpoint keys save and load
+    pub(crate) checkpoint_path: PathBuf,
+    pub(crate) sources: FoodSources,
+    // Index into DEBUG_CHANNELS of what the debug keys print and show
+    pub(crate) debug_channel: usize,
+    // Simulation steps since start or the last reset. The clock modulations
+    // run on.
+    pub(crate) step: u64,
+    // Steps each `update` records back-to-back
+    pub(crate) steps_per_frame: u64,
+    // Submit after each compute stage instead of once a frame, what the
+    // benchmark compares against
+    pub(crate) split_submits: bool,
+    pub(crate) paused: bool,
+    // Run one step on the next `update` while paused
+    pub(crate) single_step: bool,
+    pub(crate) show_agents: bool,
+    pub(crate) show_sensors: bool,
     pub(crate) app_time: std::time::Instant,
     // Keep window at the bottom,
-    // must be dropped after surface
-    pub(crate) window: std::sync::Arc<winit::window::Window>,
+    // must be dropped after surface. None headless.
+    pub(crate) window: Option<std::sync::Arc<winit::window::Window>>,
 }
 
 impl<'a> State<'a> {
-    pub(crate) async fn new(window: Arc<winit::window::Window>) -> Self {
-        let size = window.inner_size();
+    /// Without a window the simulation runs headless, nothing is rendered or
+    /// presented and the field is the same size
+    pub(crate) async fn new(window: Option<Arc<winit::window::Window>>, args: &Args) -> Self {
+        let size = window.as_ref().map_or(
+            winit::dpi::PhysicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+            |window| window.inner_size(),
+        );
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let instance = init_instance(args);
         let app_time = std::time::Instant::now();
 
         // SURFACE
-        let surface = instance
-            .create_surface(Arc::clone(&window))
-            .expect("surface init should work");
+        let surface = window.as_ref().map(|window| {
+            instance
+                .create_surface(Arc::clone(window))
+                .expect("surface init should work")
+        });
 
         // ADAPTER
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
+        let adapter = init_adapter(&instance, surface.as_ref(), args)
             .await
-            .expect("get_dev_storage_texture:: adapter should work");
+            .expect("no matching adapter, see --list-adapters");
+
+        let info = adapter.get_info();
+        println!("Adapter: {} ({:?})", info.name, info.backend);
 
         let limits = adapter.limits();
+        let capabilities = init_capabilities(&adapter, args);
+
+        // Only ask for what the chosen paths use
+        let mut required_features = wgpu::Features::empty();
+        if capabilities.read_write_storage {
+            required_features |= wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        }
+        if capabilities.float32_filterable {
+            required_features |= wgpu::Features::FLOAT32_FILTERABLE;
+        }
+        // Lets the profiler time passes on the GPU, it falls back to the CPU
+        required_features |= adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
 
         // DEVICE/QUEUE
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("dev_storage_texture_capable Device"),
-                    required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                        | wgpu::Features::FLOAT32_FILTERABLE,
+                    required_features,
                     required_limits: limits,
                 },
                 None,
@@ -72,42 +165,56 @@ impl<'a> State<'a> {
             .await
             .expect("get_dev_storage_texture:: device request should work");
 
-        let surface_caps = surface.get_capabilities(&adapter);
-
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .filter(|f| f.is_srgb())
-            .next()
-            .unwrap_or(surface_caps.formats[0]);
-
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            desired_maximum_frame_latency: 1,
-            view_formats: vec![wgpu::TextureFormat::Bgra8UnormSrgb],
-            alpha_mode: surface_caps.alpha_modes[0],
+        let surface_config = match &surface {
+            Some(surface) => {
+                let surface_config =
+                    init_surface_config(surface, &adapter, size, args.present_mode.present_mode());
+                println!(
+                    "Surface: {:?}, {:?}",
+                    surface_config.format, surface_config.present_mode
+                );
+                surface.configure(&device, &surface_config);
+                surface_config
+            }
+            None => init_headless_config(size),
         };
 
-        surface.configure(&device, &surface_config);
-
-        let shader_modules = init_shader_modules(&device);
-        let params = init_params();
-        let buffers = init_buffers(&device, &params);
-        let textures = init_textures(&device, &queue);
-        let bind_groups = init_bind_groups(
+        let shader_modules = init_shader_modules(&device, capabilities);
+        let mut params = init_params();
+        params.overlay_params.target_size = [size.width as f32, size.height as f32];
+        params.stats_params.coverage_threshold = args.stats_threshold;
+        let buffers = init_buffers(&device, &params, capabilities);
+        let textures = init_textures(&device, &queue, size, capabilities);
+        let bind_groups = init_bind_groups(&device, &buffers, &textures, capabilities);
+        let pipelines = init_pipelines(
+            &device,
+            &bind_groups,
+            &shader_modules,
+            surface_config.format,
+        );
+        let controls = KeyboardState::new(load_bindings(&args.bindings));
+        let ui = UiState::new(
             &device,
-            &buffers,
-            &textures.phm_view,
-            &textures.phm_sampler,
-            &textures.phm_extent,
+            window.as_deref(),
+            surface_config.format,
+            args.gui,
+            args.stats,
         );
-        let pipelines = init_pipelines(&device, &bind_groups, &shader_modules);
-        let controls = KeyboardState::new();
+
+        let mut profiler =
+            Profiler::new(&device, &queue, args.profile || args.profile_csv.is_some());
+        if let Some(path) = &args.profile_csv {
+            if let Err(e) = profiler.export_csv(path, capabilities.agents()) {
+                eprintln!("Error creating profiler CSV {:?}: {}", path, e);
+            }
+        }
+
+        let mut stats = StatsState::new(args.stats_interval);
+        if let Some(path) = &args.stats_csv {
+            if let Err(e) = stats.export_csv(path) {
+                eprintln!("Error creating stats CSV {:?}: {}", path, e);
+            }
+        }
 
         println!("adadpter.limts: {:#?}", adapter.limits());
         Self {
@@ -124,7 +231,41 @@ impl<'a> State<'a> {
             buffers,
             bind_groups,
             textures,
+            capabilities,
+            phm_front: 0,
             controls,
+            profiler,
+            stats,
+            readback: ReadbackPool::new(),
+            readback_requests: Vec::new(),
+            ui,
+            smoother: ParamSmoother::new(args.smoothing),
+            history: ParamHistory::new(),
+            saved_preset: None,
+            modulations: Vec::new(),
+            export: ExportSettings {
+                dir: args.export_dir.clone(),
+                formats: args.export_format.clone(),
+                png_range: args.png_range,
+                graph_threshold: args.graph_threshold,
+            },
+            checkpoint_path: args
+                .checkpoint
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_CHECKPOINT_PATH)),
+            sources: FoodSources {
+                radius: args.poi_radius,
+                strength: args.poi_strength,
+                ..Default::default()
+            },
+            debug_channel: 0,
+            step: 0,
+            steps_per_frame: args.steps_per_frame as u64,
+            split_submits: false,
+            paused: false,
+            single_step: false,
+            show_agents: false,
+            show_sensors: false,
             app_time,
             // Keep at bottom, must be dropped after surface
             // and declared after it
@@ -147,42 +288,658 @@ impl<'a> State<'a> {
             compute_pass.set_pipeline(&self.pipelines.init_slime);
             compute_pass.set_bind_group(0, &self.bind_groups.compute_bg, &[]);
             compute_pass.set_bind_group(1, &self.bind_groups.uniform_bg, &[]);
-            compute_pass.dispatch_workgroups(16, 16, 1); // Adjust workgroup size as needed
+            compute_pass.dispatch_workgroups(self.capabilities.agent_workgroups(), 1, 1);
         }
 
         self.queue.submit(Some(encoder.finish()));
     }
 
-    pub(crate) fn update(&mut self) {
-        update_agent_position(&self);
-        update_pheremone_trails(&self);
-        update_cpu_read_buffers(&self);
-        update_controls(self);
+    /// Re-seeds every agent with the current spawn pattern. Passing `None` keeps the
+    /// current seed so the same starting layout is reproduced.
+    pub(crate) fn reseed_slime(&mut self, seed: Option<u32>) {
+        if let Some(seed) = seed {
+            self.params.sim_params.seed = seed;
+            update_sim_params_buffer(self);
+        }
+
+        self.init_slime();
+        println!("Re-seeded slime with seed {}", self.params.sim_params.seed);
     }
 
-    pub(crate) fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    pub(crate) fn clear_pheremones(&mut self) {
+        clear_pheremone_texture(self);
+        println!("Cleared pheremone field");
+    }
+
+    /// Clears the pheremone field and re-seeds the agents, see [`State::reseed_slime`].
+    /// Modulations start again from step 0.
+    pub(crate) fn reset(&mut self, seed: Option<u32>) {
+        self.clear_pheremones();
+        self.reseed_slime(seed);
+        self.step = 0;
+    }
+
+    /// Derives a new seed from the current one, so a sequence of re-seeds is itself
+    /// reproducible.
+    pub(crate) fn next_seed(&self) -> u32 {
+        let mut z = self.params.sim_params.seed.wrapping_add(0x9E37_79B9);
+        z = (z ^ (z >> 16)).wrapping_mul(0x85EB_CA6B);
+        z = (z ^ (z >> 13)).wrapping_mul(0xC2B2_AE35);
+        z ^ (z >> 16)
+    }
+
+    pub(crate) fn boundary_mode(&self) -> BoundaryMode {
+        BoundaryMode::from_u32(self.params.sim_params.boundary_mode)
+    }
+
+    pub(crate) fn set_boundary_mode(&mut self, mode: BoundaryMode) {
+        self.params.sim_params.boundary_mode = mode as u32;
+        update_sim_params_buffer(self);
+        println!("Boundary mode: {:?}", mode);
+    }
+
+    pub(crate) fn agent_model(&self) -> AgentModel {
+        AgentModel::from_u32(self.params.sim_params.agent_model)
+    }
+
+    pub(crate) fn set_agent_model(&mut self, model: AgentModel) {
+        self.params.sim_params.agent_model = model as u32;
+        update_sim_params_buffer(self);
+        println!("Agent model: {:?}", model);
+    }
+
+    pub(crate) fn debug_view(&self) -> DebugView {
+        DebugView::from_u32(self.params.overlay_params.debug_view)
+    }
+
+    /// Shows the selected debug channel on the agents, fitting the colour
+    /// range to it when turned on
+    pub(crate) fn set_debug_view(&mut self, view: DebugView) {
+        self.params.overlay_params.debug_view = view as u32;
+        println!("Debug view: {:?}", view);
+        if view == DebugView::Off {
+            update_overlay_params_buffer(self);
+        } else {
+            self.fit_debug_range();
+        }
+    }
+
+    /// Selects a debug channel and which of its values the view shows, both
+    /// wrapping around
+    pub(crate) fn select_debug_channel(&mut self, channel: usize, component: usize) {
+        self.debug_channel = channel % DEBUG_CHANNELS.len();
+        let channel = &DEBUG_CHANNELS[self.debug_channel];
+        let component = component % channel.labels.len();
+
+        let overlay = &mut self.params.overlay_params;
+        overlay.debug_slot = debug_slot(self.debug_channel, self.capabilities.agents()) as u32;
+        overlay.debug_component = component as u32;
+        overlay.debug_stride = match channel.scope {
+            DebugScope::PerAgent => 1,
+            DebugScope::Global => 0,
+        };
+        println!(
+            "Debug channel: {}.{}",
+            channel.name, channel.labels[component]
+        );
+        if self.debug_view() == DebugView::Off {
+            update_overlay_params_buffer(self);
+        } else {
+            self.fit_debug_range();
+        }
+    }
+
+    /// Reads the debug channels back once and maps the shown value's current
+    /// range onto the colour ramp, a frame or two later. Dropped if another
+    /// value has been picked by then.
+    pub(crate) fn fit_debug_range(&mut self) {
+        let debug_channel = self.debug_channel;
+        let component = self.params.overlay_params.debug_component;
+        let agents = self.capabilities.agents();
+        self.read_back(
+            |buffers| &buffers.debug_channels_buf,
+            "debug channels",
+            Box::new(move |state, data| {
+                let overlay = &mut state.params.overlay_params;
+                if state.debug_channel != debug_channel
+                    || overlay.debug_component != component
+                    || state.capabilities.agents() != agents
+                {
+                    return;
+                }
+                let slots = bytemuck::pod_collect_to_vec(data);
+                let values = debug_channel_values(debug_channel, &slots, agents);
+                overlay.debug_range = component_range(values, component as usize);
+                println!(
+                    "Debug range: {} to {}",
+                    overlay.debug_range[0], overlay.debug_range[1]
+                );
+                update_overlay_params_buffer(state);
+            }),
+        );
+    }
+
+    pub(crate) fn print_debug_channel(&mut self) {
+        let index = self.debug_channel;
+        // What the buffer was sized for when it was copied
+        let agents = self.capabilities.agents();
+        self.read_back(
+            |buffers| &buffers.debug_channels_buf,
+            "debug channels",
+            Box::new(move |state, data| {
+                if state.capabilities.agents() != agents {
+                    return;
+                }
+                let slots = bytemuck::pod_collect_to_vec(data);
+                let values = debug_channel_values(index, &slots, agents);
+                println!("{}", format_debug_channel(&DEBUG_CHANNELS[index], values));
+            }),
+        );
+    }
+
+    pub(crate) fn print_agents(&mut self) {
+        self.read_back(
+            |buffers| &buffers.slime_pos_buf,
+            "agents",
+            Box::new(|_, data| print_gpu_data::<Slime>(data, "Slime")),
+        );
+    }
+
+    /// Copies a buffer back without stalling the frame. The copy goes into the
+    /// next frame's encoder after its steps, `callback` runs from `update`
+    /// once it has landed.
+    fn read_back(
+        &mut self,
+        source: fn(&Buffers) -> &wgpu::Buffer,
+        label: &'static str,
+        callback: ReadbackCallback,
+    ) {
+        self.readback_requests.push(ReadbackRequest {
+            source,
+            label,
+            callback,
+        });
+    }
+
+    /// Records the copies `read_back` asked for
+    fn record_readbacks(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        for request in std::mem::take(&mut self.readback_requests) {
+            let source = (request.source)(&self.buffers);
+            if !self.readback.request(
+                &self.device,
+                encoder,
+                source,
+                source.size(),
+                request.label,
+                request.callback,
+            ) {
+                eprintln!("Readback busy, {} not read", request.label);
+            }
+        }
+    }
+
+    /// Hands finished readbacks to their callbacks
+    pub(crate) fn poll_readbacks(&mut self) {
+        for (callback, data) in self.readback.poll(&self.device) {
+            callback(self, &data);
+        }
+    }
+
+    pub(crate) fn palette_mode(&self) -> PaletteMode {
+        PaletteMode::from_u32(self.params.palette_params.mode)
+    }
+
+    pub(crate) fn set_palette_mode(&mut self, mode: PaletteMode) {
+        self.params.palette_params.mode = mode as u32;
+        update_palette_params_buffer(self);
+        println!("Palette: {:?}", mode);
+    }
+
+    pub(crate) fn tonemap_mode(&self) -> TonemapMode {
+        TonemapMode::from_u32(self.params.post_params.tonemap)
+    }
+
+    pub(crate) fn set_tonemap_mode(&mut self, mode: TonemapMode) {
+        self.params.post_params.tonemap = mode as u32;
+        update_post_params_buffer(self);
+        println!("Tonemap: {:?}", mode);
+    }
+
+    /// Loads a gradient strip into the palette LUT, used by `PaletteMode::Lut`
+    pub(crate) fn load_palette_lut(&mut self, path: &Path) -> Result<(), image::ImageError> {
+        let texels = load_palette_lut(path)?;
+        update_palette_lut_texture(self, &texels);
+        self.params.palette_lut_path = Some(path.to_path_buf());
+        self.ui.lut_path = path.display().to_string();
+        println!("Loaded palette LUT from {}", path.display());
+        Ok(())
+    }
+
+    /// Saves where the parameters are heading, not where they've eased to
+    pub(crate) fn save_preset(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let targets = self.smoother.targets(&self.params);
+        let mut preset = preset_from_params(&unmodulated_params(&self.modulations, &targets));
+        preset.modulations = self
+            .modulations
+            .iter()
+            .map(|active| active.modulation.clone())
+            .collect();
+        save_preset(path, &preset)?;
+        self.saved_preset = Some(preset);
+        println!("Saved preset to {}", path.display());
+        Ok(())
+    }
+
+    /// Replaces every parameter with the preset's. The agents keep moving from
+    /// where they are, re-seed to restart from the preset's seed.
+    pub(crate) fn load_preset(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let preset = load_preset(path)?;
+        self.apply_preset(&preset)?;
+        self.saved_preset = Some(preset);
+        println!("Loaded preset from {}", path.display());
+        Ok(())
+    }
+
+    /// Goes back to the last preset saved or loaded, as one undo step
+    pub(crate) fn revert_preset(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(preset) = self.saved_preset.clone() else {
+            println!("No preset saved or loaded yet");
+            return Ok(());
+        };
+        self.apply_preset(&preset)?;
+        println!("Reverted to the last saved preset");
+        Ok(())
+    }
+
+    // Registered parameters go through `set_params`, so they ease in and undo
+    // as one step, everything else applies at once
+    fn apply_preset(&mut self, preset: &Preset) -> Result<(), Box<dyn Error>> {
+        // Read before anything changes, so a bad LUT leaves the old look
+        let lut = match &preset.palette.lut_path {
+            Some(path) => Some(
+                load_palette_lut(path)
+                    .map_err(|e| format!("palette LUT {}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+
+        let mut loaded = self.params.clone();
+        apply_preset_to_params(preset, &mut loaded);
+
+        self.modulations = resolve_modulations(&preset.modulations, &loaded);
+
+        let values = PARAMS
+            .iter()
+            .map(|info| (info, (info.get)(&loaded)))
+            .collect::<Vec<_>>();
+        for info in PARAMS {
+            *(info.get_mut)(&mut loaded) = (info.get)(&self.params);
+        }
+        self.params = loaded;
+
+        update_view_params_buffer(self);
+        update_slime_params_buffer(self);
+        update_jones_params_buffer(self);
+        update_pheremone_params_buffer(self);
+        update_sim_params_buffer(self);
+        update_palette_params_buffer(self);
+        update_post_params_buffer(self);
+
+        set_params(self, &values);
+
+        // A preset without a LUT of its own gets the grey ramp back, not
+        // whichever one was loaded last
+        update_palette_lut_texture(self, &lut.unwrap_or_else(default_palette_lut));
+        self.ui.lut_path = preset
+            .palette
+            .lut_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        Ok(())
+    }
+
+    /// Starts from a preset before the agents are seeded. Unlike
+    /// [`State::load_preset`] it applies at once and can't be undone.
+    pub(crate) fn load_initial_preset(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let preset = load_preset(path)?;
+        self.apply_preset_at_once(&preset)?;
+        self.saved_preset = Some(preset);
+        println!("Loaded preset from {}", path.display());
+        Ok(())
+    }
+
+    // `apply_preset` without easing or an undo step, for jumps that shouldn't
+    // be undone into
+    fn apply_preset_at_once(&mut self, preset: &Preset) -> Result<(), Box<dyn Error>> {
+        let smoothing = std::mem::replace(&mut self.smoother.time, 0.0);
+        let result = self.apply_preset(preset);
+        self.smoother.time = smoothing;
+        self.history = ParamHistory::new();
+        result
+    }
+
+    /// Saves the agents, field, parameters and step
+    pub(crate) fn save_checkpoint(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        write_checkpoint(path, &capture_checkpoint(self))?;
+        println!(
+            "Saved checkpoint to {} at step {}",
+            path.display(),
+            self.step
+        );
+        Ok(())
+    }
+
+    /// Carries on from a checkpoint exactly where it was saved
+    pub(crate) fn load_checkpoint(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let checkpoint = read_checkpoint(path, &self.device.limits())?;
+        let elapsed = Duration::try_from_secs_f32(checkpoint.header.time)
+            .map_err(|e| format!("bad checkpoint time {}: {}", checkpoint.header.time, e))?;
+        if checkpoint.header.agent_count != self.capabilities.agent_count {
+            self.set_agent_count(checkpoint.header.agent_count)?;
+        }
+        restore_checkpoint(self, &checkpoint)?;
+
+        let header = checkpoint.header;
+        // Only the palette LUT can fail, the simulation is restored either
+        // way so the run carries on with the grey ramp
+        if let Err(e) = self.apply_preset_at_once(&header.preset) {
+            eprintln!("Error loading the checkpoint's palette LUT: {}", e);
+            let mut preset = header.preset.clone();
+            preset.palette.lut_path = None;
+            self.apply_preset_at_once(&preset)?;
+        }
+        self.params.view_params = header.view_params;
+        self.params.post_params = header.post_params;
+        update_view_params_buffer(self);
+        update_post_params_buffer(self);
+
+        self.saved_preset = Some(header.preset);
+        self.step = header.step;
+        self.app_time = Instant::now()
+            .checked_sub(elapsed)
+            .unwrap_or_else(Instant::now);
+
+        println!(
+            "Loaded checkpoint from {} at step {}",
+            path.display(),
+            self.step
+        );
+        Ok(())
+    }
+
+    /// Rebuilds the shaders, agent buffers, bind groups and pipelines for
+    /// `agent_count` agents. The agents are all zero until they're seeded or
+    /// restored.
+    pub(crate) fn set_agent_count(&mut self, agent_count: u32) -> Result<(), String> {
+        let capabilities = Capabilities {
+            agent_count,
+            ..self.capabilities
+        };
+        check_agent_count(capabilities, &self.device.limits())?;
+
+        self.capabilities = capabilities;
+        self.shader_modules = init_shader_modules(&self.device, capabilities);
+        (self.buffers.slime_pos_buf, self.buffers.debug_channels_buf) =
+            init_agent_buffers(&self.device, capabilities);
+        self.bind_groups =
+            init_bind_groups(&self.device, &self.buffers, &self.textures, capabilities);
+        self.pipelines = init_pipelines(
+            &self.device,
+            &self.bind_groups,
+            &self.shader_modules,
+            self.surface_config.format,
+        );
+
+        self.params.overlay_params.debug_slot =
+            debug_slot(self.debug_channel, capabilities.agents()) as u32;
+        update_overlay_params_buffer(self);
+        println!("Rebuilt for {} agents", agent_count);
+        Ok(())
+    }
+
+    /// Loads the food sources and land mask given on the command line. They
+    /// stay in place through resets and checkpoints.
+    pub(crate) fn load_sources(&mut self, args: &Args) -> Result<(), Box<dyn Error>> {
+        let extent = self.textures.phm_extent;
+
+        if let Some(path) = &args.land_mask {
+            let land = read_land_mask(path, extent)?;
+            println!(
+                "Loaded land mask from {}, {:.1}% land",
+                path.display(),
+                land.iter().filter(|&&land| land).count() as f32 * 100.0 / land.len() as f32
+            );
+            self.sources.land = Some(land);
+        }
+
+        if let Some(path) = &args.poi {
+            self.sources.points = read_points(path, args.poi_bounds, extent)?;
+            println!(
+                "Loaded {} food sources from {}",
+                self.sources.points.len(),
+                path.display()
+            );
+        }
+
+        update_sources_buffer(self, &source_texels(&self.sources, extent));
+        Ok(())
+    }
+
+    /// Writes the current pheremone field in every format given on the command
+    /// line, into the export directory
+    pub(crate) fn export_fields(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let paths = export_fields(self)?;
+        for path in &paths {
+            println!("Exported field to {}", path.display());
+        }
+        Ok(paths)
+    }
+
+    /// Extracts the transport network from the current field and writes it as
+    /// GraphML and GeoJSON into the export directory
+    pub(crate) fn extract_graph(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let paths = export_graph(self)?;
+        for path in &paths {
+            println!("Exported graph to {}", path.display());
+        }
+        Ok(paths)
+    }
+
+    /// Runs the GUI, then records this frame's simulation steps into a new
+    /// encoder, which `render` finishes and submits
+    pub(crate) fn update(&mut self) -> wgpu::CommandEncoder {
+        self.run_ui();
+        let steps = if self.paused { 1 } else { self.steps_per_frame };
+        self.update_steps(steps)
+    }
+
+    /// Handles the controls and landed readbacks, then records `steps` steps.
+    /// Nothing is recorded before the controls run, so a save reads back the
+    /// step it's stamped with and a load isn't run over by older steps.
+    pub(crate) fn update_steps(&mut self, steps: u64) -> wgpu::CommandEncoder {
+        self.profiler.begin_frame();
+        self.poll_readbacks();
+        update_controls(self);
+
+        update_time_uniform_buffer(self);
+        advance_params(self);
 
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
+                label: Some("Frame Encoder"),
             });
 
+        if !self.paused || std::mem::take(&mut self.single_step) {
+            self.encode_steps(&mut encoder, steps);
+        }
+        self.record_readbacks(&mut encoder);
+
+        encoder
+    }
+
+    /// Records `steps` simulation steps. Runs of steps between statistics
+    /// samples share a compute pass, unless the passes are being timed or
+    /// submitted one by one. With modulations active every step is submitted
+    /// on its own, queued parameter writes only land between submits.
+    fn encode_steps(&mut self, encoder: &mut wgpu::CommandEncoder, steps: u64) {
+        let modulated = !self.modulations.is_empty();
+        let mut remaining = steps;
+        while remaining > 0 {
+            let run = if modulated {
+                modulate_params(self);
+                1
+            } else {
+                (1..=remaining)
+                    .find(|&run| self.stats.due(self.step + run))
+                    .unwrap_or(remaining)
+            };
+
+            if self.profiler.enabled() || self.split_submits {
+                for _ in 0..run {
+                    update_agent_position(self, encoder);
+                    self.end_stage(encoder, ProfiledPass::Movement);
+                    update_pheremone_trails(self, encoder);
+                    self.end_stage(encoder, ProfiledPass::Diffusion);
+                    if !self.capabilities.read_write_storage {
+                        self.phm_front ^= 1;
+                    }
+                }
+            } else {
+                self.phm_front = update_sim_steps(self, encoder, run);
+            }
+
+            self.step += run;
+            update_sim_stats(self, encoder);
+            remaining -= run;
+
+            if modulated && remaining > 0 {
+                self.submit_encoder(encoder);
+            }
+        }
+    }
+
+    /// Ends a compute stage. Only submits when the CPU is timing the stages
+    /// or submits are split, everything else goes out once a frame.
+    pub(crate) fn end_stage(&self, encoder: &mut wgpu::CommandEncoder, pass: ProfiledPass) {
+        if !self.profiler.cpu_timing() && !self.split_submits {
+            return;
+        }
+
+        self.submit_encoder(encoder);
+        self.profiler.cpu_pass(&self.device, pass);
+    }
+
+    /// Submits what `encoder` has recorded so far and swaps in a fresh one
+    fn submit_encoder(&self, encoder: &mut wgpu::CommandEncoder) {
+        let recorded = std::mem::replace(
+            encoder,
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Frame Encoder"),
+                }),
+        );
+        self.queue.submit(Some(recorded.finish()));
+    }
+
+    /// Submits the frame with the profiler's timestamps, then starts mapping
+    /// what it read back. `pass` is what the CPU fallback times the submit as.
+    fn submit_frame(&mut self, mut encoder: wgpu::CommandEncoder, pass: Option<ProfiledPass>) {
+        if let Some((timestamps, size, callback)) = self.profiler.resolve(&mut encoder) {
+            if !self.readback.request(
+                &self.device,
+                &mut encoder,
+                timestamps,
+                size,
+                "profiler",
+                callback,
+            ) {
+                eprintln!("Readback busy, profiler timings not read");
+            }
+        }
+        self.queue.submit(Some(encoder.finish()));
+        if let Some(pass) = pass {
+            self.profiler.cpu_pass(&self.device, pass);
+        }
+        self.readback.map_requested();
+        self.profiler.end_frame();
+    }
+
+    /// A frame without a window, `update` and the end of the frame `render`
+    /// would do
+    pub(crate) fn step_headless(&mut self, steps: u64) {
+        let encoder = self.update_steps(steps);
+        self.submit_frame(encoder, None);
+    }
+
+    /// Builds this frame's HUD and GUI before the steps are recorded, so GUI
+    /// edits apply to them. `render` paints the output.
+    fn run_ui(&mut self) {
+        self.ui.tick();
+        if !self.ui.visible() {
+            return;
+        }
+        let Some(window) = self.window.clone() else {
+            return;
+        };
+
+        let raw_input = self.ui.take_input(&window);
+        let ctx = self.ui.ctx.clone();
+
+        let output = ctx.run(raw_input, |ctx| {
+            if self.ui.show_hud {
+                draw_hud(ctx, self);
+            }
+            if self.ui.show_gui {
+                draw_gui(ctx, self);
+            }
+            if self.ui.show_stats {
+                draw_stats(ctx, self);
+            }
+        });
+        self.ui.output = Some(output);
+    }
+
+    /// Records the render passes after `update`'s steps and submits the
+    /// frame. The steps are submitted even if there's nothing to present.
+    pub(crate) fn render(
+        &mut self,
+        mut encoder: wgpu::CommandEncoder,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let ui_output = self.ui.output.take();
+
+        // Nothing to present headless
+        let Some(surface) = &self.surface else {
+            self.submit_frame(encoder, None);
+            return Ok(());
+        };
+        let output = match surface.get_current_texture() {
+            Ok(output) => output,
+            Err(e) => {
+                self.submit_frame(encoder, None);
+                return Err(e);
+            }
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let hdr_view = &self.textures.post.hdr_view;
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: hdr_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
+                timestamp_writes: self
+                    .profiler
+                    .render_timestamps(ProfiledPass::Field, true, true),
                 ..Default::default()
             });
 
@@ -191,7 +948,7 @@ impl<'a> State<'a> {
             render_pass.set_bind_group(0, &self.bind_groups.compute_bg, &[]);
             render_pass.set_bind_group(1, &self.bind_groups.uniform_bg, &[]);
             render_pass.set_bind_group(2, &self.bind_groups.param_bg, &[]);
-            render_pass.set_bind_group(3, &self.bind_groups.sampled_phm_bg, &[]);
+            render_pass.set_bind_group(3, &self.bind_groups.sampled_phm_bgs[self.phm_front], &[]);
             render_pass.set_vertex_buffer(0, self.buffers.vertex_buf.slice(..));
 
             let vertex_range = 0..VERTICES.len() as u32;
@@ -199,7 +956,55 @@ impl<'a> State<'a> {
             render_pass.draw(vertex_range, instance_range);
         }
 
-        self.queue.submit(Some(encoder.finish()));
+        // Separate pass, the field binds the agents read-write and the
+        // overlay binds them read-only, which can't share a usage scope
+        let show_debug = self.debug_view() != DebugView::Off;
+        if self.show_sensors || self.show_agents || show_debug {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Agent Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: hdr_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                timestamp_writes: self.profiler.render_timestamps(
+                    ProfiledPass::Overlay,
+                    true,
+                    true,
+                ),
+                ..Default::default()
+            });
+
+            render_pass.set_bind_group(0, &self.bind_groups.agent_draw_bg, &[]);
+
+            if self.show_sensors {
+                render_pass.set_pipeline(&self.pipelines.draw_sensors);
+                render_pass.draw(0..6, 0..self.capabilities.agent_count * 3);
+            }
+
+            if self.show_agents || show_debug {
+                render_pass.set_pipeline(&self.pipelines.draw_agents);
+                render_pass.draw(0..6, 0..self.capabilities.agent_count);
+            }
+        }
+
+        encode_post_processing(self, &mut encoder, &view);
+
+        if let (Some(ui_output), Some(window)) = (ui_output, &self.window) {
+            self.ui.paint(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &view,
+                window,
+                ui_output,
+            );
+        }
+
+        self.submit_frame(encoder, Some(ProfiledPass::Render));
         output.present();
 
         Ok(())
@@ -210,7 +1015,23 @@ impl<'a> State<'a> {
             self.size = new_size;
             self.surface_config.width = new_size.width;
             self.surface_config.height = new_size.height;
-            self.surface.configure(&self.device, &self.surface_config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.surface_config);
+            }
+
+            self.params.overlay_params.target_size =
+                [new_size.width as f32, new_size.height as f32];
+            update_overlay_params_buffer(self);
+
+            self.textures.post = init_post_textures(&self.device, new_size);
+            self.bind_groups.post = init_post_bind_groups(
+                &self.device,
+                &self.bind_groups.post_bgl,
+                &self.bind_groups.post_composite_bgl,
+                &self.bind_groups.exposure_bgl,
+                &self.buffers,
+                &self.textures,
+            );
         }
     }
 
@@ -218,3 +1039,70 @@ impl<'a> State<'a> {
         self.app_time.elapsed().as_secs_f32()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checkpoint::checkpoint_functions::capture_checkpoint, state::action_state::Action,
+        Checkpoint,
+    };
+    use clap::Parser;
+
+    // None without an adapter to run on
+    fn headless_state(checkpoint: &Path) -> Option<State<'static>> {
+        let args = Args::parse_from([
+            "slime-wgpu",
+            "--headless",
+            "--checkpoint",
+            checkpoint.to_str()?,
+        ]);
+        // State::new panics without an adapter. A second instance to check
+        // for one first would tear down the GL display the state runs on.
+        let mut state =
+            std::panic::catch_unwind(|| futures::executor::block_on(State::new(None, &args)))
+                .ok()?;
+        state.init_slime();
+        Some(state)
+    }
+
+    fn agent_bytes(checkpoint: &Checkpoint) -> &[u8] {
+        bytemuck::cast_slice(&checkpoint.agents)
+    }
+
+    #[test]
+    fn keys_act_before_the_frames_steps() {
+        let path = std::env::temp_dir().join(format!("slime_ordering_{}.bin", std::process::id()));
+        let Some(mut state) = headless_state(&path) else {
+            eprintln!("No adapter, skipping");
+            return;
+        };
+
+        state.step_headless(2);
+        let before = capture_checkpoint(&state);
+
+        // Saved before the frame's steps, with the step it was taken at
+        state.controls.tap(Action::SaveCheckpoint);
+        state.step_headless(3);
+        state.controls.clear_keys();
+        assert_eq!(state.step, 5);
+        let after = capture_checkpoint(&state);
+
+        let saved = read_checkpoint(&path, &state.device.limits()).unwrap();
+        assert_eq!(saved.header.step, 2);
+        assert_eq!(agent_bytes(&saved), agent_bytes(&before));
+        assert_eq!(saved.field, before.field);
+
+        // Loaded before the frame's steps, which carry on from it to the
+        // same place
+        state.controls.tap(Action::LoadCheckpoint);
+        state.step_headless(3);
+        state.controls.clear_keys();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(state.step, 5);
+        let replayed = capture_checkpoint(&state);
+        assert_eq!(agent_bytes(&replayed), agent_bytes(&after));
+        assert_eq!(replayed.field, after.field);
+    }
+}
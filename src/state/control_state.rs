@@ -1,59 +1,209 @@
-use std::collections::HashSet;
-use std::thread;
-use std::time;
-
-use winit::keyboard::{KeyCode, PhysicalKey};
-
-use crate::updates::update_functions::update_pheremone_params_buffer;
-use crate::updates::update_functions::update_slime_params_buffer;
-use crate::updates::update_functions::update_view_params_buffer;
-use crate::Slime;
-use crate::NUM_AGENTS;
-
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+
+use crate::params::param_functions::{
+    find_param, format_param, nudge_param, param_name, param_target, redo_params, set_param,
+    undo_params,
+};
+use crate::updates::update_functions::update_palette_params_buffer;
+use crate::updates::update_functions::update_post_params_buffer;
+use crate::AgentModel;
+use crate::ParamScale;
+use crate::DEFAULT_PRESET_PATH;
+
+use super::action_state::{Action, Bindings};
 use super::app_state::State;
 
-#[derive(Debug, Copy, Clone)]
+// How long an adjustment is held before it starts repeating, and how often
+// it repeats after that
+const REPEAT_DELAY: Duration = Duration::from_millis(300);
+const REPEAT_INTERVAL: Duration = Duration::from_millis(33);
+// Growth of the repeated step per second held past the delay, and its cap
+const REPEAT_ACCELERATION: f32 = 2.0;
+const MAX_REPEAT_SCALE: f32 = 16.0;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub(crate) enum KeyboardMode {
     DEBUG,
     SLIME,
     PHEREMONES,
     VIEW,
     PRINT,
+    POST,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RepeatTimer {
+    started: Instant,
+    next: Instant,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct KeyboardState {
-    keys: HashSet<winit::keyboard::PhysicalKey>,
+    keys: HashSet<KeyCode>,
+    // Keys that went down since the last `begin_frame`, so a tap shorter
+    // than a frame still counts
+    just_pressed: HashSet<KeyCode>,
+    modifiers: ModifiersState,
+    bindings: Bindings,
+    // Action edges, worked out once per frame in `begin_frame`
+    held: HashSet<Action>,
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
+    repeat_timers: HashMap<Action, RepeatTimer>,
+    // Step scale of the actions that fire a repeat this frame
+    repeats: HashMap<Action, f32>,
     mode: KeyboardMode,
 }
 
 impl KeyboardState {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(bindings: Bindings) -> Self {
         Self {
             keys: HashSet::new(),
+            just_pressed: HashSet::new(),
+            modifiers: ModifiersState::empty(),
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            repeat_timers: HashMap::new(),
+            repeats: HashMap::new(),
             mode: KeyboardMode::VIEW,
         }
     }
 
-    pub(crate) fn key_pressed(&self, key: winit::keyboard::PhysicalKey) -> bool {
-        self.keys.contains(&key)
-    }
-
     pub(crate) fn handle_keyboard_input(&mut self, input: &winit::event::KeyEvent) {
-        let key = input.physical_key;
+        let PhysicalKey::Code(key) = input.physical_key else {
+            return;
+        };
+
+        // OS key repeat is ignored, held actions repeat on their own timer
         if input.state == winit::event::ElementState::Pressed {
+            if !input.repeat {
+                self.just_pressed.insert(key);
+            }
             self.keys.insert(key);
         } else {
             self.keys.remove(&key);
         }
     }
 
+    pub(crate) fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    /// Taps `action`'s first binding, as if its keys went down and up within
+    /// a frame. Its modifiers stay down until `clear_keys`.
+    #[cfg(test)]
+    pub(crate) fn tap(&mut self, action: Action) {
+        let binding = self.bindings.get(action)[0].clone();
+        self.modifiers = binding.modifiers;
+        self.just_pressed.extend(binding.keys);
+    }
+
     pub(crate) fn clear_keys(&mut self) {
         self.keys.clear();
+        self.just_pressed.clear();
+        self.modifiers = ModifiersState::empty();
+    }
+
+    /// Works out which actions were pressed, released, held or repeat this frame
+    pub(crate) fn begin_frame(&mut self, agent_model: AgentModel) {
+        let now = Instant::now();
+
+        let candidates = Action::ALL
+            .iter()
+            .filter(|action| action.mode().is_none_or(|mode| mode == self.mode))
+            .filter(|action| {
+                action
+                    .agent_model()
+                    .is_none_or(|model| model == agent_model)
+            })
+            .filter_map(|&action| {
+                self.bindings
+                    .get(action)
+                    .iter()
+                    .filter(|binding| binding.modifiers == self.modifiers)
+                    .filter(|binding| {
+                        binding
+                            .keys
+                            .iter()
+                            .all(|key| self.keys.contains(key) || self.just_pressed.contains(key))
+                    })
+                    .max_by_key(|binding| binding.keys.len())
+                    .map(|binding| (action, binding.keys.clone()))
+            })
+            .collect::<Vec<(Action, Vec<KeyCode>)>>();
+
+        // A chord shadows any held chord made of a subset of its keys,
+        // so S+D+ArrowUp doesn't also fire a binding on S+ArrowUp
+        let held = candidates
+            .iter()
+            .filter(|(_, keys)| {
+                !candidates.iter().any(|(_, other)| {
+                    other.len() > keys.len() && keys.iter().all(|key| other.contains(key))
+                })
+            })
+            .map(|(action, _)| *action)
+            .collect::<HashSet<Action>>();
+
+        self.pressed = held.difference(&self.held).copied().collect();
+        self.released = self.held.difference(&held).copied().collect();
+        self.held = held;
+
+        self.repeats.clear();
+        self.repeat_timers
+            .retain(|action, _| self.held.contains(action));
+
+        for &action in &self.held {
+            if self.pressed.contains(&action) {
+                self.repeat_timers.insert(
+                    action,
+                    RepeatTimer {
+                        started: now,
+                        next: now + REPEAT_DELAY,
+                    },
+                );
+                self.repeats.insert(action, 1.0);
+            } else if let Some(timer) = self.repeat_timers.get_mut(&action) {
+                if now >= timer.next {
+                    timer.next = now + REPEAT_INTERVAL;
+                    let repeating = (now - timer.started).saturating_sub(REPEAT_DELAY);
+                    let scale = 1.0 + repeating.as_secs_f32() * REPEAT_ACCELERATION;
+                    self.repeats.insert(action, scale.min(MAX_REPEAT_SCALE));
+                }
+            }
+        }
+
+        self.just_pressed.clear();
+    }
+
+    /// The action's keys went down this frame
+    pub(crate) fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// The action's keys were let go this frame
+    pub(crate) fn released(&self, action: Action) -> bool {
+        self.released.contains(&action)
     }
 
-    pub(crate) fn get_keys(&self) -> &HashSet<winit::keyboard::PhysicalKey> {
-        &self.keys
+    /// The action's keys are down, every frame
+    pub(crate) fn held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+
+    /// Fires on press, then after `REPEAT_DELAY` at `REPEAT_INTERVAL` with a
+    /// step scale that grows the longer the action is held
+    pub(crate) fn repeat(&self, action: Action) -> Option<f32> {
+        self.repeats.get(&action).copied()
+    }
+
+    pub(crate) fn bindings(&self) -> &Bindings {
+        &self.bindings
     }
 
     pub(crate) fn get_mode(&self) -> &KeyboardMode {
@@ -65,234 +215,264 @@ impl KeyboardState {
     }
 }
 
-pub(crate) fn print_gpu_data<T: bytemuck::Pod + std::fmt::Debug>(
-    device: &wgpu::Device,
-    buffer: &wgpu::Buffer,
-    obj_label: &str,
-) {
-    // Map the buffer for reading
-    let buffer_slice = buffer.slice(..);
-    let (tx, rx) = futures::channel::oneshot::channel();
-
-    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-        tx.send(result).unwrap();
-    });
+pub(crate) fn print_gpu_data<T: bytemuck::Pod + std::fmt::Debug>(data: &[u8], obj_label: &str) {
+    let data: Vec<T> = bytemuck::pod_collect_to_vec(data);
 
-    println!("buffer size: {:?}", buffer.size());
-    // Wait for the GPU to finish executing the commands
-    device.poll(wgpu::Maintain::Wait);
-    // Wait for the buffer to be mapped
-    let result = futures::executor::block_on(rx);
-
-    match result {
-        Ok(_) => {
-            let buf_view = buffer_slice.get_mapped_range();
-            let data: &[T] = bytemuck::cast_slice(&buf_view);
-
-            // Print the boids current properties
-            for (i, obj) in data.iter().enumerate() {
-                println!("{} {}:\n{:?}", obj_label, i, obj);
-            }
-
-            drop(buf_view);
-            buffer.unmap();
-        }
-        Err(e) => eprintln!("Error retrieving gpu data: {:?}", e),
+    println!("buffer size: {:?}", std::mem::size_of_val(data.as_slice()));
+    for (i, obj) in data.iter().enumerate() {
+        println!("{} {}:\n{:?}", obj_label, i, obj);
     }
 }
 
 pub(crate) fn update_controls(state: &mut State) {
-    if state.controls.key_pressed(PhysicalKey::Code(KeyCode::KeyP)) {
+    let agent_model = state.agent_model();
+    state.controls.begin_frame(agent_model);
+
+    if state.controls.pressed(Action::ModeDebug) {
         state.controls.set_mode(KeyboardMode::DEBUG);
-    } else if state
-        .controls
-        .key_pressed(PhysicalKey::Code(KeyCode::Digit1))
-    {
+    } else if state.controls.pressed(Action::ModeView) {
         state.controls.set_mode(KeyboardMode::VIEW);
-    } else if state
-        .controls
-        .key_pressed(PhysicalKey::Code(KeyCode::Digit2))
-    {
+    } else if state.controls.pressed(Action::ModeSlime) {
         state.controls.set_mode(KeyboardMode::SLIME);
-    } else if state
-        .controls
-        .key_pressed(PhysicalKey::Code(KeyCode::Digit3))
-    {
+    } else if state.controls.pressed(Action::ModePheremones) {
         state.controls.set_mode(KeyboardMode::PHEREMONES);
-    } else if state
-        .controls
-        .key_pressed(PhysicalKey::Code(KeyCode::Digit4))
-    {
+    } else if state.controls.pressed(Action::ModePrint) {
         state.controls.set_mode(KeyboardMode::PRINT);
+    } else if state.controls.pressed(Action::ModePost) {
+        state.controls.set_mode(KeyboardMode::POST);
     }
 
+    reset_controls(state);
+    ui_controls(state);
+    boundary_controls(state);
+    agent_model_controls(state);
+    preset_controls(state);
+
+    param_controls(state);
+
     match state.controls.get_mode() {
         KeyboardMode::DEBUG => debug_controls(state),
-        KeyboardMode::SLIME => slime_controls(state),
-        KeyboardMode::PHEREMONES => pheremone_controls(state),
         KeyboardMode::VIEW => view_controls(state),
         KeyboardMode::PRINT => print_controls(state),
+        KeyboardMode::POST => post_controls(state),
+        // Everything in these modes is a parameter
+        KeyboardMode::SLIME | KeyboardMode::PHEREMONES => {}
     }
 }
 
-fn debug_controls(state: &mut State) {
-    let pressed = state.controls.get_keys();
+// Available in every mode
+fn reset_controls(state: &mut State) {
+    if state.controls.pressed(Action::Reseed) {
+        state.reseed_slime(None);
+    } else if state.controls.pressed(Action::ReseedNew) {
+        let seed = state.next_seed();
+        state.reseed_slime(Some(seed));
+    } else if state.controls.pressed(Action::ClearField) {
+        state.clear_pheremones();
+    } else if state.controls.pressed(Action::Reset) {
+        state.reset(None);
+    } else if state.controls.pressed(Action::ToggleProfiler) {
+        state.profiler.toggle();
+    }
+}
 
-    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS)) {
-        print_gpu_data::<[f32; 4]>(
-            &state.device,
-            &state.buffers.cpu_read_generic_debug_buf,
-            "Debug",
-        );
-        thread::sleep(time::Duration::from_millis(50));
-        state.controls.set_mode(KeyboardMode::VIEW);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyA)) {
-        print_gpu_data::<[[f32; 4]; NUM_AGENTS]>(
-            &state.device,
-            &state.buffers.cpu_read_generic_debug_array_buf,
-            "Debug Array",
-        );
-        thread::sleep(time::Duration::from_millis(50));
-        state.controls.set_mode(KeyboardMode::VIEW);
+// Available in every mode
+fn ui_controls(state: &mut State) {
+    if state.controls.pressed(Action::ToggleHud) {
+        state.ui.toggle_hud();
+    } else if state.controls.pressed(Action::ToggleGui) {
+        state.ui.toggle_gui();
+    } else if state.controls.pressed(Action::ToggleStats) {
+        state.ui.toggle_stats();
     }
 }
 
-fn slime_controls(state: &mut State) {
-    let pressed = state.controls.get_keys();
-    let mut dval = 0.0f32;
-
-    if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
-        dval = 1.0f32;
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
-        dval = -1.0f32;
-    }
-
-    // MOVEMENT
-    if pressed.contains(&PhysicalKey::Code(KeyCode::Period)) {
-        let maxv = &mut state.params.slime_params.max_velocity;
-        *maxv = f32::max(0.1, *maxv + (1e-5f32 * dval));
-        update_slime_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::Comma)) {
-        let minv = &mut state.params.slime_params.min_velocity;
-        *minv = f32::max(0.0, *minv + (1e-5f32 * dval));
-        update_slime_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyT)) {
-        let tf = &mut state.params.slime_params.turn_factor;
-        *tf = f32::max(0.0, *tf + (1e-6f32 * dval));
-        update_slime_params_buffer(state);
-
-    // SENSORS
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS))
-        && pressed.contains(&PhysicalKey::Code(KeyCode::KeyD))
-    {
-        let tf = &mut state.params.slime_params.sensor_dist;
-        *tf = f32::max(0.0, *tf + (0.001 * dval));
-        update_slime_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS))
-        && pressed.contains(&PhysicalKey::Code(KeyCode::KeyA))
-    {
-        let tf = &mut state.params.slime_params.sensor_offset;
-        *tf = f32::max(0.0, *tf + (0.1 * dval));
-        update_slime_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS))
-        && pressed.contains(&PhysicalKey::Code(KeyCode::KeyR))
-    {
-        let tf = &mut state.params.slime_params.sensor_radius;
-        *tf = f32::max(0.0, *tf + (0.001 * dval));
-        update_slime_params_buffer(state);
+// Available in every mode
+fn boundary_controls(state: &mut State) {
+    if state.controls.pressed(Action::CycleBoundary) {
+        let mode = state.boundary_mode().next();
+        state.set_boundary_mode(mode);
+    }
+}
+
+// Available in every mode
+fn agent_model_controls(state: &mut State) {
+    if state.controls.pressed(Action::CycleAgentModel) {
+        let model = state.agent_model().next();
+        state.set_agent_model(model);
+    }
+}
+
+// Available in every mode
+fn preset_controls(state: &mut State) {
+    let path = std::path::Path::new(DEFAULT_PRESET_PATH);
+
+    if state.controls.pressed(Action::SavePreset) {
+        if let Err(e) = state.save_preset(path) {
+            eprintln!("Error saving preset: {}", e);
+        }
+    } else if state.controls.pressed(Action::LoadPreset) {
+        if let Err(e) = state.load_preset(path) {
+            eprintln!("Error loading preset: {}", e);
+        }
+    } else if state.controls.pressed(Action::RevertPreset) {
+        if let Err(e) = state.revert_preset() {
+            eprintln!("Error reverting preset: {}", e);
+        }
+    } else if state.controls.pressed(Action::SaveCheckpoint) {
+        if let Err(e) = state.save_checkpoint(&state.checkpoint_path) {
+            eprintln!("Error saving checkpoint: {}", e);
+        }
+    } else if state.controls.pressed(Action::LoadCheckpoint) {
+        let path = state.checkpoint_path.clone();
+        if let Err(e) = state.load_checkpoint(&path) {
+            eprintln!("Error loading checkpoint: {}", e);
+        }
+    }
+
+    if state.controls.repeat(Action::Undo).is_some() {
+        undo_params(state);
+    } else if state.controls.repeat(Action::Redo).is_some() {
+        redo_params(state);
+    }
+}
+
+fn debug_controls(state: &mut State) {
+    if state.controls.pressed(Action::PrintDebugChannel) {
+        state.print_debug_channel();
+    } else if state.controls.pressed(Action::CycleDebugChannel) {
+        state.select_debug_channel(state.debug_channel + 1, 0);
+    } else if state.controls.pressed(Action::CycleDebugComponent) {
+        state.select_debug_channel(
+            state.debug_channel,
+            state.params.overlay_params.debug_component as usize + 1,
+        );
+    } else if state.controls.pressed(Action::CycleDebugView) {
+        state.set_debug_view(state.debug_view().next());
+    } else if state.controls.pressed(Action::FitDebugRange) {
+        state.fit_debug_range();
     }
 }
 
-fn pheremone_controls(state: &mut State) {
-    let pressed = state.controls.get_keys();
-    let mut dval = 0.0f32;
-
-    if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
-        dval = 1.0f32;
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
-        dval = -1.0f32;
-    }
-
-    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyA)) {
-        let maxv = &mut state.params.pheremone_params.deposition_amount;
-        *maxv = f32::max(0.0, *maxv + (0.003 * dval));
-        update_pheremone_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS)) {
-        let minv = &mut state.params.pheremone_params.diffusion_factor;
-        *minv = f32::max(0.0, *minv + (0.03 * dval));
-        update_pheremone_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyD)) {
-        let tf = &mut state.params.pheremone_params.decay_factor;
-        *tf = f32::max(0.0, *tf + (0.003 * dval));
-        update_pheremone_params_buffer(state);
+// Up/down actions of the registered parameters, only the current mode's are
+// active. Linear parameters step on the repeat timer, log ones scale every
+// frame they're held so zoom and exposure change smoothly.
+fn param_controls(state: &mut State) {
+    for action in Action::ALL {
+        let Some((name, direction)) = action.param() else {
+            continue;
+        };
+        let info = find_param(name).expect("actions should step registered params");
+
+        let steps = match info.scale {
+            ParamScale::Linear => state.controls.repeat(action),
+            ParamScale::Log => state.controls.held(action).then_some(1.0),
+        };
+        if let Some(steps) = steps {
+            nudge_param(state, info, direction * steps);
+        }
+
+        if state.controls.released(action) {
+            let value = param_target(state, info);
+            println!("{} = {}", param_name(info), format_param(info, value));
+        }
     }
 }
 
 fn view_controls(state: &mut State) {
-    let pressed = state.controls.get_keys();
-
-    if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowLeft)) {
-        state.params.view_params.x_shift -=
-            (0.01 * state.params.view_params.shift_modifier) / state.params.view_params.zoom;
-        update_view_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowRight)) {
-        state.params.view_params.x_shift +=
-            (0.01 * state.params.view_params.shift_modifier) / state.params.view_params.zoom;
-        update_view_params_buffer(state);
-    }
-
-    if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowUp)) {
-        state.params.view_params.y_shift +=
-            (0.01 * state.params.view_params.shift_modifier) / state.params.view_params.zoom;
-        update_view_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::ArrowDown)) {
-        state.params.view_params.y_shift -=
-            (0.01 * state.params.view_params.shift_modifier) / state.params.view_params.zoom;
-        update_view_params_buffer(state);
-    }
-
-    if pressed.contains(&PhysicalKey::Code(KeyCode::PageDown)) {
-        state.params.view_params.shift_modifier -= 0.1;
-        update_view_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::PageUp)) {
-        state.params.view_params.shift_modifier += 0.1;
-        update_view_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyX)) {
-        let mz = state.params.view_params.zoom;
-        state.params.view_params.zoom -= 0.1 * mz;
-        update_view_params_buffer(state);
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyZ)) {
-        let mz = state.params.view_params.zoom;
-        state.params.view_params.zoom += 0.1 * mz;
-        update_view_params_buffer(state);
+    if state.controls.pressed(Action::CyclePalette) {
+        let mode = state.palette_mode().next();
+        state.set_palette_mode(mode);
+    } else if state.controls.pressed(Action::ToggleAgents) {
+        state.show_agents = !state.show_agents;
+    } else if state.controls.pressed(Action::ToggleSensors) {
+        state.show_sensors = !state.show_sensors;
+    }
+
+    if state.controls.held(Action::PaletteScaleDown) {
+        let scale = &mut state.params.palette_params.scale;
+        *scale = f32::max(0.01, *scale * 0.98);
+        update_palette_params_buffer(state);
+    } else if state.controls.held(Action::PaletteScaleUp) {
+        let scale = &mut state.params.palette_params.scale;
+        *scale *= 1.02;
+        update_palette_params_buffer(state);
+    }
+
+    // Panning moves a little every frame rather than repeating, by the same
+    // distance on screen whatever the zoom
+    let view = &state.params.view_params;
+    let pan = (0.01 * view.shift_modifier) / view.zoom;
+
+    let mut dx = 0.0;
+    if state.controls.held(Action::PanLeft) {
+        dx = -pan;
+    } else if state.controls.held(Action::PanRight) {
+        dx = pan;
+    }
+
+    let mut dy = 0.0;
+    if state.controls.held(Action::PanUp) {
+        dy = pan;
+    } else if state.controls.held(Action::PanDown) {
+        dy = -pan;
+    }
+
+    for (name, delta) in [("view.x_shift", dx), ("view.y_shift", dy)] {
+        if delta != 0.0 {
+            let info = find_param(name).expect("pan should move registered params");
+            let value = param_target(state, info) + delta;
+            set_param(state, info, value);
+        }
     }
 }
 
-fn print_controls(state: &State) {
-    let pressed = state.controls.get_keys();
+fn post_controls(state: &mut State) {
+    if state.controls.pressed(Action::CycleTonemap) {
+        let mode = state.tonemap_mode().next();
+        state.set_tonemap_mode(mode);
+    } else if state.controls.pressed(Action::ToggleAutoExposure) {
+        let auto_exposure = &mut state.params.post_params.auto_exposure;
+        *auto_exposure ^= 1;
+        println!("Auto exposure: {}", *auto_exposure != 0);
+        update_post_params_buffer(state);
+    } else if state.controls.pressed(Action::ToggleBloom) {
+        let bloom_enabled = &mut state.params.post_params.bloom_enabled;
+        *bloom_enabled ^= 1;
+        println!("Bloom: {}", *bloom_enabled != 0);
+        update_post_params_buffer(state);
+    }
+}
 
+fn print_controls(state: &mut State) {
     // PRINT CURRENT FRAME --------------------------------------------------------
-    if pressed.contains(&PhysicalKey::Code(KeyCode::Space)) {
-        capture_frame_and_save(&state.device, &state.queue, &state.surface);
+    if state.controls.pressed(Action::CaptureFrame) {
+        if let Some(surface) = &state.surface {
+            capture_frame_and_save(&state.device, &state.queue, surface);
+        }
+    } else if state.controls.pressed(Action::ExportField) {
+        if let Err(e) = state.export_fields() {
+            eprintln!("Error exporting field: {}", e);
+        }
+    } else if state.controls.pressed(Action::ExtractGraph) {
+        if let Err(e) = state.extract_graph() {
+            eprintln!("Error extracting graph: {}", e);
+        }
     }
 
     // PRINT CURRENT PARAMETER VALUES ----------------------------------------------
-    if pressed.contains(&PhysicalKey::Code(KeyCode::KeyI)) {
+    if state.controls.pressed(Action::PrintViewParams) {
         println!("\nview_params:\n{:#?}\n", state.params.view_params);
-        thread::sleep(time::Duration::from_millis(50));
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::KeyS)) {
+    } else if state.controls.pressed(Action::PrintSlimeParams) {
         println!("\nslime_params:\n{:#?}", state.params.slime_params);
-        thread::sleep(time::Duration::from_millis(50));
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::Period)) {
+    } else if state.controls.pressed(Action::PrintJonesParams) {
+        println!("\njones_params:\n{:#?}", state.params.jones_params);
+    } else if state.controls.pressed(Action::PrintPostParams) {
+        println!("\npost_params:\n{:#?}", state.params.post_params);
+    } else if state.controls.pressed(Action::PrintPheremoneParams) {
         println!("\npheremone_params:\n{:#?}", state.params.pheremone_params);
-        thread::sleep(time::Duration::from_millis(50));
-    } else if pressed.contains(&PhysicalKey::Code(KeyCode::Comma)) {
-        print_gpu_data::<Slime>(
-            &state.device,
-            &state.buffers.cpu_read_slime_pos_buf,
-            "Slime",
-        );
-        thread::sleep(time::Duration::from_millis(50));
+    } else if state.controls.pressed(Action::PrintAgents) {
+        state.print_agents();
     }
 }
 
@@ -304,8 +484,8 @@ fn capture_frame_and_save(device: &wgpu::Device, queue: &wgpu::Queue, surface: &
         .expect("Failed to acquire next swap chain texture");
 
     // Ensure bytes per row is multiple of 256 as per wgpu standard
-    let output_width = ((output.texture.size().width + 255) / 256) * 256;
-    let output_height = ((output.texture.size().height + 255) / 256) * 256;
+    let output_width = output.texture.size().width.div_ceil(256) * 256;
+    let output_height = output.texture.size().height.div_ceil(256) * 256;
 
     println!("output_width: {:?}", output_width);
     println!("output_height: {:?}", output_height);
@@ -341,7 +521,7 @@ fn capture_frame_and_save(device: &wgpu::Device, queue: &wgpu::Queue, surface: &
 
     {
         // Set up a render pass that targets your texture
-        let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        let _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &render_texture_view,
@@ -381,12 +561,7 @@ fn capture_frame_and_save(device: &wgpu::Device, queue: &wgpu::Queue, surface: &
     device.poll(wgpu::Maintain::Wait);
 
     // Map the buffer's memory to the CPU
-    let frame_data = buffer
-        .slice(..)
-        .get_mapped_range()
-        .iter()
-        .map(|b| *b)
-        .collect::<Vec<u8>>();
+    let frame_data = buffer.slice(..).get_mapped_range().to_vec();
 
     // Create an ImageBuffer from the frame data
     let img = image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(
@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+
+use crate::ParamInfo;
+
+// Edits to the same parameters closer together than this merge into one undo
+// step, so a held key or a slider drag undoes in one go
+const MERGE_WINDOW: Duration = Duration::from_millis(750);
+const MAX_HISTORY: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ParamChange {
+    pub(crate) param: &'static ParamInfo,
+    pub(crate) from: f32,
+    pub(crate) to: f32,
+}
+
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    changes: Vec<ParamChange>,
+    last_edit: Instant,
+}
+
+impl HistoryEntry {
+    fn same_params(&self, changes: &[ParamChange]) -> bool {
+        self.changes.len() == changes.len()
+            && self
+                .changes
+                .iter()
+                .zip(changes)
+                .all(|(a, b)| std::ptr::eq(a.param, b.param))
+    }
+}
+
+/// Undo and redo stacks of parameter edits
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParamHistory {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+}
+
+impl ParamHistory {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one edit of any number of parameters, dropping the redo stack
+    pub(crate) fn record(&mut self, changes: Vec<ParamChange>) {
+        let changes = changes
+            .into_iter()
+            .filter(|change| change.from != change.to)
+            .collect::<Vec<_>>();
+        if changes.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        self.redo.clear();
+
+        if let Some(last) = self.undo.last_mut() {
+            if now - last.last_edit < MERGE_WINDOW && last.same_params(&changes) {
+                for (merged, change) in last.changes.iter_mut().zip(&changes) {
+                    merged.to = change.to;
+                }
+                last.last_edit = now;
+                return;
+            }
+        }
+
+        self.undo.push(HistoryEntry {
+            changes,
+            last_edit: now,
+        });
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+    }
+
+    /// The values to restore for the newest edit, which moves to the redo stack
+    pub(crate) fn undo(&mut self) -> Option<Vec<(&'static ParamInfo, f32)>> {
+        let entry = self.undo.pop()?;
+        let values = entry
+            .changes
+            .iter()
+            .map(|change| (change.param, change.from))
+            .collect();
+        self.redo.push(entry);
+        Some(values)
+    }
+
+    /// The values to reapply for the newest undone edit
+    pub(crate) fn redo(&mut self) -> Option<Vec<(&'static ParamInfo, f32)>> {
+        let mut entry = self.redo.pop()?;
+        let values = entry
+            .changes
+            .iter()
+            .map(|change| (change.param, change.to))
+            .collect();
+        // Never merge a redone edit with the next one
+        entry.last_edit = Instant::now() - MERGE_WINDOW;
+        self.undo.push(entry);
+        Some(values)
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
@@ -1,2 +1,9 @@
+pub(crate) mod action_state;
 pub(crate) mod app_state;
 pub(crate) mod control_state;
+pub(crate) mod history_state;
+pub(crate) mod profiler_state;
+pub(crate) mod readback_state;
+pub(crate) mod smoothing_state;
+pub(crate) mod stats_state;
+pub(crate) mod ui_state;
@@ -0,0 +1,402 @@
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::app_state::State;
+use super::readback_state::ReadbackCallback;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ProfiledPass {
+    Movement,
+    Diffusion,
+    Stats,
+    Field,
+    Overlay,
+    Exposure,
+    // Bright pass through to the vertical blur
+    Bloom,
+    Tonemap,
+    // Only used by the CPU fallback, which can't see inside the render submit
+    Render,
+}
+
+impl ProfiledPass {
+    const ALL: [ProfiledPass; 9] = [
+        ProfiledPass::Movement,
+        ProfiledPass::Diffusion,
+        ProfiledPass::Stats,
+        ProfiledPass::Field,
+        ProfiledPass::Overlay,
+        ProfiledPass::Exposure,
+        ProfiledPass::Bloom,
+        ProfiledPass::Tonemap,
+        ProfiledPass::Render,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ProfiledPass::Movement => "movement",
+            ProfiledPass::Diffusion => "diffusion",
+            ProfiledPass::Stats => "stats",
+            ProfiledPass::Field => "field",
+            ProfiledPass::Overlay => "overlay",
+            ProfiledPass::Exposure => "exposure",
+            ProfiledPass::Bloom => "bloom",
+            ProfiledPass::Tonemap => "tonemap",
+            ProfiledPass::Render => "render",
+        }
+    }
+}
+
+type PassSamples = [Option<f64>; ProfiledPass::ALL.len()];
+
+// Begin and end query pairs a frame can write, a pair per pass it runs, so
+// each sub-step of a frame gets its own. Passes past the last pair go
+// untimed that frame.
+const QUERY_PAIRS: u32 = 1024;
+// Weight of the newest sample in the rolling average
+const AVERAGE_WEIGHT: f64 = 0.05;
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct GpuTimestamps {
+    query_set: wgpu::QuerySet,
+    resolve_buf: wgpu::Buffer,
+    // Nanoseconds per timestamp tick
+    period: f32,
+}
+
+/// Per-pass timings, from timestamp queries when the device has
+/// `TIMESTAMP_QUERY`, otherwise by waiting on each submit from the CPU. A
+/// pass that runs several times a frame is reported as its total.
+#[derive(Debug)]
+pub(crate) struct Profiler {
+    enabled: bool,
+    gpu: Option<GpuTimestamps>,
+    // The pass each query pair written this frame times, by pair
+    pairs: RefCell<Vec<ProfiledPass>>,
+    // Pair a pass spanning several render passes has begun and not ended
+    open: Cell<[Option<u32>; ProfiledPass::ALL.len()]>,
+    // Passes that ran out of query pairs this frame
+    dropped: Cell<u32>,
+    // CPU fallback, start of the submit being timed
+    cpu_mark: Cell<Instant>,
+    cpu_samples: Cell<PassSamples>,
+    averages: [Option<f64>; ProfiledPass::ALL.len()],
+    frame: u64,
+    last_report: Instant,
+    csv: Option<BufWriter<File>>,
+    agent_count: usize,
+}
+
+impl Profiler {
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue, enabled: bool) -> Self {
+        let gpu = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| {
+                let size = QUERY_PAIRS as wgpu::BufferAddress * 2 * wgpu::QUERY_SIZE as u64;
+
+                GpuTimestamps {
+                    query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("Profiler Query Set"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: QUERY_PAIRS * 2,
+                    }),
+                    resolve_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Profiler Resolve Buffer"),
+                        size,
+                        usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    }),
+                    period: queue.get_timestamp_period(),
+                }
+            });
+
+        if gpu.is_none() {
+            println!("Profiler: TIMESTAMP_QUERY is unavailable, falling back to CPU timing");
+        }
+
+        Self {
+            enabled,
+            gpu,
+            pairs: RefCell::new(Vec::new()),
+            open: Cell::new([None; ProfiledPass::ALL.len()]),
+            dropped: Cell::new(0),
+            cpu_mark: Cell::new(Instant::now()),
+            cpu_samples: Cell::new([None; ProfiledPass::ALL.len()]),
+            averages: [None; ProfiledPass::ALL.len()],
+            frame: 0,
+            last_report: Instant::now(),
+            csv: None,
+            agent_count: 0,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.averages = [None; ProfiledPass::ALL.len()];
+        self.clear_frame();
+        println!("Profiler: {}", if self.enabled { "on" } else { "off" });
+    }
+
+    /// Writes one row per frame, a column per pass in milliseconds. Passes
+    /// that didn't run are left empty.
+    pub(crate) fn export_csv(&mut self, path: &Path, agent_count: usize) -> std::io::Result<()> {
+        let mut csv = BufWriter::new(File::create(path)?);
+
+        write!(csv, "frame,agents")?;
+        for pass in ProfiledPass::ALL {
+            write!(csv, ",{}_ms", pass.name())?;
+        }
+        writeln!(csv)?;
+
+        self.csv = Some(csv);
+        self.agent_count = agent_count;
+        Ok(())
+    }
+
+    pub(crate) fn compute_timestamps(
+        &self,
+        pass: ProfiledPass,
+    ) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let gpu = self.gpu.as_ref().filter(|_| self.enabled)?;
+        let pair = self.begin_pair(pass)?;
+
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set: &gpu.query_set,
+            beginning_of_pass_write_index: Some(pair * 2),
+            end_of_pass_write_index: Some(pair * 2 + 1),
+        })
+    }
+
+    /// `begin`/`end` let a pass that spans several render passes time from
+    /// the start of the first to the end of the last.
+    pub(crate) fn render_timestamps(
+        &self,
+        pass: ProfiledPass,
+        begin: bool,
+        end: bool,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let gpu = self.gpu.as_ref().filter(|_| self.enabled)?;
+
+        let mut open = self.open.get();
+        let pair = if begin {
+            self.begin_pair(pass)?
+        } else {
+            open[pass as usize]?
+        };
+        open[pass as usize] = (!end).then_some(pair);
+        self.open.set(open);
+
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &gpu.query_set,
+            beginning_of_pass_write_index: begin.then_some(pair * 2),
+            end_of_pass_write_index: end.then_some(pair * 2 + 1),
+        })
+    }
+
+    // Hands out the next query pair to `pass`, None once they've run out
+    fn begin_pair(&self, pass: ProfiledPass) -> Option<u32> {
+        let mut pairs = self.pairs.borrow_mut();
+        if pairs.len() as u32 == QUERY_PAIRS {
+            self.dropped.set(self.dropped.get() | 1 << pass as u32);
+            return None;
+        }
+        pairs.push(pass);
+        Some(pairs.len() as u32 - 1)
+    }
+
+    fn clear_frame(&self) {
+        self.pairs.borrow_mut().clear();
+        self.open.set([None; ProfiledPass::ALL.len()]);
+        self.dropped.set(0);
+        self.cpu_samples.set([None; ProfiledPass::ALL.len()]);
+    }
+
+    pub(crate) fn begin_frame(&self) {
+        self.cpu_mark.set(Instant::now());
+    }
+
+    /// Whether passes are timed from the CPU, which needs a submit after each
+    pub(crate) fn cpu_timing(&self) -> bool {
+        self.enabled && self.gpu.is_none()
+    }
+
+    /// CPU fallback, call straight after the submit that contains `pass`
+    pub(crate) fn cpu_pass(&self, device: &wgpu::Device, pass: ProfiledPass) {
+        if !self.cpu_timing() {
+            return;
+        }
+
+        device.poll(wgpu::Maintain::Wait);
+        let now = Instant::now();
+
+        let mut samples = self.cpu_samples.get();
+        let ms = (now - self.cpu_mark.get()).as_secs_f64() * 1000.0;
+        samples[pass as usize] = Some(samples[pass as usize].unwrap_or(0.0) + ms);
+        self.cpu_samples.set(samples);
+        self.cpu_mark.set(now);
+    }
+
+    /// Resolves this frame's timestamps at the end of its last encoder.
+    /// Returns the buffer to read back, how much of it, and the callback that
+    /// records the timings once the readback pool has mapped it, a frame or
+    /// more later.
+    pub(crate) fn resolve(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Option<(&wgpu::Buffer, wgpu::BufferAddress, ReadbackCallback)> {
+        let gpu = self.gpu.as_ref().filter(|_| self.enabled)?;
+        let passes = self.pairs.take();
+        if passes.is_empty() {
+            return None;
+        }
+
+        let queries = passes.len() as u32 * 2;
+        encoder.resolve_query_set(&gpu.query_set, 0..queries, &gpu.resolve_buf, 0);
+
+        let (frame, dropped, period) = (self.frame, self.dropped.get(), gpu.period);
+        let callback: ReadbackCallback = Box::new(move |state: &mut State, data: &[u8]| {
+            let timestamps = bytemuck::pod_collect_to_vec::<u8, u64>(data);
+            let samples = pass_totals(&passes, &timestamps, dropped, period);
+            state.profiler.record(frame, samples);
+        });
+        Some((
+            &gpu.resolve_buf,
+            queries as wgpu::BufferAddress * wgpu::QUERY_SIZE as u64,
+            callback,
+        ))
+    }
+
+    /// Ends the frame after its last submit. CPU timings are recorded at
+    /// once, GPU timings when their readback lands.
+    pub(crate) fn end_frame(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.gpu.is_none() {
+            self.record(self.frame, self.cpu_samples.get());
+        }
+        self.clear_frame();
+        self.frame += 1;
+    }
+
+    fn record(&mut self, frame: u64, samples: PassSamples) {
+        if !self.enabled {
+            return;
+        }
+
+        for (average, sample) in self.averages.iter_mut().zip(samples) {
+            if let Some(sample) = sample {
+                *average = Some(match *average {
+                    Some(average) => average + (sample - average) * AVERAGE_WEIGHT,
+                    None => sample,
+                });
+            }
+        }
+
+        if let Some(csv) = &mut self.csv {
+            let row = samples
+                .iter()
+                .map(|sample| sample.map_or(String::new(), |ms| format!("{:.4}", ms)))
+                .collect::<Vec<String>>()
+                .join(",");
+
+            if let Err(e) = writeln!(csv, "{},{},{}", frame, self.agent_count, row) {
+                eprintln!("Error writing profiler CSV: {}", e);
+                self.csv = None;
+            }
+        }
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.last_report = Instant::now();
+            println!("{}", self.summary());
+        }
+    }
+
+    /// Rolling averages of the passes that have run, in milliseconds
+    pub(crate) fn averages(&self) -> Vec<(&'static str, f64)> {
+        ProfiledPass::ALL
+            .iter()
+            .zip(self.averages)
+            .filter_map(|(pass, average)| average.map(|ms| (pass.name(), ms)))
+            .collect()
+    }
+
+    fn summary(&self) -> String {
+        let source = if self.gpu.is_some() { "GPU" } else { "CPU" };
+        let passes = self
+            .averages()
+            .iter()
+            .map(|(name, ms)| format!("{} {:.3}", name, ms))
+            .collect::<Vec<String>>()
+            .join(" | ");
+
+        format!("{} ms: {}", source, passes)
+    }
+}
+
+/// Milliseconds per pass, summed over the query pairs that timed it. Passes
+/// without a pair, or that ran out of them, are None.
+fn pass_totals(
+    passes: &[ProfiledPass],
+    timestamps: &[u64],
+    dropped: u32,
+    period: f32,
+) -> PassSamples {
+    let mut samples: PassSamples = [None; ProfiledPass::ALL.len()];
+    for (pair, &pass) in passes.iter().enumerate() {
+        let ticks = timestamps[pair * 2 + 1].wrapping_sub(timestamps[pair * 2]);
+        let ms = ticks as f64 * period as f64 / 1_000_000.0;
+        samples[pass as usize] = Some(samples[pass as usize].unwrap_or(0.0) + ms);
+    }
+
+    for pass in ProfiledPass::ALL {
+        if dropped & 1 << pass as u32 != 0 {
+            samples[pass as usize] = None;
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_every_sub_step() {
+        // Two sub-steps of movement and diffusion, a 1 tick per ns clock
+        let passes = [
+            ProfiledPass::Movement,
+            ProfiledPass::Diffusion,
+            ProfiledPass::Movement,
+            ProfiledPass::Diffusion,
+        ];
+        let timestamps = [
+            0, 1_000_000, 1_000_000, 3_000_000, 3_000_000, 4_500_000, 5_000_000, 7_000_000,
+        ];
+        let samples = pass_totals(&passes, &timestamps, 0, 1.0);
+
+        assert_eq!(samples[ProfiledPass::Movement as usize], Some(2.5));
+        assert_eq!(samples[ProfiledPass::Diffusion as usize], Some(4.0));
+        assert_eq!(samples[ProfiledPass::Stats as usize], None);
+    }
+
+    #[test]
+    fn drops_passes_that_ran_out_of_pairs() {
+        let passes = [ProfiledPass::Movement, ProfiledPass::Diffusion];
+        let dropped = 1 << ProfiledPass::Diffusion as u32;
+        let samples = pass_totals(&passes, &[0, 10, 10, 20], dropped, 1_000_000.0);
+
+        assert_eq!(samples[ProfiledPass::Movement as usize], Some(10.0));
+        assert_eq!(samples[ProfiledPass::Diffusion as usize], None);
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use super::app_state::State;
+use crate::Buffers;
+
+// Staging buffers kept around, a request is turned away if they're all busy
+const MAX_SLOTS: usize = 8;
+
+const MAP_PENDING: u8 = 0;
+const MAP_OK: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// Gets the bytes a readback asked for, once they've landed
+pub(crate) type ReadbackCallback = Box<dyn FnOnce(&mut State, &[u8])>;
+
+/// A readback asked for outside the frame encoder, recorded into the next one
+pub(crate) struct ReadbackRequest {
+    pub(crate) source: fn(&Buffers) -> &wgpu::Buffer,
+    pub(crate) label: &'static str,
+    pub(crate) callback: ReadbackCallback,
+}
+
+impl std::fmt::Debug for ReadbackRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadbackRequest")
+            .field("label", &self.label)
+            .finish_non_exhaustive()
+    }
+}
+
+struct PendingReadback {
+    label: &'static str,
+    size: wgpu::BufferAddress,
+    // Order of the requests, callbacks run in it
+    sequence: u64,
+    // None until the copy has been submitted and `map_requested` has run
+    status: Option<Arc<AtomicU8>>,
+    callback: ReadbackCallback,
+}
+
+struct ReadbackSlot {
+    buf: wgpu::Buffer,
+    pending: Option<PendingReadback>,
+}
+
+/// Reads GPU buffers back on request without stalling. Each request records
+/// a copy into a free staging buffer in the frame's encoder, the buffer is
+/// mapped once the frame is submitted and `poll` hands the bytes to the
+/// request's callback a frame or two later. Nothing is copied while there
+/// are no requests.
+pub(crate) struct ReadbackPool {
+    slots: Vec<ReadbackSlot>,
+    requests: u64,
+}
+
+impl std::fmt::Debug for ReadbackPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pending = self
+            .slots
+            .iter()
+            .filter_map(|slot| slot.pending.as_ref().map(|pending| pending.label))
+            .collect::<Vec<&str>>();
+        f.debug_struct("ReadbackPool")
+            .field("slots", &self.slots.len())
+            .field("pending", &pending)
+            .finish()
+    }
+}
+
+impl ReadbackPool {
+    pub(crate) fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            requests: 0,
+        }
+    }
+
+    /// Records a copy of the first `size` bytes of `source` into `encoder`.
+    /// False if every staging buffer is still busy, nothing is copied then.
+    pub(crate) fn request(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        size: wgpu::BufferAddress,
+        label: &'static str,
+        callback: ReadbackCallback,
+    ) -> bool {
+        let size = size.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+
+        // Reuse a free buffer that's big enough, then grow a free one, then
+        // add one
+        let index = match self
+            .slots
+            .iter()
+            .position(|slot| slot.pending.is_none() && slot.buf.size() >= size)
+        {
+            Some(index) => index,
+            None => match self.slots.iter().position(|slot| slot.pending.is_none()) {
+                Some(index) => {
+                    self.slots[index].buf = create_staging_buffer(device, size);
+                    index
+                }
+                None if self.slots.len() < MAX_SLOTS => {
+                    self.slots.push(ReadbackSlot {
+                        buf: create_staging_buffer(device, size),
+                        pending: None,
+                    });
+                    self.slots.len() - 1
+                }
+                None => return false,
+            },
+        };
+        let slot = &mut self.slots[index];
+        encoder.copy_buffer_to_buffer(source, 0, &slot.buf, 0, size);
+
+        slot.pending = Some(PendingReadback {
+            label,
+            size,
+            sequence: self.requests,
+            status: None,
+            callback,
+        });
+        self.requests += 1;
+        true
+    }
+
+    /// Starts mapping the copies `request` recorded, call after their submit
+    pub(crate) fn map_requested(&mut self) {
+        for slot in &mut self.slots {
+            let Some(pending) = &mut slot.pending else {
+                continue;
+            };
+            if pending.status.is_some() {
+                continue;
+            }
+
+            let status = Arc::new(AtomicU8::new(MAP_PENDING));
+            let callback_status = Arc::clone(&status);
+            slot.buf
+                .slice(..pending.size)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let value = if result.is_ok() { MAP_OK } else { MAP_FAILED };
+                    callback_status.store(value, Ordering::Release);
+                });
+            pending.status = Some(status);
+        }
+    }
+
+    /// Takes the readbacks that have landed with their bytes, never waits on
+    /// the GPU. Run the callbacks once the pool is no longer borrowed.
+    pub(crate) fn poll(&mut self, device: &wgpu::Device) -> Vec<(ReadbackCallback, Vec<u8>)> {
+        let mapping = |slot: &ReadbackSlot| {
+            slot.pending
+                .as_ref()
+                .is_some_and(|pending| pending.status.is_some())
+        };
+        if !self.slots.iter().any(mapping) {
+            return Vec::new();
+        }
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut done = Vec::new();
+        for slot in &mut self.slots {
+            let Some(PendingReadback {
+                label,
+                status: Some(status),
+                ..
+            }) = &slot.pending
+            else {
+                continue;
+            };
+
+            match status.load(Ordering::Acquire) {
+                MAP_PENDING => continue,
+                MAP_OK => {
+                    let pending = slot.pending.take().expect("slot should be pending");
+                    let data = slot.buf.slice(..pending.size).get_mapped_range().to_vec();
+                    slot.buf.unmap();
+                    done.push((pending.sequence, pending.callback, data));
+                }
+                _ => {
+                    eprintln!("Error mapping {} readback buffer", label);
+                    slot.pending = None;
+                }
+            }
+        }
+
+        done.sort_by_key(|(sequence, _, _)| *sequence);
+        done.into_iter()
+            .map(|(_, callback, data)| (callback, data))
+            .collect()
+    }
+}
+
+/// Reads back whatever `copy` records into the staging buffer it's handed,
+/// blocking until the bytes land. For file writes that need them at once,
+/// everything else goes through [`ReadbackPool`].
+pub(crate) fn read_back_blocking(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: wgpu::BufferAddress,
+    label: &str,
+    copy: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::Buffer),
+) -> Vec<u8> {
+    let buffer = create_staging_buffer(device, size);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("read_back_blocking encoder"),
+    });
+    copy(&mut encoder, &buffer);
+    queue.submit(Some(encoder.finish()));
+
+    let buffer_slice = buffer.slice(..);
+    let (tx, rx) = futures::channel::oneshot::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        tx.send(result).unwrap();
+    });
+    device.poll(wgpu::Maintain::Wait);
+    if let Err(e) = futures::executor::block_on(rx).expect("map callback should run") {
+        panic!("{} readback buffer should map: {}", label, e);
+    }
+
+    let data = buffer_slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    data
+}
+
+fn create_staging_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Staging Buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    })
+}
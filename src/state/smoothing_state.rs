@@ -0,0 +1,110 @@
+use std::time::Instant;
+
+use crate::{ParamGroup, ParamInfo, ParamScale, Params};
+
+// Fraction of a step, or of the value for log parameters, close enough to the
+// target to snap to it
+const SNAP_FRACTION: f32 = 1e-2;
+
+#[derive(Debug, Clone, Copy)]
+struct SmoothedParam {
+    param: &'static ParamInfo,
+    target: f32,
+}
+
+/// Eases parameter edits in over time instead of applying them at once, so a
+/// big change doesn't tear up an established network
+#[derive(Debug, Clone)]
+pub(crate) struct ParamSmoother {
+    // Seconds to get 95% of the way to a new value, 0 applies edits at once
+    pub(crate) time: f32,
+    active: Vec<SmoothedParam>,
+    last_advance: Instant,
+}
+
+impl ParamSmoother {
+    pub(crate) fn new(time: f32) -> Self {
+        Self {
+            time: time.max(0.0),
+            active: Vec::new(),
+            last_advance: Instant::now(),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.time > 0.0
+    }
+
+    /// Where the parameter is heading, `None` if it isn't moving
+    pub(crate) fn target(&self, param: &ParamInfo) -> Option<f32> {
+        self.active
+            .iter()
+            .find(|smoothed| std::ptr::eq(smoothed.param, param))
+            .map(|smoothed| smoothed.target)
+    }
+
+    pub(crate) fn set_target(&mut self, param: &'static ParamInfo, target: f32) {
+        match self
+            .active
+            .iter_mut()
+            .find(|smoothed| std::ptr::eq(smoothed.param, param))
+        {
+            Some(smoothed) => smoothed.target = target,
+            None => self.active.push(SmoothedParam { param, target }),
+        }
+    }
+
+    /// Stops easing `param`, leaving it where it is
+    pub(crate) fn cancel(&mut self, param: &ParamInfo) {
+        self.active
+            .retain(|smoothed| !std::ptr::eq(smoothed.param, param));
+    }
+
+    /// `params` with every easing parameter at its target
+    pub(crate) fn targets(&self, params: &Params) -> Params {
+        let mut targets = params.clone();
+        for smoothed in &self.active {
+            *(smoothed.param.get_mut)(&mut targets) = smoothed.target;
+        }
+        targets
+    }
+
+    /// Moves the easing parameters towards their targets by the time since the
+    /// last call, returning the groups that changed
+    pub(crate) fn advance(&mut self, params: &mut Params) -> Vec<ParamGroup> {
+        let now = Instant::now();
+        let dt = (now - self.last_advance).as_secs_f32();
+        self.last_advance = now;
+
+        // Exponential approach, ln(20) time constants reach 95%
+        let blend = if self.time > 0.0 {
+            1.0 - (-dt * 20f32.ln() / self.time).exp()
+        } else {
+            1.0
+        };
+
+        let mut groups = Vec::new();
+        self.active.retain(|smoothed| {
+            let info = smoothed.param;
+            let value = (info.get)(params);
+
+            let next = match info.scale {
+                ParamScale::Linear => value + (smoothed.target - value) * blend,
+                // Log parameters ease by ratio, so zooming looks even
+                ParamScale::Log => value * (smoothed.target / value).powf(blend),
+            };
+            let snap = match info.scale {
+                ParamScale::Linear => info.step * SNAP_FRACTION,
+                ParamScale::Log => smoothed.target.abs() * SNAP_FRACTION,
+            };
+            let done = !next.is_finite() || (smoothed.target - next).abs() <= snap;
+
+            *(info.get_mut)(params) = if done { smoothed.target } else { next };
+            if !groups.contains(&info.group) {
+                groups.push(info.group);
+            }
+            !done
+        });
+        groups
+    }
+}
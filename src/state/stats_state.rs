@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::SimStats;
+
+// Samples kept for the plots
+const HISTORY_LEN: usize = 240;
+
+/// The GPU statistics read back so far. Samples come through the readback
+/// pool, a frame or two after their step.
+#[derive(Debug)]
+pub(crate) struct StatsState {
+    // Steps between samples, 0 turns the statistics off
+    pub(crate) interval: u32,
+    history: VecDeque<(u64, SimStats)>,
+    csv: Option<BufWriter<File>>,
+}
+
+impl StatsState {
+    pub(crate) fn new(interval: u32) -> Self {
+        Self {
+            interval,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            csv: None,
+        }
+    }
+
+    /// Writes one row per sample
+    pub(crate) fn export_csv(&mut self, path: &Path) -> std::io::Result<()> {
+        let mut csv = BufWriter::new(File::create(path)?);
+        writeln!(
+            csv,
+            "step,total_mass,max_mass,coverage,mean_speed,speed_variance,heading_entropy,forward_fraction"
+        )?;
+        self.csv = Some(csv);
+        Ok(())
+    }
+
+    pub(crate) fn due(&self, step: u64) -> bool {
+        self.interval > 0 && step.is_multiple_of(self.interval as u64)
+    }
+
+    /// Adds a sample read back by the readback pool
+    pub(crate) fn push(&mut self, step: u64, stats: SimStats) {
+        if let Some(csv) = &mut self.csv {
+            let row = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                step,
+                stats.total_mass,
+                stats.max_mass,
+                stats.coverage,
+                stats.mean_speed,
+                stats.speed_variance,
+                stats.heading_entropy,
+                stats.forward_fraction,
+            );
+            if let Err(e) = row {
+                eprintln!("Error writing stats CSV: {}", e);
+                self.csv = None;
+            }
+        }
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back((step, stats));
+    }
+
+    /// Oldest first
+    pub(crate) fn history(&self) -> &VecDeque<(u64, SimStats)> {
+        &self.history
+    }
+}
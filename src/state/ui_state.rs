@@ -0,0 +1,183 @@
+use std::time::Instant;
+
+use crate::DEFAULT_PRESET_PATH;
+
+// Weight of the newest frame in the rolling frame time
+const FRAME_TIME_WEIGHT: f32 = 0.05;
+
+/// Window overlay drawn with egui on top of the tonemapped frame
+pub(crate) struct UiState {
+    pub(crate) ctx: egui::Context,
+    // None headless, the methods taking a window need it
+    input: Option<egui_winit::State>,
+    renderer: egui_wgpu::Renderer,
+    pub(crate) show_hud: bool,
+    pub(crate) show_gui: bool,
+    pub(crate) show_stats: bool,
+    // Contents of the preset path field in the GUI
+    pub(crate) preset_path: String,
+    // Contents of the palette LUT path field, the loaded LUT's path
+    pub(crate) lut_path: String,
+    // Built by `State::run_ui` before the steps, painted after them
+    pub(crate) output: Option<egui::FullOutput>,
+    last_frame: Instant,
+    // Rolling average, in seconds
+    frame_time: f32,
+}
+
+impl std::fmt::Debug for UiState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UiState")
+            .field("show_hud", &self.show_hud)
+            .field("show_gui", &self.show_gui)
+            .field("show_stats", &self.show_stats)
+            .field("frame_time", &self.frame_time)
+            .finish_non_exhaustive()
+    }
+}
+
+impl UiState {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        window: Option<&winit::window::Window>,
+        surface_format: wgpu::TextureFormat,
+        show_gui: bool,
+        show_stats: bool,
+    ) -> Self {
+        let ctx = egui::Context::default();
+        let input = window.map(|window| {
+            egui_winit::State::new(
+                ctx.clone(),
+                egui::ViewportId::ROOT,
+                window,
+                Some(window.scale_factor() as f32),
+                Some(device.limits().max_texture_dimension_2d as usize),
+            )
+        });
+        let renderer = egui_wgpu::Renderer::new(device, surface_format, None, 1);
+        let windowed = input.is_some();
+
+        Self {
+            ctx,
+            input,
+            renderer,
+            show_hud: windowed,
+            show_gui: show_gui && windowed,
+            show_stats: show_stats && windowed,
+            preset_path: DEFAULT_PRESET_PATH.to_string(),
+            lut_path: String::new(),
+            output: None,
+            last_frame: Instant::now(),
+            frame_time: 0.0,
+        }
+    }
+
+    pub(crate) fn toggle_hud(&mut self) {
+        self.show_hud = !self.show_hud;
+    }
+
+    pub(crate) fn toggle_gui(&mut self) {
+        self.show_gui = !self.show_gui;
+    }
+
+    pub(crate) fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+    }
+
+    fn input_mut(&mut self) -> &mut egui_winit::State {
+        self.input
+            .as_mut()
+            .expect("UI was created without a window")
+    }
+
+    pub(crate) fn visible(&self) -> bool {
+        self.show_hud || self.show_gui || self.show_stats
+    }
+
+    pub(crate) fn on_window_event(
+        &mut self,
+        window: &winit::window::Window,
+        event: &winit::event::WindowEvent,
+    ) -> egui_winit::EventResponse {
+        self.input_mut().on_window_event(window, event)
+    }
+
+    /// Call once per presented frame
+    pub(crate) fn tick(&mut self) {
+        let now = Instant::now();
+        let dt = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        self.frame_time = if self.frame_time == 0.0 {
+            dt
+        } else {
+            self.frame_time + (dt - self.frame_time) * FRAME_TIME_WEIGHT
+        };
+    }
+
+    pub(crate) fn frame_time_ms(&self) -> f32 {
+        self.frame_time * 1000.0
+    }
+
+    pub(crate) fn fps(&self) -> f32 {
+        if self.frame_time > 0.0 {
+            1.0 / self.frame_time
+        } else {
+            0.0
+        }
+    }
+
+    pub(crate) fn take_input(&mut self, window: &winit::window::Window) -> egui::RawInput {
+        self.input_mut().take_egui_input(window)
+    }
+
+    /// Uploads the frame's shapes and draws them over `target`
+    pub(crate) fn paint(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        window: &winit::window::Window,
+        output: egui::FullOutput,
+    ) {
+        self.input_mut()
+            .handle_platform_output(window, output.platform_output);
+
+        let paint_jobs = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        let size = window.inner_size();
+        let screen_descriptor = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, image_delta) in &output.textures_delta.set {
+            self.renderer
+                .update_texture(device, queue, *id, image_delta);
+        }
+        self.renderer
+            .update_buffers(device, queue, encoder, &paint_jobs, &screen_descriptor);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("UI Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            self.renderer
+                .render(&mut render_pass, &paint_jobs, &screen_descriptor);
+        }
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub(crate) mod structs;
 pub(crate) use structs::*;
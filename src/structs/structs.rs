@@ -1,8 +1,30 @@
-pub(crate) const NUM_AGENTS: usize = 256;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub(crate) const DEFAULT_NUM_AGENTS: usize = 256;
 pub(crate) const SCREEN_WIDTH: u32 = 1376;
 pub(crate) const SCREEN_HEIGHT: u32 = 768;
-pub(crate) const DISPATCH_SIZE_X: u32 = ((SCREEN_WIDTH as u32).saturating_add(32)) / 32;
-pub(crate) const DISPATCH_SIZE_Y: u32 = ((SCREEN_HEIGHT as u32).saturating_add(32)) / 32;
+pub(crate) const DISPATCH_SIZE_X: u32 = SCREEN_WIDTH.div_ceil(32);
+pub(crate) const DISPATCH_SIZE_Y: u32 = SCREEN_HEIGHT.div_ceil(32);
+// Agents per workgroup of the agent kernels, if the adapter allows that many
+pub(crate) const AGENT_WORKGROUP_SIZE: u32 = 64;
+pub(crate) const DEFAULT_SEED: u32 = 0;
+
+pub(crate) const PALETTE_LUT_SIZE: u32 = 256;
+pub(crate) const DEFAULT_PRESET_PATH: &str = "slime_preset.toml";
+pub(crate) const DEFAULT_BINDINGS_PATH: &str = "bindings.toml";
+pub(crate) const DEFAULT_EXPORT_DIR: &str = "exports";
+pub(crate) const DEFAULT_CHECKPOINT_PATH: &str = "slime_checkpoint.bin";
+// Bump when the checkpoint layout or the Slime struct changes, and keep
+// reading the old versions, see checkpoint/
+pub(crate) const CHECKPOINT_VERSION: u32 = 1;
+
+// The field and overlays render into this, the tonemap pass writes the surface
+pub(crate) const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Keep in sync with HISTOGRAM_BINS in shaders/post/common.wgsl
+pub(crate) const EXPOSURE_HISTOGRAM_BINS: usize = 64;
+// Side of the field tiles reduced by stats.wgsl
+pub(crate) const STATS_TILE_SIZE: u32 = 16;
 
 pub(crate) const TEXTURE_BUF_SIZE: usize =
     SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 4 * (std::mem::size_of::<f32>());
@@ -36,15 +58,6 @@ pub(crate) const VERTICES: &[Vertex; 6] = &[
     },
 ];
 
-#[repr(C)]
-#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-pub(crate) struct DebugBuffer {
-    pub(crate) d1: [f32; 4],
-    pub(crate) d2: [f32; 4],
-    pub(crate) d3: [f32; 4],
-    pub(crate) d4: [f32; 4],
-}
-
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub(crate) struct TimeUniform {
@@ -65,13 +78,24 @@ pub(crate) struct Buffers {
     pub(crate) const_uniform_buf: wgpu::Buffer,
     pub(crate) view_params_buf: wgpu::Buffer,
     pub(crate) slime_pos_buf: wgpu::Buffer,
-    pub(crate) cpu_read_slime_pos_buf: wgpu::Buffer,
-    pub(crate) generic_debug_buf: wgpu::Buffer,
-    pub(crate) cpu_read_generic_debug_buf: wgpu::Buffer,
-    pub(crate) generic_debug_array_buf: wgpu::Buffer,
-    pub(crate) cpu_read_generic_debug_array_buf: wgpu::Buffer,
+    // One vec4 per slot of DEBUG_CHANNELS
+    pub(crate) debug_channels_buf: wgpu::Buffer,
     pub(crate) slime_params_buf: wgpu::Buffer,
     pub(crate) pheremone_params_buf: wgpu::Buffer,
+    pub(crate) sim_params_buf: wgpu::Buffer,
+    pub(crate) jones_params_buf: wgpu::Buffer,
+    pub(crate) palette_params_buf: wgpu::Buffer,
+    pub(crate) overlay_params_buf: wgpu::Buffer,
+    pub(crate) post_params_buf: wgpu::Buffer,
+    pub(crate) exposure_state_buf: wgpu::Buffer,
+    pub(crate) stats_params_buf: wgpu::Buffer,
+    // One FieldPartial per field tile
+    pub(crate) stats_partials_buf: wgpu::Buffer,
+    pub(crate) stats_buf: wgpu::Buffer,
+    // Fixed point deposits, only used when the field ping-pongs
+    pub(crate) phm_deposit_buf: Option<wgpu::Buffer>,
+    // One SourceTexel per field texel
+    pub(crate) sources_buf: wgpu::Buffer,
 }
 
 #[derive(Debug)]
@@ -82,10 +106,55 @@ pub(crate) struct BindGroups {
     pub(crate) param_bgl: wgpu::BindGroupLayout,
     pub(crate) compute_bg: wgpu::BindGroup,
     pub(crate) compute_bgl: wgpu::BindGroupLayout,
-    pub(crate) phm_bg: wgpu::BindGroup,
+    // Indexed by State::phm_front, identical unless the field ping-pongs
+    pub(crate) phm_bgs: [wgpu::BindGroup; 2],
     pub(crate) phm_bgl: wgpu::BindGroupLayout,
-    pub(crate) sampled_phm_bg: wgpu::BindGroup,
+    pub(crate) sampled_phm_bgs: [wgpu::BindGroup; 2],
     pub(crate) sampled_phm_bgl: wgpu::BindGroupLayout,
+    pub(crate) agent_draw_bg: wgpu::BindGroup,
+    pub(crate) agent_draw_bgl: wgpu::BindGroupLayout,
+    pub(crate) post_bgl: wgpu::BindGroupLayout,
+    pub(crate) post_composite_bgl: wgpu::BindGroupLayout,
+    pub(crate) exposure_bgl: wgpu::BindGroupLayout,
+    pub(crate) stats_bg: wgpu::BindGroup,
+    pub(crate) stats_bgl: wgpu::BindGroupLayout,
+    // Recreated with the post textures on resize
+    pub(crate) post: PostBindGroups,
+}
+
+#[derive(Debug)]
+pub(crate) struct PostBindGroups {
+    pub(crate) hdr_bg: wgpu::BindGroup,
+    pub(crate) bloom_bgs: [wgpu::BindGroup; 2],
+    pub(crate) composite_bg: wgpu::BindGroup,
+    pub(crate) exposure_bg: wgpu::BindGroup,
+}
+
+// Decides which shader variants get built, see init_capabilities
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Capabilities {
+    // Update the pheremone field in place, otherwise ping-pong two textures
+    pub(crate) read_write_storage: bool,
+    // Sample the Rgba32Float field with a filtering sampler, otherwise
+    // filter by hand in the fragment shader
+    pub(crate) float32_filterable: bool,
+    // Agents per workgroup of the 1D agent kernels, within the adapter's
+    // limits
+    pub(crate) agent_workgroup_size: u32,
+    // Agents the buffers and shaders are built for, --agents or a resumed
+    // checkpoint's
+    pub(crate) agent_count: u32,
+}
+
+impl Capabilities {
+    /// Workgroups that cover every agent once
+    pub(crate) fn agent_workgroups(&self) -> u32 {
+        self.agent_count.div_ceil(self.agent_workgroup_size)
+    }
+
+    pub(crate) fn agents(&self) -> usize {
+        self.agent_count as usize
+    }
 }
 
 #[derive(Debug)]
@@ -95,6 +164,11 @@ pub(crate) struct ShaderModules {
     pub(crate) init_slime_shader: wgpu::ShaderModule,
     pub(crate) update_slime_shader: wgpu::ShaderModule,
     pub(crate) update_phm_shader: wgpu::ShaderModule,
+    pub(crate) agent_overlay_shader: wgpu::ShaderModule,
+    pub(crate) exposure_shader: wgpu::ShaderModule,
+    pub(crate) bloom_shader: wgpu::ShaderModule,
+    pub(crate) tonemap_shader: wgpu::ShaderModule,
+    pub(crate) stats_shader: wgpu::ShaderModule,
 }
 
 #[derive(Debug)]
@@ -103,6 +177,16 @@ pub(crate) struct Pipelines {
     pub(crate) init_slime: wgpu::ComputePipeline,
     pub(crate) update_slime: wgpu::ComputePipeline,
     pub(crate) update_phm: wgpu::ComputePipeline,
+    pub(crate) draw_agents: wgpu::RenderPipeline,
+    pub(crate) draw_sensors: wgpu::RenderPipeline,
+    pub(crate) exposure_histogram: wgpu::ComputePipeline,
+    pub(crate) exposure_adapt: wgpu::ComputePipeline,
+    pub(crate) bloom_bright: wgpu::RenderPipeline,
+    pub(crate) bloom_blur_h: wgpu::RenderPipeline,
+    pub(crate) bloom_blur_v: wgpu::RenderPipeline,
+    pub(crate) tonemap: wgpu::RenderPipeline,
+    pub(crate) stats_field: wgpu::ComputePipeline,
+    pub(crate) stats_finish: wgpu::ComputePipeline,
 }
 
 #[derive(Debug)]
@@ -110,7 +194,36 @@ pub(crate) struct Textures {
     pub(crate) phm: wgpu::Texture,
     pub(crate) phm_sampler: wgpu::Sampler,
     pub(crate) phm_view: wgpu::TextureView,
+    // Second half of the ping-pong pair, see Capabilities::read_write_storage
+    pub(crate) phm_back: Option<wgpu::Texture>,
+    pub(crate) phm_back_view: Option<wgpu::TextureView>,
     pub(crate) phm_extent: wgpu::Extent3d,
+    pub(crate) palette_lut: wgpu::Texture,
+    pub(crate) palette_lut_view: wgpu::TextureView,
+    pub(crate) post_sampler: wgpu::Sampler,
+    // Sized to the surface, recreated on resize
+    pub(crate) post: PostTextures,
+}
+
+impl Textures {
+    /// The texture holding the current field, `front` as in `State::phm_front`
+    pub(crate) fn phm_front(&self, front: usize) -> &wgpu::Texture {
+        match (front, &self.phm_back) {
+            (1, Some(phm_back)) => phm_back,
+            _ => &self.phm,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PostTextures {
+    #[allow(dead_code)]
+    pub(crate) hdr: wgpu::Texture,
+    pub(crate) hdr_view: wgpu::TextureView,
+    // Half resolution ping-pong pair for the blur
+    #[allow(dead_code)]
+    pub(crate) bloom: [wgpu::Texture; 2],
+    pub(crate) bloom_views: [wgpu::TextureView; 2],
 }
 
 #[repr(C)]
@@ -121,18 +234,32 @@ pub(crate) struct Slime {
     pub(crate) s1_pos: [f32; 2],
     pub(crate) s2_pos: [f32; 2],
     pub(crate) s3_pos: [f32; 2],
+    // 1.0 if the agent kept its heading towards the strongest sensor last step
+    pub(crate) moved_forward: f32,
+    // WGSL aligns the vec4<u32> below to 16 bytes
+    pub(crate) _pad: u32,
+    // Per-agent hybrid Tausworthe state, see shaders/common/rng.wgsl
+    pub(crate) rng: [u32; 4],
 }
 
 // PARAMETERS
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Params {
     pub(crate) view_params: ViewParams,
     pub(crate) slime_params: SlimeParams,
     pub(crate) pheremone_params: PheremoneParams,
+    pub(crate) sim_params: SimParams,
+    pub(crate) jones_params: JonesParams,
+    pub(crate) palette_params: PaletteParams,
+    pub(crate) overlay_params: OverlayParams,
+    pub(crate) post_params: PostParams,
+    pub(crate) stats_params: StatsParams,
+    // Source of the gradient currently in the palette LUT texture, if any
+    pub(crate) palette_lut_path: Option<std::path::PathBuf>,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub(crate) struct SlimeParams {
     pub(crate) max_velocity: f32,
     pub(crate) min_velocity: f32,
@@ -143,8 +270,19 @@ pub(crate) struct SlimeParams {
     pub(crate) sensor_radius: f32,
 }
 
+// Angles in radians, distances in texels
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct JonesParams {
+    pub(crate) sensor_angle: f32,
+    pub(crate) rotation_angle: f32,
+    pub(crate) sensor_offset: f32,
+    pub(crate) sensor_width: f32,
+    pub(crate) step_size: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub(crate) struct PheremoneParams {
     pub(crate) deposition_amount: f32,
     pub(crate) diffusion_factor: f32,
@@ -152,7 +290,7 @@ pub(crate) struct PheremoneParams {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
 pub(crate) struct ViewParams {
     pub(crate) shift_modifier: f32,
     pub(crate) x_shift: f32,
@@ -160,3 +298,800 @@ pub(crate) struct ViewParams {
     pub(crate) zoom: f32,
     pub(crate) time_modifier: f32,
 }
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct SimParams {
+    pub(crate) seed: u32,
+    pub(crate) boundary_mode: u32,
+    pub(crate) agent_model: u32,
+}
+
+// Keep in sync with the BOUNDARY_* constants in shaders/common/boundary.wgsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum BoundaryMode {
+    Wrap = 0,
+    Reflect = 1,
+    Clamp = 2,
+    Absorb = 3,
+}
+
+impl BoundaryMode {
+    pub(crate) const ALL: [BoundaryMode; 4] = [
+        BoundaryMode::Wrap,
+        BoundaryMode::Reflect,
+        BoundaryMode::Clamp,
+        BoundaryMode::Absorb,
+    ];
+
+    pub(crate) fn from_u32(value: u32) -> Self {
+        Self::ALL[value as usize % Self::ALL.len()]
+    }
+
+    pub(crate) fn next(self) -> Self {
+        Self::from_u32(self as u32 + 1)
+    }
+}
+
+// Keep in sync with the AGENT_MODEL_* constants in slime_movement.wgsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum AgentModel {
+    // Steering by velocity, see SlimeParams
+    Velocity = 0,
+    // Jones (2010) heading/sensor model, see JonesParams
+    Jones = 1,
+}
+
+impl AgentModel {
+    pub(crate) const ALL: [AgentModel; 2] = [AgentModel::Velocity, AgentModel::Jones];
+
+    pub(crate) fn from_u32(value: u32) -> Self {
+        Self::ALL[value as usize % Self::ALL.len()]
+    }
+
+    pub(crate) fn next(self) -> Self {
+        Self::from_u32(self as u32 + 1)
+    }
+}
+
+// Sizes are in pixels of the render target
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct OverlayParams {
+    pub(crate) target_size: [f32; 2],
+    // Half width of an agent's dot
+    pub(crate) agent_size: f32,
+    // Sensor circles never shrink below this radius when zoomed out
+    pub(crate) min_sensor_size: f32,
+    // DebugView of the debug channel starting at debug_slot
+    pub(crate) debug_view: u32,
+    pub(crate) debug_slot: u32,
+    // Which of the channel's four values is shown
+    pub(crate) debug_component: u32,
+    // Slots between agents, 0 shows a global channel on every agent
+    pub(crate) debug_stride: u32,
+    // Values mapped to the ends of the colour ramp
+    pub(crate) debug_range: [f32; 2],
+    // Half width of an agent's heatmap splat
+    pub(crate) heatmap_size: f32,
+    pub(crate) _pad: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub(crate) struct PostParams {
+    // Manual exposure, or compensation on top of the auto exposure
+    pub(crate) exposure: f32,
+    // Fraction of the way to the target exposure moved each frame
+    pub(crate) adapt_rate: f32,
+    // Fraction of lit pixels that auto exposure keeps below 1.0
+    pub(crate) percentile: f32,
+    // Brightness that maps to 1.0 under the log curve
+    pub(crate) white_point: f32,
+    pub(crate) auto_exposure: u32,
+    pub(crate) tonemap: u32,
+    pub(crate) bloom_enabled: u32,
+    pub(crate) bloom_threshold: f32,
+    pub(crate) bloom_intensity: f32,
+}
+
+// Written by the exposure compute passes, only initialised from the CPU
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ExposureState {
+    pub(crate) exposure: f32,
+    pub(crate) luminance: f32,
+    pub(crate) histogram: [u32; EXPOSURE_HISTOGRAM_BINS],
+}
+
+// Keep in sync with stats.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct StatsParams {
+    pub(crate) coverage_threshold: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct FieldPartial {
+    pub(crate) mass: f32,
+    pub(crate) max_mass: f32,
+    pub(crate) covered: f32,
+}
+
+/// One reduction of the field and agents, speeds are in texels per step
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct SimStats {
+    pub(crate) total_mass: f32,
+    pub(crate) max_mass: f32,
+    // Fraction of texels above StatsParams::coverage_threshold
+    pub(crate) coverage: f32,
+    pub(crate) mean_speed: f32,
+    pub(crate) speed_variance: f32,
+    // Of the heading histogram, 0.0 all aligned to 1.0 uniform
+    pub(crate) heading_entropy: f32,
+    pub(crate) forward_fraction: f32,
+}
+
+// Keep in sync with the TONEMAP_* constants in shaders/post/common.wgsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum TonemapMode {
+    Linear = 0,
+    Log = 1,
+    Reinhard = 2,
+    Aces = 3,
+}
+
+impl TonemapMode {
+    pub(crate) const ALL: [TonemapMode; 4] = [
+        TonemapMode::Linear,
+        TonemapMode::Log,
+        TonemapMode::Reinhard,
+        TonemapMode::Aces,
+    ];
+
+    pub(crate) fn from_u32(value: u32) -> Self {
+        Self::ALL[value as usize % Self::ALL.len()]
+    }
+
+    pub(crate) fn next(self) -> Self {
+        Self::from_u32(self as u32 + 1)
+    }
+}
+
+// Coefficients are vec4 on the GPU, w is unused.
+// color(t) = a + b * cos(2PI * (c * t + d))
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct PaletteParams {
+    pub(crate) a: [f32; 4],
+    pub(crate) b: [f32; 4],
+    pub(crate) c: [f32; 4],
+    pub(crate) d: [f32; 4],
+    pub(crate) mode: u32,
+    // Pheremone intensity that maps to the top of the palette is 1.0 / scale,
+    // anything brighter scales the top colour up
+    pub(crate) scale: f32,
+    pub(crate) _pad: [u32; 2],
+}
+
+// Keep in sync with the PALETTE_* constants in slime_frag.wgsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum PaletteMode {
+    Red = 0,
+    Cosine = 1,
+    Viridis = 2,
+    Magma = 3,
+    Inferno = 4,
+    Lut = 5,
+}
+
+impl PaletteMode {
+    pub(crate) const ALL: [PaletteMode; 6] = [
+        PaletteMode::Red,
+        PaletteMode::Cosine,
+        PaletteMode::Viridis,
+        PaletteMode::Magma,
+        PaletteMode::Inferno,
+        PaletteMode::Lut,
+    ];
+
+    pub(crate) fn from_u32(value: u32) -> Self {
+        Self::ALL[value as usize % Self::ALL.len()]
+    }
+
+    pub(crate) fn next(self) -> Self {
+        Self::from_u32(self as u32 + 1)
+    }
+}
+
+// How the agent overlay shows a debug channel, keep in sync with the
+// DEBUG_VIEW_* constants in overlay/agents.wgsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DebugView {
+    Off = 0,
+    // Each agent's dot coloured by its value
+    AgentColour = 1,
+    // Wide soft splats adding up where agents crowd
+    Heatmap = 2,
+}
+
+impl DebugView {
+    pub(crate) const ALL: [DebugView; 3] =
+        [DebugView::Off, DebugView::AgentColour, DebugView::Heatmap];
+
+    pub(crate) fn from_u32(value: u32) -> Self {
+        Self::ALL[value as usize % Self::ALL.len()]
+    }
+
+    pub(crate) fn next(self) -> Self {
+        Self::from_u32(self as u32 + 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DebugScope {
+    // A slot per agent
+    PerAgent,
+    // A single slot
+    Global,
+}
+
+// Named values a shader writes to the debug channels buffer, see
+// common/debug.wgsl
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DebugChannel {
+    pub(crate) name: &'static str,
+    pub(crate) scope: DebugScope,
+    // What each component of the vec4 holds
+    pub(crate) labels: [&'static str; 4],
+}
+
+/// Physarum slime mould simulation
+#[derive(Debug, Clone, clap::Parser)]
+#[command(version, about)]
+pub(crate) struct Args {
+    /// How frames are presented to the window
+    #[arg(long, value_enum, default_value_t = PresentModeArg::Vsync)]
+    pub(crate) present_mode: PresentModeArg,
+
+    /// Print the available adapters and exit
+    #[arg(long)]
+    pub(crate) list_adapters: bool,
+
+    /// Only consider adapters on this backend
+    #[arg(long, value_enum)]
+    pub(crate) backend: Option<BackendArg>,
+
+    /// Use the first adapter whose name contains this, ignoring case
+    #[arg(long)]
+    pub(crate) adapter: Option<String>,
+
+    /// Use the software fallback adapter
+    #[arg(long, conflicts_with = "adapter")]
+    pub(crate) fallback_adapter: bool,
+
+    /// Key bindings to use instead of the defaults, action by action. See
+    /// src/state/default_bindings.toml for the action names
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_BINDINGS_PATH)]
+    pub(crate) bindings: std::path::PathBuf,
+
+    /// Start with the parameter panel open, F4 toggles it
+    #[arg(long)]
+    pub(crate) gui: bool,
+
+    /// Seconds a parameter edit takes to ease in, 0 applies edits at once
+    #[arg(long, value_name = "SECONDS", default_value_t = 0.0)]
+    pub(crate) smoothing: f32,
+
+    /// Compute simulation statistics every this many steps, 0 turns them off
+    #[arg(long, value_name = "STEPS", default_value_t = 10)]
+    pub(crate) stats_interval: u32,
+
+    /// Pheremone level a texel needs to count towards coverage
+    #[arg(long, value_name = "LEVEL", default_value_t = 0.1)]
+    pub(crate) stats_threshold: f32,
+
+    /// Write each statistics sample to this CSV file
+    #[arg(long, value_name = "PATH")]
+    pub(crate) stats_csv: Option<std::path::PathBuf>,
+
+    /// Start with the statistics plots open, F10 toggles them
+    #[arg(long)]
+    pub(crate) stats: bool,
+
+    /// Start with the pass profiler on, F9 toggles it
+    #[arg(long)]
+    pub(crate) profile: bool,
+
+    /// Write per-frame pass timings to this CSV file, turns the profiler on
+    #[arg(long, value_name = "PATH")]
+    pub(crate) profile_csv: Option<std::path::PathBuf>,
+
+    /// Use the ping-pong and manual filtering paths even if the adapter
+    /// doesn't need them
+    #[arg(long)]
+    pub(crate) compat: bool,
+
+    /// Agents to simulate, a resumed checkpoint brings its own count
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = DEFAULT_NUM_AGENTS as u32,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub(crate) agents: u32,
+
+    /// Simulation steps recorded back-to-back each frame
+    #[arg(
+        long,
+        value_name = "STEPS",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub(crate) steps_per_frame: u32,
+
+    /// Load this preset at startup, agents are seeded from its seed
+    #[arg(long, value_name = "PATH")]
+    pub(crate) preset: Option<std::path::PathBuf>,
+
+    /// Gradient strip to colour the field with, loaded after the preset or
+    /// checkpoint
+    #[arg(long, value_name = "PATH")]
+    pub(crate) lut: Option<std::path::PathBuf>,
+
+    /// Run the simulation without a window for --steps steps, then exit
+    #[arg(long)]
+    pub(crate) headless: bool,
+
+    /// Steps a headless run takes
+    #[arg(
+        long,
+        value_name = "STEPS",
+        default_value_t = 1000,
+        requires = "headless"
+    )]
+    pub(crate) steps: u64,
+
+    /// Export the pheremone field every this many steps of a headless run, 0
+    /// exports it once at the end
+    #[arg(long, value_name = "STEPS", default_value_t = 0, requires = "headless")]
+    pub(crate) export_interval: u64,
+
+    /// Checkpoint file the save and load checkpoint keys use. Headless runs
+    /// save one here when they finish.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) checkpoint: Option<std::path::PathBuf>,
+
+    /// Carry on from this checkpoint instead of seeding new agents
+    #[arg(long, value_name = "PATH", conflicts_with = "preset")]
+    pub(crate) resume: Option<std::path::PathBuf>,
+
+    /// Directory exported fields are written to
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_EXPORT_DIR)]
+    pub(crate) export_dir: std::path::PathBuf,
+
+    /// Formats the field is exported as, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "npy")]
+    pub(crate) export_format: Vec<FieldFormat>,
+
+    /// Field levels mapped to black and white in PNG exports, the field's own
+    /// minimum and maximum if not given
+    #[arg(long, value_name = "MIN,MAX", value_parser = parse_range)]
+    pub(crate) png_range: Option<[f32; 2]>,
+
+    /// Extract the transport network at the end of a headless run, written
+    /// as GraphML and GeoJSON to the export directory
+    #[arg(long, requires = "headless")]
+    pub(crate) graph: bool,
+
+    /// Time --steps steps submitting each stage on its own, then in one
+    /// encoder a frame at 1 and at --steps-per-frame steps a frame, and exit
+    #[arg(long, requires = "headless")]
+    pub(crate) benchmark: bool,
+
+    /// Pheremone level above which a texel is part of the network, picked
+    /// with Otsu's method if not given
+    #[arg(long, value_name = "LEVEL")]
+    pub(crate) graph_threshold: Option<f32>,
+
+    /// CSV of named food sources with a header row. Columns are name, then x
+    /// and y in texels or lat and lon in degrees, and an optional weight.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) poi: Option<std::path::PathBuf>,
+
+    /// Lat/lon box mapped onto the field, fitted around the points if not
+    /// given
+    #[arg(long, value_name = "SOUTH,WEST,NORTH,EAST", value_parser = parse_bounds)]
+    pub(crate) poi_bounds: Option<GeoBounds>,
+
+    /// Radius of each food source in texels
+    #[arg(long, value_name = "TEXELS", default_value_t = 3.0)]
+    pub(crate) poi_radius: f32,
+
+    /// Attractant a food source of weight 1 adds to each of its texels every
+    /// step
+    #[arg(long, value_name = "AMOUNT", default_value_t = 0.1)]
+    pub(crate) poi_strength: f32,
+
+    /// Image stretched over the field whose dark pixels are water. Agents
+    /// turn back at water and the field stays empty there.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) land_mask: Option<std::path::PathBuf>,
+}
+
+fn parse_range(text: &str) -> Result<[f32; 2], String> {
+    let (low, high) = text
+        .split_once(',')
+        .ok_or_else(|| format!("expected MIN,MAX, got {:?}", text))?;
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<f32>()
+            .map_err(|e| format!("{:?}: {}", value, e))
+    };
+    let range = [parse(low)?, parse(high)?];
+
+    if range[0] >= range[1] {
+        return Err(format!("MIN must be below MAX, got {:?}", text));
+    }
+    Ok(range)
+}
+
+fn parse_bounds(text: &str) -> Result<GeoBounds, String> {
+    let values = text
+        .split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| format!("{:?}: {}", value, e))
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+
+    let [south, west, north, east] = values[..] else {
+        return Err(format!("expected SOUTH,WEST,NORTH,EAST, got {:?}", text));
+    };
+    if south >= north || west >= east {
+        return Err(format!(
+            "SOUTH and WEST must be below NORTH and EAST, got {:?}",
+            text
+        ));
+    }
+    Ok(GeoBounds {
+        south,
+        west,
+        north,
+        east,
+    })
+}
+
+// Everything but the bulk data of a checkpoint, stored as TOML at the start of
+// the file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CheckpointHeader {
+    pub(crate) step: u64,
+    // Seconds since start, what the time uniform was
+    pub(crate) time: f32,
+    pub(crate) agent_count: u32,
+    // Bytes per agent, catches a Slime layout change without a version bump
+    pub(crate) agent_size: u32,
+    pub(crate) field_width: u32,
+    pub(crate) field_height: u32,
+    // Registered parameters, seed, modes, palette and modulations
+    pub(crate) preset: Preset,
+    // What the preset leaves out, the pan position and post-processing modes
+    pub(crate) view_params: ViewParams,
+    pub(crate) post_params: PostParams,
+}
+
+// The whole simulation, enough to carry on exactly where it was saved. The
+// agents carry their own RNG state.
+pub(crate) struct Checkpoint {
+    pub(crate) header: CheckpointHeader,
+    pub(crate) agents: Vec<Slime>,
+    // Every channel of every texel, row by row from the top
+    pub(crate) field: Vec<[f32; 4]>,
+}
+
+// Exported files hold only the trail level, the red channel of the field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum FieldFormat {
+    /// NumPy float32 array shaped (height, width)
+    Npy,
+    /// OpenEXR with a single float32 Y channel
+    Exr,
+    /// 16-bit grayscale PNG normalised to --png-range
+    Png,
+}
+
+impl FieldFormat {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            FieldFormat::Npy => "npy",
+            FieldFormat::Exr => "exr",
+            FieldFormat::Png => "png",
+        }
+    }
+}
+
+// What the export key and headless runs write, from the command line
+#[derive(Debug, Clone)]
+pub(crate) struct ExportSettings {
+    pub(crate) dir: std::path::PathBuf,
+    pub(crate) formats: Vec<FieldFormat>,
+    pub(crate) png_range: Option<[f32; 2]>,
+    pub(crate) graph_threshold: Option<f32>,
+}
+
+// A junction or end of the extracted network. Positions are in texels from
+// the top left of the field.
+#[derive(Debug, Clone)]
+pub(crate) struct GraphNode {
+    pub(crate) position: [f32; 2],
+    // Food sources the node is nearest to, see label_graph
+    pub(crate) name: Option<String>,
+    // Edge ends at the node, a loop counts twice. 1 is an end, 3 or more a
+    // junction and 2 only happens on a loop without junctions.
+    pub(crate) degree: usize,
+}
+
+// A skeleton path between two nodes
+#[derive(Debug, Clone)]
+pub(crate) struct GraphEdge {
+    pub(crate) source: usize,
+    pub(crate) target: usize,
+    // Along the path, in texels
+    pub(crate) length: f32,
+    // Pheremone level averaged over the path
+    pub(crate) mean_intensity: f32,
+    // Texels from source to target
+    pub(crate) path: Vec<[u32; 2]>,
+}
+
+// Named nodes joined by the network without passing another named node
+#[derive(Debug, Clone)]
+pub(crate) struct NamedLink {
+    pub(crate) source: String,
+    pub(crate) target: String,
+    // Shortest way along the network, in texels
+    pub(crate) length: f32,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Graph {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) threshold: f32,
+    pub(crate) nodes: Vec<GraphNode>,
+    pub(crate) edges: Vec<GraphEdge>,
+}
+
+// Degrees, mapped onto the field with north at the top
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GeoBounds {
+    pub(crate) south: f64,
+    pub(crate) west: f64,
+    pub(crate) north: f64,
+    pub(crate) east: f64,
+}
+
+// A named food source. Positions are in texels from the top left of the field.
+#[derive(Debug, Clone)]
+pub(crate) struct PointOfInterest {
+    pub(crate) name: String,
+    pub(crate) position: [f32; 2],
+    pub(crate) weight: f32,
+}
+
+// Food and land at one texel, read by the trail update and the agents
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct SourceTexel {
+    pub(crate) attractant: f32,
+    // 1 on land, 0 on water where the field is cleared
+    pub(crate) land: f32,
+}
+
+// Food sources and land mask from the command line
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FoodSources {
+    pub(crate) points: Vec<PointOfInterest>,
+    pub(crate) radius: f32,
+    pub(crate) strength: f32,
+    // One per texel, everything is land without a mask
+    pub(crate) land: Option<Vec<bool>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum PresentModeArg {
+    Vsync,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentModeArg {
+    pub(crate) fn present_mode(self) -> wgpu::PresentMode {
+        match self {
+            PresentModeArg::Vsync => wgpu::PresentMode::AutoVsync,
+            PresentModeArg::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentModeArg::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum BackendArg {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl BackendArg {
+    pub(crate) fn backends(self) -> wgpu::Backends {
+        match self {
+            BackendArg::Vulkan => wgpu::Backends::VULKAN,
+            BackendArg::Metal => wgpu::Backends::METAL,
+            BackendArg::Dx12 => wgpu::Backends::DX12,
+            BackendArg::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+// Everything needed to reproduce a look, saved to and loaded from a TOML file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Preset {
+    pub(crate) seed: u32,
+    pub(crate) boundary_mode: BoundaryMode,
+    pub(crate) agent_model: AgentModel,
+    // Registered parameters, by group then field
+    #[serde(default)]
+    pub(crate) params: BTreeMap<String, BTreeMap<String, f32>>,
+    // Written before the parameter registry, only read
+    #[serde(default, skip_serializing)]
+    pub(crate) view_params: Option<ViewParams>,
+    #[serde(default, skip_serializing)]
+    pub(crate) slime_params: Option<SlimeParams>,
+    #[serde(default, skip_serializing)]
+    pub(crate) jones_params: Option<JonesParams>,
+    #[serde(default, skip_serializing)]
+    pub(crate) pheremone_params: Option<PheremoneParams>,
+    pub(crate) palette: PresetPalette,
+    // Saved as [[modulation]] tables
+    #[serde(default, rename = "modulation")]
+    pub(crate) modulations: Vec<Modulation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PresetPalette {
+    pub(crate) mode: PaletteMode,
+    pub(crate) scale: f32,
+    pub(crate) a: [f32; 3],
+    pub(crate) b: [f32; 3],
+    pub(crate) c: [f32; 3],
+    pub(crate) d: [f32; 3],
+    pub(crate) lut_path: Option<std::path::PathBuf>,
+}
+
+// Which params struct, and so which uniform buffer, a parameter lives in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ParamGroup {
+    Slime,
+    Jones,
+    Pheremone,
+    View,
+    Post,
+}
+
+impl ParamGroup {
+    // Prefix of the parameter names, and the preset table they're saved in
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ParamGroup::Slime => "slime",
+            ParamGroup::Jones => "jones",
+            ParamGroup::Pheremone => "pheremone",
+            ParamGroup::View => "view",
+            ParamGroup::Post => "post",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ParamScale {
+    // Steps add `step`
+    Linear,
+    // Steps multiply by `1.0 + step`, for values spanning orders of magnitude
+    Log,
+}
+
+/// Metadata for one editable `f32` field of `Params`, see `PARAMS` in
+/// param_functions.rs
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ParamInfo {
+    // Name of the field in its params struct, and in presets
+    pub(crate) field: &'static str,
+    pub(crate) label: &'static str,
+    pub(crate) description: &'static str,
+    pub(crate) group: ParamGroup,
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+    pub(crate) step: f32,
+    pub(crate) scale: ParamScale,
+    pub(crate) get: fn(&Params) -> f32,
+    pub(crate) get_mut: fn(&mut Params) -> &mut f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LfoShape {
+    Sine,
+    Triangle,
+    // Smoothly interpolated random values, one per period
+    Noise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum KeyframeInterpolation {
+    Step,
+    #[default]
+    Linear,
+    Smooth,
+}
+
+/// What drives a modulated parameter. Times are in simulation steps, so a
+/// render plays back the same however fast it runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum ModulationSource {
+    /// Swings `amplitude` either side of `center`, or of the preset's value
+    /// for the parameter, once every `period` steps. `phase` is in periods.
+    Lfo {
+        shape: LfoShape,
+        period: f32,
+        amplitude: f32,
+        #[serde(default)]
+        phase: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        center: Option<f32>,
+    },
+    /// Passes through `[step, value]` points, holding the first and last
+    /// values outside them unless `repeat` plays them back to back
+    Keyframes {
+        keys: Vec<(f32, f32)>,
+        #[serde(default)]
+        interpolation: KeyframeInterpolation,
+        #[serde(default)]
+        repeat: bool,
+    },
+}
+
+/// One entry of a preset's `[[modulation]]` list, e.g.
+///
+/// ```toml
+/// [[modulation]]
+/// param = "slime.sensor_offset"
+/// kind = "lfo"
+/// shape = "sine"
+/// period = 5000.0
+/// amplitude = 0.3
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Modulation {
+    // Registry name, "<group>.<field>"
+    pub(crate) param: String,
+    #[serde(flatten)]
+    pub(crate) source: ModulationSource,
+}
+
+/// A `Modulation` resolved against the registry
+#[derive(Debug, Clone)]
+pub(crate) struct ActiveModulation {
+    pub(crate) modulation: Modulation,
+    pub(crate) param: &'static ParamInfo,
+    // The parameter's value when the modulation was loaded, the LFO centre
+    // if none is given and what's saved back to the preset
+    pub(crate) base: f32,
+}
@@ -1,8 +1,17 @@
 use crate::{
-    state::app_state::State, PheremoneParams, Slime, SlimeParams, ViewParams, DISPATCH_SIZE_X,
-    DISPATCH_SIZE_Y, NUM_AGENTS,
+    state::{app_state::State, profiler_state::ProfiledPass},
+    ParamGroup, PheremoneParams, SimStats, SlimeParams, SourceTexel, ViewParams, DISPATCH_SIZE_X,
+    DISPATCH_SIZE_Y, PALETTE_LUT_SIZE, STATS_TILE_SIZE, TEXTURE_BUF_SIZE,
 };
 
+pub(crate) fn update_time_uniform_buffer(state: &State) {
+    state.queue.write_buffer(
+        &state.buffers.time_uniform_buf,
+        0,
+        bytemuck::cast_slice(&[state.get_time()]),
+    );
+}
+
 pub(crate) fn update_view_params_buffer(state: &State) {
     let new_view_params = ViewParams {
         shift_modifier: state.params.view_params.shift_modifier,
@@ -51,81 +60,217 @@ pub(crate) fn update_pheremone_params_buffer(state: &State) {
     );
 }
 
-pub(crate) fn update_cpu_read_buffers(state: &State) {
-    let mut encoder = state
-        .device
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("update_cpu_read_buffers encoder"),
-        });
-
-    encoder.copy_buffer_to_buffer(
-        &state.buffers.slime_pos_buf,
+pub(crate) fn update_sim_params_buffer(state: &State) {
+    state.queue.write_buffer(
+        &state.buffers.sim_params_buf,
         0,
-        &state.buffers.cpu_read_slime_pos_buf,
+        bytemuck::cast_slice(&[state.params.sim_params]),
+    );
+}
+
+pub(crate) fn update_jones_params_buffer(state: &State) {
+    state.queue.write_buffer(
+        &state.buffers.jones_params_buf,
         0,
-        (std::mem::size_of::<[Slime; NUM_AGENTS]>()) as wgpu::BufferAddress,
+        bytemuck::cast_slice(&[state.params.jones_params]),
     );
+}
 
-    encoder.copy_buffer_to_buffer(
-        &state.buffers.generic_debug_buf,
+pub(crate) fn update_palette_params_buffer(state: &State) {
+    state.queue.write_buffer(
+        &state.buffers.palette_params_buf,
         0,
-        &state.buffers.cpu_read_generic_debug_buf,
+        bytemuck::cast_slice(&[state.params.palette_params]),
+    );
+}
+
+pub(crate) fn update_post_params_buffer(state: &State) {
+    state.queue.write_buffer(
+        &state.buffers.post_params_buf,
         0,
-        (std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+        bytemuck::cast_slice(&[state.params.post_params]),
     );
+}
 
-    encoder.copy_buffer_to_buffer(
-        &state.buffers.generic_debug_array_buf,
+pub(crate) fn update_stats_params_buffer(state: &State) {
+    state.queue.write_buffer(
+        &state.buffers.stats_params_buf,
         0,
-        &state.buffers.cpu_read_generic_debug_array_buf,
+        bytemuck::cast_slice(&[state.params.stats_params]),
+    );
+}
+
+pub(crate) fn update_param_group_buffer(state: &State, group: ParamGroup) {
+    match group {
+        ParamGroup::Slime => update_slime_params_buffer(state),
+        ParamGroup::Jones => update_jones_params_buffer(state),
+        ParamGroup::Pheremone => update_pheremone_params_buffer(state),
+        ParamGroup::View => update_view_params_buffer(state),
+        ParamGroup::Post => update_post_params_buffer(state),
+    }
+}
+
+pub(crate) fn update_palette_lut_texture(state: &State, texels: &[u8]) {
+    state.queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &state.textures.palette_lut,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        texels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(PALETTE_LUT_SIZE * 4),
+            rows_per_image: Some(1),
+        },
+        state.textures.palette_lut.size(),
+    );
+}
+
+pub(crate) fn update_overlay_params_buffer(state: &State) {
+    state.queue.write_buffer(
+        &state.buffers.overlay_params_buf,
         0,
-        (std::mem::size_of::<[[f32; 4]; NUM_AGENTS]>()) as wgpu::BufferAddress,
+        bytemuck::cast_slice(&[state.params.overlay_params]),
     );
+}
 
-    state.queue.submit(Some(encoder.finish()));
+pub(crate) fn update_sources_buffer(state: &State, texels: &[SourceTexel]) {
+    state
+        .queue
+        .write_buffer(&state.buffers.sources_buf, 0, bytemuck::cast_slice(texels));
 }
 
-pub(crate) fn update_agent_position(state: &State) {
-    let mut encoder = state
-        .device
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("update_slime_position encoder"),
-        });
+pub(crate) fn clear_pheremone_texture(state: &State) {
+    let zeros = vec![0u8; TEXTURE_BUF_SIZE];
 
-    {
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Slime Moves Compute Pass"),
-            timestamp_writes: None,
-        });
+    for texture in std::iter::once(&state.textures.phm).chain(&state.textures.phm_back) {
+        state.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &zeros,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(state.textures.phm_extent.width * 4 * 4),
+                rows_per_image: Some(state.textures.phm_extent.height),
+            },
+            state.textures.phm_extent,
+        );
+    }
 
+    if let Some(phm_deposit_buf) = &state.buffers.phm_deposit_buf {
+        state.queue.write_buffer(
+            phm_deposit_buf,
+            0,
+            &vec![0u8; phm_deposit_buf.size() as usize],
+        );
+    }
+}
+
+pub(crate) fn update_agent_position(state: &State, encoder: &mut wgpu::CommandEncoder) {
+    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Slime Moves Compute Pass"),
+        timestamp_writes: state.profiler.compute_timestamps(ProfiledPass::Movement),
+    });
+
+    compute_pass.set_pipeline(&state.pipelines.update_slime);
+    compute_pass.set_bind_group(0, &state.bind_groups.compute_bg, &[]);
+    compute_pass.set_bind_group(1, &state.bind_groups.uniform_bg, &[]);
+    compute_pass.set_bind_group(2, &state.bind_groups.phm_bgs[state.phm_front], &[]);
+    compute_pass.dispatch_workgroups(state.capabilities.agent_workgroups(), 1, 1);
+}
+
+pub(crate) fn update_pheremone_trails(state: &State, encoder: &mut wgpu::CommandEncoder) {
+    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Pheremone Trails Compute Pass"),
+        timestamp_writes: state.profiler.compute_timestamps(ProfiledPass::Diffusion),
+    });
+    compute_pass.set_pipeline(&state.pipelines.update_phm);
+    compute_pass.set_bind_group(0, &state.bind_groups.compute_bg, &[]);
+    compute_pass.set_bind_group(1, &state.bind_groups.uniform_bg, &[]);
+    compute_pass.set_bind_group(2, &state.bind_groups.phm_bgs[state.phm_front], &[]);
+    compute_pass.dispatch_workgroups(DISPATCH_SIZE_X, DISPATCH_SIZE_Y, 1); // Adjust workgroup size as needed
+}
+
+/// Records `steps` steps of movement and diffusion back-to-back in one
+/// compute pass. Bind groups are only set again when the field's front
+/// changes. Returns the front after the last step.
+pub(crate) fn update_sim_steps(
+    state: &State,
+    encoder: &mut wgpu::CommandEncoder,
+    steps: u64,
+) -> usize {
+    let mut front = state.phm_front;
+
+    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Simulation Steps Compute Pass"),
+        timestamp_writes: None,
+    });
+    compute_pass.set_bind_group(0, &state.bind_groups.compute_bg, &[]);
+    compute_pass.set_bind_group(1, &state.bind_groups.uniform_bg, &[]);
+    compute_pass.set_bind_group(2, &state.bind_groups.phm_bgs[front], &[]);
+
+    let agent_workgroups = state.capabilities.agent_workgroups();
+    for _ in 0..steps {
         compute_pass.set_pipeline(&state.pipelines.update_slime);
-        compute_pass.set_bind_group(0, &state.bind_groups.compute_bg, &[]);
-        compute_pass.set_bind_group(1, &state.bind_groups.uniform_bg, &[]);
-        compute_pass.set_bind_group(2, &state.bind_groups.phm_bg, &[]);
-        compute_pass.dispatch_workgroups(DISPATCH_SIZE_X, DISPATCH_SIZE_Y, 1); // Adjust workgroup size as needed
+        compute_pass.dispatch_workgroups(agent_workgroups, 1, 1);
+        compute_pass.set_pipeline(&state.pipelines.update_phm);
+        compute_pass.dispatch_workgroups(DISPATCH_SIZE_X, DISPATCH_SIZE_Y, 1);
+
+        if !state.capabilities.read_write_storage {
+            front ^= 1;
+            compute_pass.set_bind_group(2, &state.bind_groups.phm_bgs[front], &[]);
+        }
     }
 
-    state.queue.submit(Some(encoder.finish()));
+    front
 }
 
-pub(crate) fn update_pheremone_trails(state: &State) {
-    let mut encoder = state
-        .device
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("update_pheremone_trails encoder"),
-        });
+/// Reduces the field and agents into `SimStats` every `StatsState::interval`
+/// steps and queues the result for readback, mapped once the frame is
+/// submitted
+pub(crate) fn update_sim_stats(state: &mut State, encoder: &mut wgpu::CommandEncoder) {
+    if !state.stats.due(state.step) {
+        return;
+    }
 
     {
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Pheremone Trails Compute Pass"),
-            timestamp_writes: None,
+            label: Some("Simulation Stats Compute Pass"),
+            timestamp_writes: state.profiler.compute_timestamps(ProfiledPass::Stats),
         });
-        compute_pass.set_pipeline(&state.pipelines.update_phm);
-        compute_pass.set_bind_group(0, &state.bind_groups.compute_bg, &[]);
+        compute_pass.set_bind_group(0, &state.bind_groups.stats_bg, &[]);
         compute_pass.set_bind_group(1, &state.bind_groups.uniform_bg, &[]);
-        compute_pass.set_bind_group(2, &state.bind_groups.phm_bg, &[]);
-        compute_pass.dispatch_workgroups(DISPATCH_SIZE_X, DISPATCH_SIZE_Y, 1); // Adjust workgroup size as needed
+        compute_pass.set_bind_group(2, &state.bind_groups.phm_bgs[state.phm_front], &[]);
+
+        let extent = state.textures.phm_extent;
+        compute_pass.set_pipeline(&state.pipelines.stats_field);
+        compute_pass.dispatch_workgroups(
+            extent.width.div_ceil(STATS_TILE_SIZE),
+            extent.height.div_ceil(STATS_TILE_SIZE),
+            1,
+        );
+        compute_pass.set_pipeline(&state.pipelines.stats_finish);
+        compute_pass.dispatch_workgroups(1, 1, 1);
     }
 
-    state.queue.submit(Some(encoder.finish()));
+    // The sample is dropped if every staging buffer is busy
+    let step = state.step;
+    state.readback.request(
+        &state.device,
+        encoder,
+        &state.buffers.stats_buf,
+        std::mem::size_of::<SimStats>() as wgpu::BufferAddress,
+        "stats",
+        Box::new(move |state, data| {
+            let stats = bytemuck::pod_read_unaligned(&data[..std::mem::size_of::<SimStats>()]);
+            state.stats.push(step, stats);
+        }),
+    );
+    state.end_stage(encoder, ProfiledPass::Stats);
 }